@@ -27,6 +27,9 @@ if [ "$ANOTHER_FILE" != "" ]; then
 	cp $ANOTHER_FILE $MOUNT_POINT/
 fi
 
+# home directory (the shell keeps its command history here)
+mkdir -p $MOUNT_POINT/home
+
 if [ "$APPS_DIR" != "" ]; then
 	mkdir $MOUNT_POINT/$APPS_DIR
 fi
@@ -41,5 +44,13 @@ if [ "${RESOURCE_DIR}" != "" ]; then
 	cp ${MIKANOS_DIR}/${RESOURCE_DIR}/* "${MOUNT_POINT}"
 fi
 
+# ext2 volume mounted at /mnt (the loader reads it from \ext2_disk)
+# the files under $EXT2_DIR are copied into it if the variable is set
+EXT2_IMG=$(mktemp)
+rm -f $EXT2_IMG
+mkfs.ext2 -q -b 1024 ${EXT2_DIR:+-d "$EXT2_DIR"} $EXT2_IMG 4M
+cp $EXT2_IMG $MOUNT_POINT/ext2_disk
+rm -f $EXT2_IMG
+
 sleep 0.5
 sudo umount $MOUNT_POINT
@@ -75,6 +75,8 @@ pub fn main(args: TokenStream, input: TokenStream) -> TokenStream {
         #[no_mangle]
         extern "sysv64" fn _start(argc: i32, argv :*const *const ::core::ffi::c_char) -> ! {
             let args = unsafe { ::app_lib::args::Args::new(argc as usize, argv) };
+            // 環境変数は argv の後ろのヌルポインタに続いて並んでいる
+            unsafe { ::app_lib::env::init(argv.add(argc as usize + 1)) };
             ::app_lib::exit(#fn_ident(args))
         }
 
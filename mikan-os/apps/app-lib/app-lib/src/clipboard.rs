@@ -0,0 +1,91 @@
+//! システム全体で共有するクリップボード。
+//!
+//! 内容は `text/plain` のような MIME タイプ風の種類と組にして置く。
+//! ターミナルで選択した文字列は [TEXT_PLAIN] として置かれる。
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+
+use crate::{buf::CStrBuf, errno::ErrNo, syscall};
+
+type Result<T> = core::result::Result<T, ErrNo>;
+
+/// テキストを表す種類。
+pub const TEXT_PLAIN: &str = "text/plain";
+/// 種類を表す文字列の最大の長さ。
+pub const MAX_TYPE_LEN: usize = 64;
+
+/// クリップボードの内容を、種類 `ty` のデータ `data` で置き換える。
+pub fn set(ty: &str, data: &[u8]) -> Result<()> {
+    use core::fmt::Write as _;
+
+    let mut buf = [0; MAX_TYPE_LEN + 1];
+    let mut buf = CStrBuf::new_unchecked(&mut buf);
+    write!(buf, "{}", ty).map_err(|_| ErrNo::EINVAL)?;
+    let res = unsafe {
+        syscall::__set_clipboard(
+            buf.to_cstr().as_ptr() as _,
+            data.as_ptr() as _,
+            data.len() as _,
+        )
+    };
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// クリップボードにテキストを置く。
+pub fn set_text(text: &str) -> Result<()> {
+    set(TEXT_PLAIN, text.as_bytes())
+}
+
+/// クリップボードの内容の種類を `ty` に、データを `buf` に入るだけ書き込む。
+///
+/// 種類とデータ全体の長さを返す。データの長さが `buf` より長い場合は、先頭だけを書き込む。
+/// クリップボードが空の場合は [ErrNo::ENODATA] を返す。
+pub fn get<'a>(ty: &'a mut [u8; MAX_TYPE_LEN + 1], buf: &mut [u8]) -> Result<(&'a str, usize)> {
+    let res = unsafe {
+        syscall::__get_clipboard(
+            ty.as_mut_ptr() as _,
+            ty.len() as _,
+            buf.as_mut_ptr() as _,
+            buf.len() as _,
+        )
+    };
+
+    if res.error != 0 {
+        return Err(res.error.into());
+    }
+    let len = ty.iter().position(|&b| b == 0).unwrap_or(ty.len());
+    let ty = core::str::from_utf8(&ty[..len]).map_err(|_| ErrNo::EINVAL)?;
+    Ok((ty, res.value as _))
+}
+
+/// クリップボードの内容を、種類とデータの組で返す。
+#[cfg(feature = "alloc")]
+pub fn get_vec() -> Result<(String, Vec<u8>)> {
+    let mut ty = [0; MAX_TYPE_LEN + 1];
+    let mut data = Vec::new();
+    loop {
+        let (t, len) = get(&mut ty, &mut data)?;
+        // 読み込む間に内容が変わって長くなった場合は読み直す
+        if len <= data.len() {
+            data.truncate(len);
+            return Ok((t.into(), data));
+        }
+        data.resize(len, 0);
+    }
+}
+
+/// クリップボードのテキストを返す。テキストでない内容が置かれている場合は [ErrNo::EINVAL] を返す。
+#[cfg(feature = "alloc")]
+pub fn get_text() -> Result<String> {
+    let (ty, data) = get_vec()?;
+    if !ty.starts_with("text/") {
+        return Err(ErrNo::EINVAL);
+    }
+    String::from_utf8(data).map_err(|_| ErrNo::EILSEQ)
+}
@@ -0,0 +1,42 @@
+//! 環境変数。
+//!
+//! 環境変数はアプリの起動時にカーネルから渡され、起動後に変更されることはない。
+
+use core::{
+    ffi::{c_char, CStr},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// ヌルポインタで終端された、`NAME=value` の形の文字列へのポインタの配列。
+static ENVP: AtomicPtr<*const c_char> = AtomicPtr::new(ptr::null_mut());
+
+/// 環境変数の配列を設定する。エントリーポイントから呼ばれる。
+///
+/// # Safety
+///
+/// `envp` はヌル終端された文字列へのポインタの、ヌルポインタで終端された配列へのポインタでなければならない。
+#[doc(hidden)]
+pub unsafe fn init(envp: *const *const c_char) {
+    ENVP.store(envp as *mut _, Ordering::Relaxed);
+}
+
+/// 環境変数 `name` の値を返す。
+/// 設定されていない場合は `None` を返す。
+pub fn var(name: &str) -> Option<&'static str> {
+    vars().find_map(|(n, value)| (n == name).then_some(value))
+}
+
+/// 全ての環境変数を、名前と値の組として返す。
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let envp = ENVP.load(Ordering::Relaxed) as *const *const c_char;
+    (0..)
+        .map_while(move |i| {
+            if envp.is_null() {
+                return None;
+            }
+            let s = unsafe { *envp.add(i) };
+            (!s.is_null()).then_some(s)
+        })
+        .filter_map(|s| unsafe { CStr::from_ptr(s) }.to_str().ok()?.split_once('='))
+}
@@ -30,6 +30,19 @@ pub enum AppEvent {
         ascii: u8,
         press: bool,
     },
+    /// マウスホイールの回転。`delta` は奥に回すと正。
+    MouseWheel {
+        x: i32,
+        y: i32,
+        delta: i32,
+    },
+    /// ウィンドウの大きさが変えられた。`width`, `height` は
+    /// [open_window](crate::graphics::open_window) と同じく、ウィンドウ全体の大きさ。
+    WindowResize {
+        layer_id: u32,
+        width: i32,
+        height: i32,
+    },
 }
 
 impl AppEvent {
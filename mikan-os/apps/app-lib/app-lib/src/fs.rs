@@ -1,5 +1,5 @@
 use core::{
-    fmt::Display,
+    fmt::{Debug, Display},
     ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not},
     slice,
 };
@@ -15,19 +15,106 @@ use crate::{
 type Result<T> = core::result::Result<T, ErrNo>;
 
 pub fn open(path: impl Display, flags: FileFlags) -> Result<File> {
+    let res = with_cpath(path, |path| unsafe {
+        syscall::__open_file(path as _, flags.0 as _)
+    })?;
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(File(res.value as _))
+    }
+}
+
+/// カレントディレクトリを `path` に変更する。
+pub fn chdir(path: impl Display) -> Result<()> {
+    let res = with_cpath(path, |path| unsafe { syscall::__change_dir(path as _) })?;
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// 空のディレクトリ `path` を作る。
+pub fn mkdir(path: impl Display) -> Result<()> {
+    let res = with_cpath(path, |path| unsafe { syscall::__make_dir(path as _) })?;
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// FIFO `path` を作る。FIFO は `/tmp` 以下にのみ作れる。
+///
+/// 作った FIFO は読み込み専用もしくは書き込み専用で開く。
+/// 開く際は、相手側が開かれるまでブロックする。
+pub fn mkfifo(path: impl Display) -> Result<()> {
+    let res = with_cpath(path, |path| unsafe { syscall::__make_fifo(path as _) })?;
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// ファイルもしくは空のディレクトリ `path` を削除する。
+pub fn remove(path: impl Display) -> Result<()> {
+    let res = with_cpath(path, |path| unsafe { syscall::__remove_file(path as _) })?;
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// パイプを作り、読み込み側と書き込み側の組を返す。
+///
+/// 書き込み側が全て閉じられると、読み込み側は EOF を受け取る。
+pub fn pipe() -> Result<(File, File)> {
+    let mut fds = [0i32; 2];
+    let res = unsafe { syscall::__create_pipe(fds.as_mut_ptr() as _) };
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok((File(fds[0]), File(fds[1])))
+    }
+}
+
+/// カレントディレクトリの絶対パスを `buf` に書き込み、その文字列を返す。
+///
+/// `buf` が短い場合は [ErrNo::ERANGE] を返す。
+pub fn getcwd(buf: &mut [u8]) -> Result<&str> {
+    let res = unsafe { syscall::__get_cwd(buf.as_mut_ptr() as _, buf.len() as _) };
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        core::str::from_utf8(&buf[..res.value as usize]).map_err(|_| ErrNo::EINVAL)
+    }
+}
+
+/// `path` をヌル文字終端した文字列に変換し、その先頭へのポインタを `f` に渡す。
+fn with_cpath<R>(path: impl Display, f: impl FnOnce(*const u8) -> R) -> Result<R> {
     #[cfg(not(feature = "alloc"))]
-    let res = {
+    {
         use crate::buf::CStrBuf;
         use core::fmt::Write as _;
 
         let mut buf = [0; 1024];
         let mut buf = CStrBuf::new_unchecked(&mut buf);
         write!(buf, "{}", path).unwrap();
-        unsafe { syscall::__open_file(buf.to_cstr().as_ptr() as _, flags.0 as _) }
-    };
+        Ok(f(buf.to_cstr().as_ptr() as _))
+    }
 
     #[cfg(feature = "alloc")]
-    let res = {
+    {
         use alloc::ffi::CString;
         use alloc::format;
 
@@ -36,13 +123,7 @@ pub fn open(path: impl Display, flags: FileFlags) -> Result<File> {
             Ok(s) => s,
             Err(_) => return Err(ErrNo::EINVAL),
         };
-        unsafe { syscall::__open_file(path.as_ptr() as _, flags.0 as _) }
-    };
-
-    if res.error != 0 {
-        Err(res.error.into())
-    } else {
-        Ok(File(res.value as _))
+        Ok(f(path.as_ptr() as _))
     }
 }
 
@@ -84,6 +165,22 @@ pub trait Write {
 pub struct File(pub(crate) i32);
 
 impl File {
+    /// ディレクトリとして開いているファイルから、エントリを順に返すイテレータを作る。
+    pub fn read_dir(self) -> ReadDir {
+        ReadDir { dir: self }
+    }
+
+    /// ファイルを閉じる。
+    pub fn close(self) -> Result<()> {
+        let res = unsafe { syscall::__close_file(self.0 as _) };
+
+        if res.error != 0 {
+            Err(res.error.into())
+        } else {
+            Ok(())
+        }
+    }
+
     /// 現在開いているファイルをメモリにマップし、そのメモリスライスへの参照を返す。
     pub fn memmap(&mut self) -> Result<&mut [u8]> {
         let mut file_size = 0;
@@ -124,6 +221,113 @@ impl Write for File {
     }
 }
 
+/// ディレクトリエントリの名前の最大長（ヌル文字を含む）。
+const DIR_ENTRY_NAME_MAX: usize = 256;
+
+/// ディレクトリ属性を表すビット。
+const ATTR_DIRECTORY: u8 = 0x10;
+/// FIFO 属性を表すビット。
+const ATTR_FIFO: u8 = 0x40;
+
+/// ディレクトリに含まれる1つのエントリを表す。
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DirEntry {
+    name: [u8; DIR_ENTRY_NAME_MAX],
+    attr: u8,
+    size: u64,
+    first_cluster: u64,
+    mtime: u64,
+}
+
+impl DirEntry {
+    const fn empty() -> Self {
+        Self {
+            name: [0; DIR_ENTRY_NAME_MAX],
+            attr: 0,
+            size: 0,
+            first_cluster: 0,
+            mtime: 0,
+        }
+    }
+
+    /// ファイル名を返す。
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+
+    /// ファイル属性を返す。
+    pub fn attr(&self) -> u8 {
+        self.attr
+    }
+
+    /// ディレクトリかどうかを返す。
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// FIFO かどうかを返す。
+    pub fn is_fifo(&self) -> bool {
+        self.attr & ATTR_FIFO != 0
+    }
+
+    /// ファイルサイズ（バイト）を返す。
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// ファイルの先頭クラスタ番号を返す。
+    pub fn first_cluster(&self) -> u64 {
+        self.first_cluster
+    }
+
+    /// 最終更新日時を UNIX 時間（秒）で返す。不明な場合は `0` を返す。
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+}
+
+impl Debug for DirEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DirEntry")
+            .field("name", &self.name())
+            .field("attr", &self.attr)
+            .field("size", &self.size)
+            .field("first_cluster", &self.first_cluster)
+            .field("mtime", &self.mtime)
+            .finish()
+    }
+}
+
+/// ディレクトリ内のエントリを順に返すイテレータ。
+pub struct ReadDir {
+    dir: File,
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut entry = DirEntry::empty();
+        let res = unsafe { syscall::__read_dir(self.dir.0 as _, (&mut entry) as *mut _ as _, 1) };
+        match res {
+            SysResult { value: 0, error: 0 } => None,
+            SysResult { error: 0, .. } => Some(Ok(entry)),
+            SysResult { error, .. } => Some(Err(error.into())),
+        }
+    }
+}
+
+/// `path` が指すディレクトリのエントリを順に返すイテレータを返す。
+pub fn read_dir(path: impl Display) -> Result<ReadDir> {
+    open(path, FileFlags::RDONLY).map(File::read_dir)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FileFlags(i32);
 
@@ -137,6 +341,8 @@ impl FileFlags {
     pub const WRONLY: Self = Self(1);
     pub const RDWR: Self = Self(2);
     pub const CREAT: Self = Self(0o100);
+    pub const TRUNC: Self = Self(0o1000);
+    pub const APPEND: Self = Self(0o2000);
 }
 
 impl From<FileFlags> for i32 {
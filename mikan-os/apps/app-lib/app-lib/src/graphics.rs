@@ -16,6 +16,8 @@ use crate::errno::ErrNo;
 
 /// ウィンドウ描画時のフラグを表す。
 //  0 bit: 再描画を行わない。
+//  1 bit: 色の上位 8 ビットを不透明度として扱う。
+//  2 bit: 塗りつぶしで、重ねずにピクセルを置き換える。
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LayerFlags(u32);
 
@@ -37,6 +39,67 @@ impl LayerFlags {
         }
         self
     }
+
+    pub fn alpha_enable(&self) -> bool {
+        self.0 & 0b10 != 0
+    }
+
+    /// 色 `0xAARRGGBB` の `AA` を不透明度として扱う。`0` で完全に透明、`0xff` で不透明。
+    /// 不透明でない色は、ウィンドウに描かれている内容の上に重ねる。
+    /// 結果が不透明でないピクセルは、ウィンドウの下にあるものと混ぜて表示される。
+    ///
+    /// 指定しない場合、色の上位 8 ビットは無視して不透明に描く。
+    pub fn set_alpha(mut self, alpha: bool) -> Self {
+        if alpha {
+            self.0 |= 0b10;
+        } else {
+            self.0 &= !0b10;
+        }
+        self
+    }
+
+    pub fn replace_enable(&self) -> bool {
+        self.0 & 0b100 != 0
+    }
+
+    /// [win_fill_rectangle_with_flags] で、ウィンドウに描かれている内容に重ねずに、
+    /// 不透明度も含めてピクセルを置き換える。
+    /// [LayerFlags::set_alpha] と合わせて使うと、ウィンドウの一部を透けて見えるようにできる。
+    pub fn set_replace(mut self, replace: bool) -> Self {
+        if replace {
+            self.0 |= 0b100;
+        } else {
+            self.0 &= !0b100;
+        }
+        self
+    }
+}
+
+/// ウィンドウを開くときのフラグを表す。
+//  0 bit: 大きさを変えられるようにする。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowFlags(u32);
+
+#[allow(clippy::new_without_default)]
+impl WindowFlags {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn resizable(&self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    /// 枠のドラッグや最大化ボタンで大きさを変えられるようにする。
+    /// 大きさが変えられると [AppEvent::WindowResize](crate::events::AppEvent::WindowResize) が届く。
+    pub fn set_resizable(mut self, resizable: bool) -> Self {
+        if resizable {
+            self.0 |= 0b1;
+        } else {
+            self.0 &= !0b1;
+        }
+        self
+    }
 }
 
 /// 作成したウィンドウのレイヤー ID を返す。
@@ -51,12 +114,34 @@ impl LayerFlags {
     doc = "`title` が終端のヌル文字を含めて 1024 バイトを超えた場合は `panic` を起こす。"
 )]
 pub fn open_window(w: i32, h: i32, x: i32, y: i32, title: impl Display) -> u32 {
+    open_window_with_flags(w, h, x, y, title, WindowFlags::new())
+}
+
+/// フラグ `flags` を指定してウィンドウを開き、そのレイヤー ID を返す。
+/// ただし作成に失敗した場合は `0` を返す。
+pub fn open_window_with_flags(
+    w: i32,
+    h: i32,
+    x: i32,
+    y: i32,
+    title: impl Display,
+    flags: WindowFlags,
+) -> u32 {
     #[cfg(not(feature = "alloc"))]
     let res = {
         let mut buf = [0; 1024];
         let mut s = CStrBuf::new_unchecked(&mut buf);
         write!(s, "{}", title).unwrap();
-        unsafe { syscall::__open_window(w as _, h as _, x as _, y as _, s.to_cstr().as_ptr() as _) }
+        unsafe {
+            syscall::__open_window(
+                w as _,
+                h as _,
+                x as _,
+                y as _,
+                s.to_cstr().as_ptr() as _,
+                flags.0 as _,
+            )
+        }
     };
 
     #[cfg(feature = "alloc")]
@@ -69,7 +154,16 @@ pub fn open_window(w: i32, h: i32, x: i32, y: i32, title: impl Display) -> u32 {
                 return 0;
             }
         };
-        unsafe { syscall::__open_window(w as _, h as _, x as _, y as _, s.as_ptr() as _) }
+        unsafe {
+            syscall::__open_window(
+                w as _,
+                h as _,
+                x as _,
+                y as _,
+                s.as_ptr() as _,
+                flags.0 as _,
+            )
+        }
     };
 
     if res.error != 0 {
@@ -183,6 +277,22 @@ pub fn win_write_string_with_flags(
     }
 }
 
+/// ウィンドウ全体の大きさを `w` x `h` に変える。元の内容は重なる部分だけ残る。
+pub fn win_resize(layer_id: u32, w: i32, h: i32) {
+    let res = unsafe { syscall::__win_resize(layer_id as _, w as _, h as _) };
+    if res.error != 0 {
+        ERRNO.store(res.error, Relaxed);
+    }
+}
+
+/// ウィンドウ全体の不透明度を変える。`0` で完全に透明、`255` で不透明。
+pub fn win_set_opacity(layer_id: u32, opacity: u8) {
+    let res = unsafe { syscall::__win_set_opacity(layer_id as _, opacity as _) };
+    if res.error != 0 {
+        ERRNO.store(res.error, Relaxed);
+    }
+}
+
 pub fn win_fill_rectangle(layer_id: u32, x: i32, y: i32, w: i32, h: i32, color: u32) {
     let res = unsafe {
         syscall::__win_fill_rectangle(layer_id as _, x as _, y as _, w as _, h as _, color as _)
@@ -7,6 +7,8 @@ mod syscall;
 
 pub mod args;
 pub mod buf;
+pub mod clipboard;
+pub mod env;
 pub mod errno;
 pub mod events;
 pub mod fs;
@@ -14,6 +16,7 @@ pub mod graphics;
 pub mod io;
 pub mod logger;
 pub mod stdio;
+pub mod termios;
 pub mod time;
 pub mod unistd;
 
@@ -37,7 +37,7 @@ macro_rules! syscall {
 syscall!(log_string, 0x8000_0000, log_level, s);
 syscall!(put_string, 0x8000_0001, fd, buf, len);
 syscall!(exit, 0x8000_0002, code);
-syscall!(open_window, 0x8000_0003, w, h, x, y, title);
+syscall!(open_window, 0x8000_0003, w, h, x, y, title, flags);
 syscall!(
     win_write_string,
     0x8000_0004,
@@ -76,6 +76,20 @@ syscall!(open_file, 0x8000_000c, path, flags);
 syscall!(read_file, 0x8000_000d, fd, buf, count);
 syscall!(demand_pages, 0x8000_000e, nam_pages);
 syscall!(map_file, 0x8000_000f, fd, pfile_size);
+syscall!(read_dir, 0x8000_0010, fd, entries, len);
+syscall!(change_dir, 0x8000_0011, path);
+syscall!(get_cwd, 0x8000_0012, buf, size);
+syscall!(make_dir, 0x8000_0013, path);
+syscall!(remove_file, 0x8000_0014, path);
+syscall!(clock_gettime, 0x8000_0015, clock_id, tp);
+syscall!(create_pipe, 0x8000_0016, fds);
+syscall!(close_file, 0x8000_0017, fd);
+syscall!(make_fifo, 0x8000_0018, path);
+syscall!(ioctl, 0x8000_0019, fd, request, arg);
+syscall!(set_clipboard, 0x8000_001a, ty, data, len);
+syscall!(get_clipboard, 0x8000_001b, ty, ty_size, data, len);
+syscall!(win_resize, 0x8000_001c, layer_id_flags, w, h);
+syscall!(win_set_opacity, 0x8000_001d, layer_id_flags, opacity);
 
 #[repr(C)]
 #[derive(Debug, Default, Clone, Copy)]
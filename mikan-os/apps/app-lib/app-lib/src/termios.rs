@@ -0,0 +1,80 @@
+//! ターミナルの入力モードと大きさ。
+//!
+//! ターミナルは既定ではカノニカルモードで、1 行の入力が終わるまで読み込みを待ち、入力を表示する。
+//! 非カノニカルモード（raw モード）では、キーが押されるたびに読み込めるようになり、
+//! 矢印キーなどは `ESC [ A` のような VT100 のエスケープシーケンスとして読み込める。
+//!
+//! ウィンドウの大きさが変わったことを知りたい場合は、`ESC [ ? 2048 h` を出力すると、
+//! 大きさが変わるたびに `ESC [ 48 ; 行数 ; 列数 ; 高さ ; 幅 t` が入力として届く。
+
+use crate::{errno::ErrNo, fs::File, syscall};
+
+type Result<T> = core::result::Result<T, ErrNo>;
+
+const TCGETS: u64 = 0x5401;
+const TCSETS: u64 = 0x5402;
+const TIOCGWINSZ: u64 = 0x5413;
+
+/// [Termios::lflag]: 行単位で編集してから渡す（カノニカルモード）。
+pub const ICANON: u32 = 0x0002;
+/// [Termios::lflag]: 入力された文字を表示する。
+pub const ECHO: u32 = 0x0008;
+
+/// ターミナルの設定。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termios {
+    pub lflag: u32,
+}
+
+impl Termios {
+    /// 非カノニカルモードにし、入力を表示しないようにする。
+    pub fn make_raw(&mut self) {
+        self.lflag &= !(ICANON | ECHO);
+    }
+}
+
+/// ターミナルの大きさ。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WinSize {
+    /// 文字単位の行数。
+    pub rows: u16,
+    /// 文字単位の列数。
+    pub cols: u16,
+    /// ピクセル単位の幅。
+    pub xpixel: u16,
+    /// ピクセル単位の高さ。
+    pub ypixel: u16,
+}
+
+/// ターミナルを指す `file` の設定を返す。
+///
+/// ターミナルでない場合は [ErrNo::ENOTTY] を返す。
+pub fn tcgetattr(file: &File) -> Result<Termios> {
+    let mut termios = Termios { lflag: 0 };
+    ioctl(file, TCGETS, &mut termios as *mut _ as _)?;
+    Ok(termios)
+}
+
+/// ターミナルを指す `file` の設定を `termios` に変える。
+pub fn tcsetattr(file: &File, termios: &Termios) -> Result<()> {
+    ioctl(file, TCSETS, termios as *const _ as _)
+}
+
+/// ターミナルを指す `file` の大きさを返す。
+pub fn window_size(file: &File) -> Result<WinSize> {
+    let mut size = WinSize::default();
+    ioctl(file, TIOCGWINSZ, &mut size as *mut _ as _)?;
+    Ok(size)
+}
+
+fn ioctl(file: &File, request: u64, arg: u64) -> Result<()> {
+    let res = unsafe { syscall::__ioctl(file.0 as _, request, arg) };
+
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use core::sync::atomic::Ordering;
 
 use crate::{
+    errno::ErrNo,
     syscall::{self, SysResult},
     ERRNO,
 };
@@ -26,6 +27,35 @@ pub fn get_current_tick() -> TimerInfo {
     unsafe { syscall::__get_current_tick() }.into()
 }
 
+/// [clock_gettime] で読む時計の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum ClockId {
+    /// UNIX 時間（UTC）。
+    Realtime = 0,
+    /// OS 起動時からの経過時間。
+    Monotonic = 1,
+}
+
+/// 秒とナノ秒で表した時刻。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+/// `clock_id` が表す時計の現在時刻を返す。
+pub fn clock_gettime(clock_id: ClockId) -> Result<Timespec, ErrNo> {
+    let mut tp = Timespec::default();
+    let res = unsafe { syscall::__clock_gettime(clock_id as _, &mut tp as *mut _ as _) };
+    if res.error != 0 {
+        Err(res.error.into())
+    } else {
+        Ok(tp)
+    }
+}
+
 /// タイマの動作モードを決める。
 pub struct TimerMode(i32);
 
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use alloc::{string::String, vec::Vec};
+use app_lib::{clipboard, eprintln, errno::ErrNo, println};
+
+extern crate alloc;
+extern crate app_lib;
+
+/// 引数を与えた場合は、それを空白でつないだテキストをクリップボードに置く。
+/// どちらの場合も、最後にクリップボードのテキストを読み出して表示する。
+#[app_lib::main]
+fn main(args: app_lib::args::Args) -> i32 {
+    if args.len() >= 2 {
+        let text = args.skip(1).collect::<Vec<String>>().join(" ");
+        if let Err(e) = clipboard::set_text(&text) {
+            eprintln!("failed to set clipboard: {}", e);
+            return 1;
+        }
+    }
+
+    match clipboard::get_text() {
+        Ok(text) => {
+            println!("{}", text);
+            0
+        }
+        Err(ErrNo::ENODATA) => {
+            eprintln!("clipboard is empty");
+            1
+        }
+        Err(e) => {
+            eprintln!("failed to get clipboard: {}", e);
+            1
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    app_lib::eprintln!("{}", info);
+    app_lib::exit(-1)
+}
@@ -25,6 +25,19 @@ fn main(_: Args) -> i32 {
     graphics::win_write_string(layer_id, 24, 40, 0x00c000, "hello world!");
     graphics::win_write_string(layer_id, 40, 56, 0x0000c0, "hello world!");
 
+    // 下に重なっているものが透けて見える帯と、その上に重ねた文字
+    let alpha = graphics::LayerFlags::new().set_alpha(true);
+    graphics::win_fill_rectangle_with_flags(
+        layer_id,
+        4,
+        74,
+        192,
+        20,
+        0x800000c0,
+        alpha.set_replace(true),
+    );
+    graphics::win_write_string_with_flags(layer_id, 60, 76, 0xc0ffffff, "translucent", alpha);
+
     let mut events = [AppEvent::Null; 1];
     loop {
         let n = events::read_event(&mut events);
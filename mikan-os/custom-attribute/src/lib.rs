@@ -17,6 +17,9 @@ use proc_macro2::Span;
 /// その後、この属性が付いている関数を呼び出す。
 /// このとき、呼び出し規則は System-V ABI が使われる。
 ///
+/// 7, 8 番目の引数（スタック渡しの引数）もスタックの切り替え後に積み直すので、
+/// 引数は 8 つまで受け取れる。
+///
 /// 引数は以下のようにして指定する。
 /// * `stack_point` - スタック
 /// * `stack_size` - スタックのサイズを保持する定数名と、そのスタックサイズ
@@ -177,7 +180,13 @@ pub fn kernel_entry(attr: TokenStream, item: TokenStream) -> TokenStream {
         r###"
 .global {0}
 {0}:
+    mov rax, [rsp + 8]
+    mov r10, [rsp + 16]
     lea rsp, {1} + {2}
+    # 7, 8 番目の引数を新しいスタックに積み直す
+    # 2 つ積むので、呼び出し時の RSP は 16 バイト境界に揃ったままになる
+    push r10
+    push rax
     call {3}
 .fin:
     hlt
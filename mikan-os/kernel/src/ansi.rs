@@ -0,0 +1,297 @@
+//! ターミナルに出力される ANSI (VT100/xterm) のエスケープシーケンスを解釈する。
+//!
+//! 対応しているのは、カーソルの移動や消去、文字の色と属性、代替画面など、
+//! 全画面のテキストアプリを動かすのに必要な一部のみ。
+
+use alloc::vec::Vec;
+
+use crate::graphics::PixelColor;
+
+/// 既定の文字色。
+pub const DEFAULT_FG: PixelColor = PixelColor::new(255, 255, 255);
+/// 既定の背景色。
+pub const DEFAULT_BG: PixelColor = PixelColor::new(0, 0, 0);
+
+/// 文字の色と属性。
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Attr {
+    pub fg: PixelColor,
+    pub bg: PixelColor,
+    pub bold: bool,
+    pub reverse: bool,
+}
+
+impl Default for Attr {
+    fn default() -> Self {
+        Self {
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+            bold: false,
+            reverse: false,
+        }
+    }
+}
+
+impl Attr {
+    /// 反転を反映した、実際に描く文字色と背景色を返す。
+    pub fn colors(&self) -> (PixelColor, PixelColor) {
+        if self.reverse {
+            (self.bg, self.fg)
+        } else {
+            (self.fg, self.bg)
+        }
+    }
+
+    /// 消去した部分に使う属性。xterm と同じく、背景色だけを引き継ぐ。
+    pub fn erased(&self) -> Self {
+        Self {
+            bg: self.bg,
+            ..Self::default()
+        }
+    }
+
+    /// SGR (Select Graphic Rendition) のパラメータ `params` を適用する。
+    pub fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Self::default();
+            return;
+        }
+
+        let mut params = params.iter().copied();
+        while let Some(p) = params.next() {
+            match p {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                30..=37 => self.fg = color_256(p - 30),
+                90..=97 => self.fg = color_256(p - 90 + 8),
+                39 => self.fg = DEFAULT_FG,
+                40..=47 => self.bg = color_256(p - 40),
+                100..=107 => self.bg = color_256(p - 100 + 8),
+                49 => self.bg = DEFAULT_BG,
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().map(color_256),
+                        Some(2) => {
+                            let mut c = || params.next().unwrap_or(0).min(255) as u8;
+                            Some(PixelColor::new(c(), c(), c()))
+                        }
+                        _ => None,
+                    };
+                    match (p, color) {
+                        (38, Some(color)) => self.fg = color,
+                        (48, Some(color)) => self.bg = color,
+                        _ => {}
+                    }
+                }
+                // 下線や点滅などには対応していない
+                _ => {}
+            }
+        }
+    }
+}
+
+/// xterm の 256 色のパレットで `n` 番目の色を返す。
+pub fn color_256(n: u16) -> PixelColor {
+    const BASIC: [u32; 16] = [
+        0x000000, 0xcd0000, 0x00cd00, 0xcdcd00, 0x0000ee, 0xcd00cd, 0x00cdcd, 0xe5e5e5, 0x7f7f7f,
+        0xff0000, 0x00ff00, 0xffff00, 0x5c5cff, 0xff00ff, 0x00ffff, 0xffffff,
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    match n {
+        0..16 => PixelColor::to_color(BASIC[n as usize]),
+        // 6x6x6 の色立方体
+        16..232 => {
+            let n = (n - 16) as usize;
+            PixelColor::new(LEVELS[n / 36], LEVELS[n / 6 % 6], LEVELS[n % 6])
+        }
+        // 24 段階の灰色
+        _ => {
+            let level = 8 + 10 * (n.min(255) - 232) as u8;
+            PixelColor::new(level, level, level)
+        }
+    }
+}
+
+/// 画面の 1 文字分。
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// 全角文字の右半分は `'\0'` で表す。
+    pub c: char,
+    pub attr: Attr,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(Attr::default())
+    }
+}
+
+impl Cell {
+    pub fn blank(attr: Attr) -> Self {
+        Self { c: ' ', attr }
+    }
+}
+
+/// 出力を解釈した結果、ターミナルが行う処理。
+#[derive(Clone, PartialEq, Eq)]
+pub enum Action {
+    /// 文字を表示する。
+    Print(char),
+    /// 改行やバックスペースなどの制御文字。
+    Control(char),
+    /// `ESC` に続く 1 文字で表されるシーケンス。
+    Esc(char),
+    /// `ESC [` で始まる CSI シーケンス。省略されたパラメータは `0` になる。
+    Csi {
+        params: Vec<u16>,
+        /// `?` などパラメータの前に置かれる文字。
+        private: Option<char>,
+        action: char,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// `ESC (` などの後ろの 1 文字を読み飛ばす。
+    EscapeIntermediate,
+    Csi,
+    /// OSC (`ESC ]`) は解釈せず、BEL もしくは ST まで読み飛ばす。
+    Osc,
+}
+
+/// 1 つの CSI シーケンスで受け付けるパラメータの数。
+const MAX_PARAMS: usize = 16;
+
+/// 出力される文字を 1 文字ずつ受け取り、エスケープシーケンスを組み立てる。
+///
+/// シーケンスが複数の書き込みに分かれていても解釈できるよう、途中の状態を保持する。
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    params: Vec<u16>,
+    /// 読んでいる途中のパラメータ。
+    param: Option<u16>,
+    private: Option<char>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Vec::new(),
+            param: None,
+            private: None,
+        }
+    }
+
+    /// 文字 `c` を読む。処理が 1 つ組み上がったら返す。
+    pub fn advance(&mut self, c: char) -> Option<Action> {
+        match c {
+            '\x1b' => {
+                self.state = State::Escape;
+                return None;
+            }
+            // CAN, SUB はシーケンスを中断する
+            '\x18' | '\x1a' => {
+                self.state = State::Ground;
+                return None;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => Some(if c.is_control() {
+                Action::Control(c)
+            } else {
+                Action::Print(c)
+            }),
+            State::Escape => match c {
+                '[' => {
+                    self.state = State::Csi;
+                    self.params.clear();
+                    self.param = None;
+                    self.private = None;
+                    None
+                }
+                ']' => {
+                    self.state = State::Osc;
+                    None
+                }
+                '(' | ')' | '*' | '+' | '#' => {
+                    self.state = State::EscapeIntermediate;
+                    None
+                }
+                _ => {
+                    self.state = State::Ground;
+                    Some(Action::Esc(c))
+                }
+            },
+            State::EscapeIntermediate => {
+                self.state = State::Ground;
+                None
+            }
+            State::Csi => match c {
+                '0'..='9' => {
+                    let digit = c as u16 - '0' as u16;
+                    let param = self.param.unwrap_or(0);
+                    self.param = Some(param.saturating_mul(10).saturating_add(digit));
+                    None
+                }
+                // サブパラメータの区切り `:` も `;` と同じに扱う
+                ';' | ':' => {
+                    let param = self.param.take().unwrap_or(0);
+                    self.push_param(param);
+                    None
+                }
+                '<' | '=' | '>' | '?' => {
+                    self.private = Some(c);
+                    None
+                }
+                // 中間文字は使わない
+                ' '..='/' => None,
+                '@'..='~' => {
+                    self.state = State::Ground;
+                    if let Some(param) = self.param.take() {
+                        self.push_param(param);
+                    }
+                    Some(Action::Csi {
+                        params: core::mem::take(&mut self.params),
+                        private: self.private.take(),
+                        action: c,
+                    })
+                }
+                // シーケンスの途中の制御文字はそのまま処理する
+                _ if c.is_control() => Some(Action::Control(c)),
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+            State::Osc => {
+                if c == '\x07' {
+                    self.state = State::Ground;
+                }
+                None
+            }
+        }
+    }
+
+    /// CSI シーケンスのパラメータを加える。[MAX_PARAMS] 個を超えた分は捨てる。
+    fn push_param(&mut self, param: u16) {
+        if self.params.len() < MAX_PARAMS {
+            self.params.push(param);
+        }
+    }
+}
@@ -26,6 +26,17 @@ pub enum AppEvent {
         ascii: u8,
         press: bool,
     },
+    MouseWheel {
+        x: i32,
+        y: i32,
+        delta: i32,
+    },
+    /// ウィンドウの大きさが変えられた。`width`, `height` はウィンドウ全体の大きさ。
+    WindowResize {
+        layer_id: u32,
+        width: i32,
+        height: i32,
+    },
 }
 
 impl Default for AppEvent {
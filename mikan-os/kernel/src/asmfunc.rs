@@ -35,6 +35,88 @@ pub fn io_in_32(addr: u16) -> u32 {
     data
 }
 
+pub fn io_out_16(addr: u16, data: u16) {
+    unsafe {
+        asm!(
+            "out dx, ax",
+            in("dx") addr,
+            in("ax") data,
+        )
+    };
+}
+
+pub fn io_in_16(addr: u16) -> u16 {
+    let data;
+    unsafe {
+        asm!(
+            "in ax, dx",
+            in("dx") addr,
+            out("ax") data,
+        )
+    };
+    data
+}
+
+pub fn io_out_8(addr: u16, data: u8) {
+    unsafe {
+        asm!(
+            "out dx, al",
+            in("dx") addr,
+            in("al") data,
+        )
+    };
+}
+
+pub fn io_in_8(addr: u16) -> u8 {
+    let data;
+    unsafe {
+        asm!(
+            "in al, dx",
+            in("dx") addr,
+            out("al") data,
+        )
+    };
+    data
+}
+
+pub fn rdtsc() -> u64 {
+    let (lo, hi): (u32, u32);
+    unsafe {
+        asm!(
+            "rdtsc",
+            out("eax") lo,
+            out("edx") hi,
+        )
+    };
+    (hi as u64) << 32 | lo as u64
+}
+
+/// RDRAND 命令で乱数を得る。
+/// CPU が RDRAND に対応していない場合や、乱数の生成に失敗した場合は `None` を返す。
+pub fn rdrand() -> Option<u64> {
+    // CPUID.01H:ECX.RDRAND[bit 30]
+    let supported = core::arch::x86_64::__cpuid(1).ecx & (1 << 30) != 0;
+    if !supported {
+        return None;
+    }
+
+    let value: u64;
+    let ok: u8;
+    unsafe {
+        asm!(
+            "rdrand {v}",
+            "setc {ok}",
+            v = out(reg) value,
+            ok = out(reg_byte) ok,
+        )
+    };
+    if ok != 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
 pub fn get_cs() -> u16 {
     let cs;
     unsafe {
@@ -0,0 +1,101 @@
+//! QEMU や Bochs の標準 VGA (Bochs Graphics Adapter) の操作。
+//!
+//! VRAM に画面 2 枚分の領域を取り、表示する側を切り替える（ページフリップ）ために使う。
+
+use crate::{
+    asmfunc::{io_in_16, io_in_8, io_out_16},
+    frame_buffer_config::FrameBufferConfig,
+    pci,
+};
+
+/// BGA のレジスタの番号を書き込む IO ポート。
+const VBE_DISPI_IOPORT_INDEX: u16 = 0x01ce;
+/// BGA のレジスタの値を読み書きする IO ポート。
+const VBE_DISPI_IOPORT_DATA: u16 = 0x01cf;
+
+const VBE_DISPI_INDEX_ID: u16 = 0;
+const VBE_DISPI_INDEX_XRES: u16 = 1;
+const VBE_DISPI_INDEX_YRES: u16 = 2;
+const VBE_DISPI_INDEX_BPP: u16 = 3;
+const VBE_DISPI_INDEX_ENABLE: u16 = 4;
+const VBE_DISPI_INDEX_VIRT_WIDTH: u16 = 6;
+const VBE_DISPI_INDEX_VIRT_HEIGHT: u16 = 7;
+const VBE_DISPI_INDEX_X_OFFSET: u16 = 8;
+const VBE_DISPI_INDEX_Y_OFFSET: u16 = 9;
+
+/// 仮想画面と表示位置のずらしに対応している版の最小値。
+const VBE_DISPI_ID2: u16 = 0xb0c2;
+/// [VBE_DISPI_ID2] から始まる版の最大値。
+const VBE_DISPI_ID5: u16 = 0xb0c5;
+const VBE_DISPI_ENABLED: u16 = 0x01;
+
+/// QEMU の標準 VGA の PCI ベンダ ID とデバイス ID。
+const BGA_VENDOR_ID: u16 = 0x1234;
+const BGA_DEVICE_ID: u16 = 0x1111;
+
+/// VGA の入力ステータスレジスタ 1。3 ビット目が垂直帰線期間中かどうか。
+const VGA_INPUT_STATUS_1: u16 = 0x03da;
+/// 垂直帰線期間を待つときに、ステータスを読む回数の上限。
+const RETRACE_WAIT_LIMIT: usize = 100_000;
+
+fn read(index: u16) -> u16 {
+    io_out_16(VBE_DISPI_IOPORT_INDEX, index);
+    io_in_16(VBE_DISPI_IOPORT_DATA)
+}
+
+fn write(index: u16, value: u16) {
+    io_out_16(VBE_DISPI_IOPORT_INDEX, index);
+    io_out_16(VBE_DISPI_IOPORT_DATA, value);
+}
+
+/// `config` の画面が BGA のもので、VRAM に同じ大きさの画面をもう 1 枚置けるかどうか。
+///
+/// 画面の先頭が VRAM の先頭で、表示位置をずらしていない場合だけ `true` を返す。
+pub fn supports_page_flip(config: &FrameBufferConfig) -> bool {
+    let Some(dev) = pci::DEVICES.read().iter().copied().find(|dev| {
+        dev.read_vendor_id() == BGA_VENDOR_ID
+            && pci::read_device_id(dev.bus(), dev.device(), dev.function()) == BGA_DEVICE_ID
+    }) else {
+        return false;
+    };
+    // VRAM は BAR0 にあるプリフェッチ可能なメモリ
+    match dev.read_bar(0) {
+        Ok(bar) if (bar & !0xf) as usize == config.frame_buffer => {}
+        _ => return false,
+    }
+
+    let id = read(VBE_DISPI_INDEX_ID);
+    let yres = config.vertical_resolution as u16;
+    (VBE_DISPI_ID2..=VBE_DISPI_ID5).contains(&id)
+        && read(VBE_DISPI_INDEX_ENABLE) & VBE_DISPI_ENABLED != 0
+        && read(VBE_DISPI_INDEX_BPP) == 32
+        && read(VBE_DISPI_INDEX_XRES) as usize == config.horizontal_resolution
+        && read(VBE_DISPI_INDEX_YRES) == yres
+        && read(VBE_DISPI_INDEX_VIRT_WIDTH) as usize == config.pixels_per_scan_line
+        && read(VBE_DISPI_INDEX_VIRT_HEIGHT) as usize >= config.vertical_resolution * 2
+        && read(VBE_DISPI_INDEX_X_OFFSET) == 0
+        && read(VBE_DISPI_INDEX_Y_OFFSET) == 0
+}
+
+/// VRAM のうち `y` 行目から先を表示する。
+pub fn set_y_offset(y: u16) {
+    write(VBE_DISPI_INDEX_Y_OFFSET, y);
+}
+
+/// 次の垂直帰線期間が始まるまで待つ。
+///
+/// 帰線期間を知らせないハードウェアで止まらないよう、一定回数読んだらあきらめる。
+pub fn wait_vertical_retrace() {
+    let in_retrace = || io_in_8(VGA_INPUT_STATUS_1) & 0x08 != 0;
+    // 帰線期間の途中から切り替えると間に合わないことがあるので、一度抜けるのを待つ
+    for _ in 0..RETRACE_WAIT_LIMIT {
+        if !in_retrace() {
+            break;
+        }
+    }
+    for _ in 0..RETRACE_WAIT_LIMIT {
+        if in_retrace() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! システム全体で共有するクリップボード。
+//!
+//! 内容は `text/plain` のような MIME タイプ風の文字列と組にして保持する。
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::str;
+
+use crate::sync::Mutex;
+
+/// テキストを表す種類。
+pub const TEXT_PLAIN: &str = "text/plain";
+/// 種類を表す文字列の最大の長さ。
+pub const MAX_TYPE_LEN: usize = 64;
+/// クリップボードに置ける最大のバイト数。
+pub const MAX_SIZE: usize = 1 << 20;
+
+static CLIPBOARD: Mutex<Option<Arc<Content>>> = Mutex::new(None);
+
+/// クリップボードの内容。
+#[derive(Debug)]
+pub struct Content {
+    pub ty: String,
+    pub data: Vec<u8>,
+}
+
+impl Content {
+    /// 内容がテキストであれば、文字列として返す。
+    pub fn text(&self) -> Option<&str> {
+        if self.ty.starts_with("text/") {
+            str::from_utf8(&self.data).ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// クリップボードの内容を、種類 `ty` のデータ `data` で置き換える。
+pub fn set(ty: &str, data: Vec<u8>) {
+    let content = Content {
+        ty: ty.into(),
+        data,
+    };
+    *CLIPBOARD.lock_wait() = Some(Arc::new(content));
+}
+
+/// クリップボードにテキストを置く。
+pub fn set_text(text: &str) {
+    set(TEXT_PLAIN, text.as_bytes().to_vec());
+}
+
+/// クリップボードの内容を返す。空の場合は [None] を返す。
+pub fn get() -> Option<Arc<Content>> {
+    CLIPBOARD.lock_wait().clone()
+}
@@ -128,6 +128,8 @@ impl Console {
         }
 
         layer_manager.draw_id(self.layer_id);
+        // 起動中やエラーで止まる直前のログも見えるよう、次のフレームを待たずに表示する
+        layer_manager.compose();
     }
 
     fn new_line(&mut self, window: &mut Window) {
@@ -173,7 +175,9 @@ impl Write for Console {
             }
         }
 
-        LAYER_MANAGER.lock_wait().draw_id(self.layer_id);
+        let mut layer_manager = LAYER_MANAGER.lock_wait();
+        layer_manager.draw_id(self.layer_id);
+        layer_manager.compose();
         Ok(())
     }
 }
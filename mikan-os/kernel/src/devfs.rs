@@ -0,0 +1,251 @@
+//! `/dev` にマウントされるデバイスファイルシステム。
+
+use alloc::{collections::BTreeMap, format, vec::Vec};
+use core::{
+    cmp, slice,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+use crate::{
+    asmfunc,
+    console::CONSOLE,
+    error::{Code, Result},
+    file::{DirEntryInfo, FileDescriptor},
+    frame_buffer,
+    graphics::FB_CONFIG,
+    make_error,
+    message::MessageType,
+    sync::Mutex,
+    task,
+    terminal::{TerminalRef, Termios, WinSize},
+};
+
+/// 開いているターミナルの一覧。
+/// key: ターミナルのタスク ID。
+static TERMINALS: Mutex<BTreeMap<u64, TerminalEntry>> = Mutex::new(BTreeMap::new());
+
+/// 登録されたターミナルと、他のタスクから読めるように写しておいたその設定。
+#[derive(Clone, Copy)]
+struct TerminalEntry {
+    term: TerminalRef,
+    /// ターミナルのウィンドウのレイヤー ID。
+    layer_id: u32,
+    termios: Termios,
+    win_size: WinSize,
+}
+
+/// RDRAND が使えない場合の疑似乱数の状態。
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// `/dev` 以下のファイルが表すデバイス。
+#[derive(Debug, Clone, Copy)]
+pub enum Device {
+    /// 書き込みを全て捨て、読み込みは常に EOF を返す。
+    Null,
+    /// 読み込むと `0` を返し続ける。
+    Zero,
+    /// 読み込むと乱数を返し続ける。
+    Random,
+    /// デスクトップのコンソールに書き込む。
+    Console,
+    /// タスク ID が `.0` のターミナルに書き込む。
+    Terminal(u64),
+    /// 画面のフレームバッファを直接読み書きする。
+    FrameBuffer,
+}
+
+/// 常に存在するデバイス。
+const FIXED_DEVICES: [(&str, Device); 5] = [
+    ("null", Device::Null),
+    ("zero", Device::Zero),
+    ("random", Device::Random),
+    ("console", Device::Console),
+    ("fb0", Device::FrameBuffer),
+];
+
+impl Device {
+    /// `off` から `buf` に読み込み、読み込んだバイト数を返す。
+    pub fn read(&self, buf: &mut [u8], off: usize) -> usize {
+        match self {
+            Self::Null | Self::Console | Self::Terminal(_) => 0,
+            Self::Zero => {
+                buf.fill(0);
+                buf.len()
+            }
+            Self::Random => {
+                for chunk in buf.chunks_mut(8) {
+                    let r = random().to_le_bytes();
+                    chunk.copy_from_slice(&r[..chunk.len()]);
+                }
+                buf.len()
+            }
+            Self::FrameBuffer => {
+                let fb = frame_buffer();
+                let len = cmp::min(buf.len(), fb.len().saturating_sub(off));
+                buf[..len].copy_from_slice(&fb[off..off + len]);
+                len
+            }
+        }
+    }
+
+    /// `off` に `buf` を書き込み、書き込んだバイト数を返す。
+    pub fn write(&self, buf: &[u8], off: usize) -> Result<usize> {
+        match self {
+            Self::Null | Self::Zero | Self::Random => Ok(buf.len()),
+            Self::Console => {
+                asmfunc::cli();
+                CONSOLE.lock_wait().put_string(buf);
+                asmfunc::sti();
+                Ok(buf.len())
+            }
+            Self::Terminal(id) => {
+                let Some(mut term) = TERMINALS.lock_wait().get(id).map(|entry| entry.term) else {
+                    return Err(make_error!(Code::NoSuchEntry));
+                };
+                asmfunc::cli();
+                let current_id = task::current_task().id();
+                asmfunc::sti();
+                if *id == current_id {
+                    term.write_from_device(buf);
+                } else {
+                    // 他のタスクのターミナルは、そのタスクに表示してもらう
+                    let msg = MessageType::TerminalWrite { data: buf.to_vec() };
+                    asmfunc::cli();
+                    let result = task::send_message(*id, msg.into());
+                    asmfunc::sti();
+                    result?;
+                }
+                Ok(buf.len())
+            }
+            Self::FrameBuffer => {
+                let fb = frame_buffer();
+                let len = cmp::min(buf.len(), fb.len().saturating_sub(off));
+                fb[off..off + len].copy_from_slice(&buf[..len]);
+                Ok(len)
+            }
+        }
+    }
+
+    /// デバイスファイルとしてのサイズを返す。
+    pub fn size(&self) -> usize {
+        match self {
+            Self::FrameBuffer => frame_buffer().len(),
+            _ => 0,
+        }
+    }
+}
+
+/// `/dev` 以降のパス `path` が指すデバイスファイルもしくはディレクトリを開く。
+pub fn open(path: &str) -> Result<FileDescriptor> {
+    let (name, post_slash) = match path.trim_start_matches('/').split_once('/') {
+        Some((name, "")) => (name, true),
+        Some(_) => return Err(make_error!(Code::NoSuchEntry)),
+        None => (path.trim_start_matches('/'), false),
+    };
+    if name.is_empty() {
+        return Ok(FileDescriptor::new_virtual_dir(entries()));
+    }
+
+    let Some(dev) = find_device(name) else {
+        return Err(make_error!(Code::NoSuchEntry));
+    };
+    if post_slash {
+        return Err(make_error!(Code::NotDirectory));
+    }
+    Ok(FileDescriptor::new_device(dev))
+}
+
+/// `/dev` に含まれるエントリの一覧を返す。
+pub fn entries() -> Vec<DirEntryInfo> {
+    let mut entries: Vec<_> = FIXED_DEVICES
+        .iter()
+        .map(|(name, dev)| DirEntryInfo::new(name, 0, dev.size() as _))
+        .collect();
+    for &id in TERMINALS.lock_wait().keys() {
+        entries.push(DirEntryInfo::new(&format!("tty{}", id), 0, 0));
+    }
+    entries
+}
+
+/// ウィンドウを持つターミナルを `/dev/tty<task_id>` として登録する。
+pub fn register_terminal(task_id: u64, term: TerminalRef) {
+    let entry = TerminalEntry {
+        term,
+        layer_id: term.layer_id(),
+        termios: term.termios(),
+        win_size: term.window_size(),
+    };
+    TERMINALS.lock_wait().insert(task_id, entry);
+}
+
+/// [register_terminal] で登録したターミナルを取り除く。
+pub fn unregister_terminal(task_id: u64) {
+    TERMINALS.lock_wait().remove(&task_id);
+}
+
+/// タスク ID が `task_id` のターミナルを返す。
+///
+/// ターミナルはそれを持つタスクしか触らない前提なので、自身のタスクのターミナルにのみ使う。
+pub fn terminal(task_id: u64) -> Option<TerminalRef> {
+    TERMINALS.lock_wait().get(&task_id).map(|entry| entry.term)
+}
+
+/// `layer_id` がターミナルのウィンドウのレイヤーかどうか。他のタスクからも使える。
+pub fn is_terminal_layer(layer_id: u32) -> bool {
+    TERMINALS
+        .lock_wait()
+        .values()
+        .any(|entry| entry.layer_id == layer_id)
+}
+
+/// タスク ID が `task_id` のターミナルの設定が変わったことを記録する。ターミナルを持つタスクが呼ぶ。
+pub fn update_terminal(task_id: u64, termios: Termios, win_size: WinSize) {
+    if let Some(entry) = TERMINALS.lock_wait().get_mut(&task_id) {
+        entry.termios = termios;
+        entry.win_size = win_size;
+    }
+}
+
+/// タスク ID が `task_id` のターミナルの設定を返す。他のタスクからも使える。
+pub fn terminal_settings(task_id: u64) -> Option<(Termios, WinSize)> {
+    TERMINALS
+        .lock_wait()
+        .get(&task_id)
+        .map(|entry| (entry.termios, entry.win_size))
+}
+
+fn find_device(name: &str) -> Option<Device> {
+    if let Some(&(_, dev)) = FIXED_DEVICES.iter().find(|(n, _)| *n == name) {
+        return Some(dev);
+    }
+
+    let id = name.strip_prefix("tty")?.parse().ok()?;
+    if TERMINALS.lock_wait().contains_key(&id) {
+        Some(Device::Terminal(id))
+    } else {
+        None
+    }
+}
+
+/// RDRAND が使える場合はそれを、使えない場合は TSC を種とした xorshift で乱数を返す。
+fn random() -> u64 {
+    if let Some(r) = asmfunc::rdrand() {
+        return r;
+    }
+
+    let mut x = RANDOM_STATE.load(Relaxed);
+    if x == 0 {
+        x = asmfunc::rdtsc() | 1;
+    }
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    RANDOM_STATE.store(x, Relaxed);
+    x
+}
+
+fn frame_buffer() -> &'static mut [u8] {
+    let config = FB_CONFIG.as_ref();
+    let len = frame_buffer::bytes_per_scan_line(config) * config.vertical_resolution;
+    unsafe { slice::from_raw_parts_mut(config.frame_buffer as *mut u8, len) }
+}
@@ -34,6 +34,12 @@ pub enum Code {
     NoSuchEntry,
     FreeTypeError,
     EndpointNotInCharge,
+    NotDirectory,
+    ReadOnly,
+    AlreadyExists,
+    NotEmpty,
+    TooManyLinks,
+    BrokenPipe,
 }
 
 impl Display for Code {
@@ -68,6 +74,12 @@ impl Display for Code {
             Self::NoSuchEntry => write!(f, "NoSuchEntry"),
             Self::FreeTypeError => write!(f, "FreeTypeError"),
             Self::EndpointNotInCharge => write!(f, "EndpointNotInCharge"),
+            Self::NotDirectory => write!(f, "NotDirectory"),
+            Self::ReadOnly => write!(f, "ReadOnly"),
+            Self::AlreadyExists => write!(f, "AlreadyExists"),
+            Self::NotEmpty => write!(f, "NotEmpty"),
+            Self::TooManyLinks => write!(f, "TooManyLinks"),
+            Self::BrokenPipe => write!(f, "BrokenPipe"),
         }
     }
 }
@@ -0,0 +1,401 @@
+//! ext2 ファイルシステムの読み込み専用ドライバ。
+//!
+//! ブートローダが `\ext2_disk` から読み込んだボリューム（`mke2fs` で作ったイメージや、
+//! パーティションを `dd` で切り出したもの）を `/mnt` にマウントする。
+//!
+//! cf. https://www.nongnu.org/ext2-doc/ext2.html
+
+use alloc::{string::String, vec, vec::Vec};
+use core::{cmp, ffi::c_void, slice};
+
+use crate::{
+    error::{Code, Result},
+    fat::Attribute,
+    file::{DirEntryInfo, FileDescriptor},
+    make_error,
+    util::OnceStatic,
+};
+
+/// ルートディレクトリの inode 番号。
+const ROOT_INODE: u32 = 2;
+/// ボリューム先頭からスーパーブロックまでのオフセット。
+const SUPERBLOCK_OFFSET: usize = 1024;
+const EXT2_MAGIC: u16 = 0xef53;
+/// ディレクトリエントリがファイル種別を持つことを表す `s_feature_incompat` のビット。
+/// 読み込みには影響しないので、これ以外のビットが立っている場合のみ非対応とする。
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// inode が直接持つブロック番号の数。
+const NUM_DIRECT_BLOCKS: usize = 12;
+/// これより短いシンボリックリンクは、リンク先を `i_block` に直接持つ。
+const FAST_SYMLINK_MAX: u64 = 60;
+/// パスの解決中に辿るシンボリックリンクの最大数。
+const MAX_SYMLINK_FOLLOWS: usize = 8;
+
+static VOLUME: OnceStatic<Volume> = OnceStatic::new();
+
+/// ボリューム全体と、スーパーブロックから得た読み込みに必要な情報。
+struct Volume {
+    image: &'static [u8],
+    block_size: usize,
+    inodes_per_group: u32,
+    inode_size: usize,
+    /// ブロックグループディスクリプタテーブルの先頭ブロック。
+    group_desc_block: u32,
+}
+
+/// inode のうち、読み込みに必要な部分。
+#[derive(Debug, Clone, Copy)]
+pub struct Inode {
+    mode: u16,
+    size: u64,
+    /// 最終更新日時（UNIX 時間）。
+    mtime: u32,
+    /// 512 バイト単位の使用ブロック数。
+    blocks: u32,
+    /// 直接ブロック 12 個と、1 段から 3 段の間接ブロック。
+    block: [u32; 15],
+}
+
+/// `i_mode` のうちファイル種別を表す部分。
+mod mode {
+    pub const TYPE_MASK: u16 = 0xf000;
+    pub const DIR: u16 = 0x4000;
+    pub const SYMLINK: u16 = 0xa000;
+}
+
+impl Inode {
+    pub fn size(&self) -> usize {
+        self.size as _
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime as _
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & mode::TYPE_MASK == mode::DIR
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & mode::TYPE_MASK == mode::SYMLINK
+    }
+}
+
+/// 大きさが `image_size` バイトのボリュームの先頭 `volume_image` からスーパーブロックを読み込み、
+/// マウントする。
+///
+/// ext2 でない場合や、読み込みに影響する未対応の機能が使われている場合、
+/// スーパーブロックの示す大きさがボリュームに収まらない場合はエラーを返す。
+pub fn init(volume_image: *mut c_void, image_size: usize) -> Result<()> {
+    let base = volume_image as *const u8;
+    if image_size < SUPERBLOCK_OFFSET + 1024 {
+        return Err(make_error!(Code::InvalidFormat, "ext2 image is too small"));
+    }
+    let sb = unsafe { slice::from_raw_parts(base.add(SUPERBLOCK_OFFSET), 1024) };
+    if read_u16(sb, 56) != EXT2_MAGIC {
+        return Err(make_error!(Code::InvalidFormat));
+    }
+    if read_u32(sb, 96) & !INCOMPAT_FILETYPE != 0 {
+        return Err(make_error!(
+            Code::NotImplemented,
+            "unsupported ext2 incompatible features"
+        ));
+    }
+
+    // ext2 のブロックサイズは 64 KiB まで
+    let log_block_size = read_u32(sb, 24);
+    if log_block_size > 6 {
+        return Err(make_error!(Code::InvalidFormat));
+    }
+    let block_size = 1024 << log_block_size;
+    let blocks_count = read_u32(sb, 4) as usize;
+    let inodes_per_group = read_u32(sb, 40);
+    // リビジョン 0 の inode は 128 バイト固定
+    let inode_size = if read_u32(sb, 76) == 0 {
+        128
+    } else {
+        read_u16(sb, 88) as usize
+    };
+    if inodes_per_group == 0 || inode_size < 128 {
+        return Err(make_error!(Code::InvalidFormat));
+    }
+    // 読み込まれた大きさを超えるブロックを指していると、範囲外を読んでしまう
+    if blocks_count
+        .checked_mul(block_size)
+        .is_none_or(|size| size > image_size)
+    {
+        return Err(make_error!(
+            Code::InvalidFormat,
+            "ext2 volume is larger than its image"
+        ));
+    }
+
+    VOLUME.init(Volume {
+        image: unsafe { slice::from_raw_parts(base, blocks_count * block_size) },
+        block_size,
+        inodes_per_group,
+        inode_size,
+        group_desc_block: read_u32(sb, 20) + 1,
+    });
+    Ok(())
+}
+
+/// ext2 ボリュームがマウントされているかどうかを返す。
+pub fn is_mounted() -> bool {
+    VOLUME.is_initialized()
+}
+
+/// `/mnt` 以降のパス `path` が指すファイルもしくはディレクトリを開く。
+///
+/// シンボリックリンクは全て辿る。
+/// ボリュームがマウントされていない場合、`/mnt` は空のディレクトリとなる。
+pub fn open(path: &str) -> Result<FileDescriptor> {
+    if !is_mounted() {
+        return if path.bytes().all(|b| b == b'/') {
+            Ok(FileDescriptor::new_virtual_dir(Vec::new()))
+        } else {
+            Err(make_error!(Code::NoSuchEntry))
+        };
+    }
+    let volume = VOLUME.as_ref();
+
+    let inode = volume.lookup(path)?;
+    if inode.is_dir() {
+        Ok(FileDescriptor::new_virtual_dir(volume.dir_entries(&inode)?))
+    } else if path.ends_with('/') {
+        Err(make_error!(Code::NotDirectory))
+    } else {
+        Ok(FileDescriptor::new_ext2(inode))
+    }
+}
+
+/// `inode` の `off` バイト目から `buf` に読み込み、読み込んだバイト数を返す。
+pub fn read(inode: &Inode, off: usize, buf: &mut [u8]) -> usize {
+    VOLUME.as_ref().read(inode, off, buf)
+}
+
+impl Volume {
+    /// 番号 `n` のブロックを返す。
+    fn block(&self, n: u32) -> Result<&'static [u8]> {
+        let start = n as usize * self.block_size;
+        self.image
+            .get(start..start + self.block_size)
+            .ok_or(make_error!(Code::InvalidFormat))
+    }
+
+    fn inode(&self, n: u32) -> Result<Inode> {
+        if n == 0 {
+            return Err(make_error!(Code::InvalidFormat));
+        }
+        let group = (n - 1) / self.inodes_per_group;
+        let index = ((n - 1) % self.inodes_per_group) as usize;
+
+        // ブロックグループディスクリプタは 1 つ 32 バイト
+        let desc_off = group as usize * 32;
+        let desc_block = self.group_desc_block + (desc_off / self.block_size) as u32;
+        let desc = &self.block(desc_block)?[desc_off % self.block_size..];
+        let inode_table = read_u32(desc, 8);
+
+        let off = index * self.inode_size;
+        let table_block = inode_table + (off / self.block_size) as u32;
+        let raw = &self.block(table_block)?[off % self.block_size..];
+
+        let mut block = [0; 15];
+        for (i, b) in block.iter_mut().enumerate() {
+            *b = read_u32(raw, 40 + i * 4);
+        }
+        let mode = read_u16(raw, 0);
+        let mut size = read_u32(raw, 4) as u64;
+        // 通常ファイルでは i_dir_acl が サイズの上位 32 ビットを表す
+        if mode & mode::TYPE_MASK != mode::DIR {
+            size |= (read_u32(raw, 108) as u64) << 32;
+        }
+
+        Ok(Inode {
+            mode,
+            size,
+            mtime: read_u32(raw, 16),
+            blocks: read_u32(raw, 28),
+            block,
+        })
+    }
+
+    /// `inode` の `index` 番目のデータブロックの番号を返す。
+    /// 割り当てられていない（スパースファイルの穴である）場合は `0` を返す。
+    fn data_block(&self, inode: &Inode, index: usize) -> Result<u32> {
+        let per_block = self.block_size / 4;
+        if index < NUM_DIRECT_BLOCKS {
+            return Ok(inode.block[index]);
+        }
+
+        // 間接ブロックの段数と、その段数の中での位置を求める
+        let mut index = index - NUM_DIRECT_BLOCKS;
+        let mut span = per_block;
+        let mut level = 0;
+        while index >= span {
+            index -= span;
+            span *= per_block;
+            level += 1;
+            if level == 3 {
+                return Err(make_error!(Code::IndexOutOfRange));
+            }
+        }
+
+        let mut block = inode.block[NUM_DIRECT_BLOCKS + level];
+        while block != 0 {
+            span /= per_block;
+            block = read_u32(self.block(block)?, index / span * 4);
+            if span == 1 {
+                break;
+            }
+            index %= span;
+        }
+        Ok(block)
+    }
+
+    fn read(&self, inode: &Inode, off: usize, buf: &mut [u8]) -> usize {
+        let len = cmp::min(buf.len(), inode.size().saturating_sub(off));
+
+        let mut total = 0;
+        while total < len {
+            let pos = off + total;
+            let block_off = pos % self.block_size;
+            let n = cmp::min(len - total, self.block_size - block_off);
+            let dst = &mut buf[total..total + n];
+
+            match self.data_block(inode, pos / self.block_size) {
+                Ok(0) => dst.fill(0),
+                Ok(block) => match self.block(block) {
+                    Ok(data) => dst.copy_from_slice(&data[block_off..block_off + n]),
+                    Err(_) => break,
+                },
+                Err(_) => break,
+            }
+            total += n;
+        }
+        total
+    }
+
+    /// ディレクトリ `dir` に含まれる全てのエントリの名前と inode 番号を返す。
+    fn raw_dir_entries(&self, dir: &Inode) -> Result<Vec<(String, u32)>> {
+        let mut entries = Vec::new();
+        for i in 0..dir.size().div_ceil(self.block_size) {
+            let block = match self.data_block(dir, i)? {
+                0 => continue,
+                n => self.block(n)?,
+            };
+
+            let mut off = 0;
+            while off + 8 <= block.len() {
+                let inode = read_u32(block, off);
+                let rec_len = read_u16(block, off + 4) as usize;
+                let name_len = block[off + 6] as usize;
+                if rec_len == 0 {
+                    break;
+                }
+                if inode != 0 {
+                    let name = block
+                        .get(off + 8..off + 8 + name_len)
+                        .ok_or(make_error!(Code::InvalidFormat))?;
+                    entries.push((String::from_utf8_lossy(name).into_owned(), inode));
+                }
+                off += rec_len;
+            }
+        }
+        Ok(entries)
+    }
+
+    fn dir_entries(&self, dir: &Inode) -> Result<Vec<DirEntryInfo>> {
+        let mut entries = Vec::new();
+        for (name, n) in self.raw_dir_entries(dir)? {
+            let inode = self.inode(n)?;
+            let attr = if inode.is_dir() {
+                Attribute::Directory as u8
+            } else {
+                0
+            };
+            entries.push(DirEntryInfo {
+                mtime: inode.mtime(),
+                ..DirEntryInfo::new(&name, attr, inode.size)
+            });
+        }
+        Ok(entries)
+    }
+
+    fn find_entry(&self, dir: &Inode, name: &str) -> Result<u32> {
+        self.raw_dir_entries(dir)?
+            .into_iter()
+            .find_map(|(n, inode)| (n == name).then_some(inode))
+            .ok_or(make_error!(Code::NoSuchEntry))
+    }
+
+    fn symlink_target(&self, inode: &Inode) -> Result<String> {
+        let mut buf = vec![0; inode.size()];
+        if inode.size < FAST_SYMLINK_MAX && inode.blocks == 0 {
+            for (chunk, b) in buf.chunks_mut(4).zip(inode.block) {
+                chunk.copy_from_slice(&b.to_le_bytes()[..chunk.len()]);
+            }
+        } else if self.read(inode, 0, &mut buf) != buf.len() {
+            return Err(make_error!(Code::InvalidFormat));
+        }
+        String::from_utf8(buf).map_err(|_| make_error!(Code::InvalidFormat))
+    }
+
+    /// ボリュームのルートからのパス `path` を辿り、その inode を返す。
+    ///
+    /// `/` から始まるシンボリックリンクは、このボリュームのルートを基準に解決する。
+    fn lookup(&self, path: &str) -> Result<Inode> {
+        // 辿ってきたディレクトリの inode 番号。`..` で戻るために使う
+        let mut dirs = vec![ROOT_INODE];
+        // まだ辿っていない要素を逆順に並べたもの
+        let mut remain: Vec<String> = path.split('/').rev().map(String::from).collect();
+        let mut follows = 0;
+        let mut current = self.inode(ROOT_INODE)?;
+
+        while let Some(elem) = remain.pop() {
+            match elem.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    if dirs.len() > 1 {
+                        dirs.pop();
+                    }
+                    current = self.inode(*dirs.last().unwrap())?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if !current.is_dir() {
+                return Err(make_error!(Code::NotDirectory));
+            }
+            let n = self.find_entry(&current, &elem)?;
+            let inode = self.inode(n)?;
+
+            if inode.is_symlink() {
+                follows += 1;
+                if follows > MAX_SYMLINK_FOLLOWS {
+                    return Err(make_error!(Code::TooManyLinks));
+                }
+                let target = self.symlink_target(&inode)?;
+                if target.starts_with('/') {
+                    dirs.truncate(1);
+                }
+                remain.extend(target.split('/').rev().map(String::from));
+                current = self.inode(*dirs.last().unwrap())?;
+                continue;
+            }
+
+            dirs.push(n);
+            current = inode;
+        }
+        Ok(current)
+    }
+}
+
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
@@ -5,25 +5,80 @@ use core::{
     mem, ptr, slice, str,
 };
 
-use alloc::vec::Vec;
+use alloc::{
+    format,
+    string::{String, ToString as _},
+    vec::Vec,
+};
 
 use crate::{
     bitfield::BitField as _,
     error::{Code, Result},
     make_error,
+    rtc::{self, DateTime},
     util::OnceStatic,
 };
 
+/// クラスタチェーンの終端を表す。
+/// FAT の種類によらず、FAT エントリの値が終端を表す場合はこの値に読み替える。
 pub const END_OF_CLUSTER_CHAIN: u64 = 0x0fff_ffff;
 
 pub static BOOT_VOLUME_IMAGE: OnceStatic<&'static BPB> = OnceStatic::new();
 pub static BYTES_PER_CLUSTER: OnceStatic<u64> = OnceStatic::new();
+pub static FAT_TYPE: OnceStatic<FatType> = OnceStatic::new();
+
+/// FAT の種類を表す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
 
-pub fn init(volume_image: *mut c_void) {
-    BOOT_VOLUME_IMAGE.init(unsafe { &*(volume_image as *const BPB) });
+impl FatType {
+    /// これ以上の FAT エントリの値はクラスタチェーンの終端を表す。
+    fn end_of_chain_min(self) -> u32 {
+        match self {
+            Self::Fat12 => 0x0ff8,
+            Self::Fat16 => 0xfff8,
+            Self::Fat32 => 0x0fff_fff8,
+        }
+    }
 
-    let image = BOOT_VOLUME_IMAGE.get();
+    /// FAT エントリとして有効なビットのマスク。
+    fn entry_mask(self) -> u32 {
+        match self {
+            Self::Fat12 => 0x0fff,
+            Self::Fat16 => 0xffff,
+            Self::Fat32 => 0x0fff_ffff,
+        }
+    }
+}
+
+/// ボリュームの先頭 `volume_image` から FAT の種類を判定し、初期化する。
+///
+/// exFAT など、FAT12/16/32 以外のボリュームの場合はエラーを返す。
+pub fn init(volume_image: *mut c_void) -> Result<()> {
+    let image = unsafe { &*(volume_image as *const BPB) };
+    if &image.oemname() == b"EXFAT   " {
+        return Err(make_error!(Code::NotImplemented, "exFAT is not supported"));
+    }
+    if image.byts_per_sec() == 0 || image.sec_per_clus() == 0 {
+        return Err(make_error!(Code::InvalidFormat));
+    }
+
+    BOOT_VOLUME_IMAGE.init(image);
     BYTES_PER_CLUSTER.init(image.byts_per_sec() as u64 * image.sec_per_clus() as u64);
+
+    // FAT の種類はクラスタ数のみで決まる
+    let fat_type = match image.count_of_clusters() {
+        0..4085 => FatType::Fat12,
+        4085..65525 => FatType::Fat16,
+        _ => FatType::Fat32,
+    };
+    FAT_TYPE.init(fat_type);
+
+    Ok(())
 }
 
 pub fn get_sector_by_cluster<T>(cluster: u64, len: usize) -> &'static mut [T] {
@@ -54,6 +109,66 @@ pub fn read_name(entry: &DirectoryEntry) -> (&str, &str) {
     }
 }
 
+/// `entry` の名前を `base.ext` の形式で返す。
+/// 拡張子がない場合は `base` だけを返す。
+pub fn format_name(entry: &DirectoryEntry) -> String {
+    let (base, ext) = read_name(entry);
+    if ext.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+/// ディレクトリに含まれるエントリを先頭から順に返すイテレータ。
+///
+/// 削除済みのエントリと LFN エントリは飛ばす。
+#[derive(Debug, Clone)]
+pub struct DirectoryIter {
+    /// 次に読むエントリを含むクラスタ。
+    cluster: u64,
+    /// `cluster` 内での次に読むエントリの位置。
+    index: usize,
+}
+
+impl DirectoryIter {
+    /// `dir_cluster` が `0` のときはルートディレクトリを走査する。
+    pub fn new(dir_cluster: u64) -> Self {
+        let cluster = if dir_cluster == 0 {
+            root_dir_cluster()
+        } else {
+            dir_cluster
+        };
+        Self { cluster, index: 0 }
+    }
+}
+
+impl Iterator for DirectoryIter {
+    type Item = &'static mut DirectoryEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cluster != END_OF_CLUSTER_CHAIN {
+            while self.index < get_dir_entries(self.cluster).len() {
+                let entry = &mut get_dir_entries(self.cluster)[self.index];
+                self.index += 1;
+
+                // ディレクトリ内の要素が終わったことを示す
+                if entry.name[0] == 0 {
+                    self.cluster = END_OF_CLUSTER_CHAIN;
+                    return None;
+                } else if entry.name[0] == 0xe5 || entry.attr == Attribute::LongName as u8 {
+                    continue;
+                }
+                return Some(entry);
+            }
+
+            self.cluster = next_dir_cluster(self.cluster);
+            self.index = 0;
+        }
+        None
+    }
+}
+
 /// `path` が絶対パスのときはルートディレクトリ、
 /// 相対パスの場合は `directory_cluster` を基準としてファイル、ディレクトリを検索する。
 /// `directory_entry` が `0` のときはルートディレクトリを基準として探索する。
@@ -67,9 +182,9 @@ pub fn find_file(
     let (rel_path, mut directory_cluster) = if path.starts_with('/') {
         // Safety: 1バイト文字の '/' が先頭で、元々正当な文字列だから大丈夫
         let path = unsafe { str::from_utf8_unchecked(&path.as_bytes()[1..]) };
-        (path, BOOT_VOLUME_IMAGE.get().root_clus() as _)
+        (path, root_dir_cluster())
     } else if directory_cluster == 0 {
-        (path, BOOT_VOLUME_IMAGE.get().root_clus() as _)
+        (path, root_dir_cluster())
     } else {
         (path, directory_cluster)
     };
@@ -83,11 +198,7 @@ pub fn find_file(
     let path_last = next_path.is_empty();
 
     'outer: while directory_cluster != END_OF_CLUSTER_CHAIN {
-        let dir = get_sector_by_cluster::<DirectoryEntry>(
-            directory_cluster,
-            BYTES_PER_CLUSTER.get() as usize / mem::size_of::<DirectoryEntry>(),
-        );
-        for file in dir {
+        for file in get_dir_entries(directory_cluster) {
             // ディレクトリ内の要素が終わったことを示す
             if file.name[0] == 0 {
                 break 'outer;
@@ -102,48 +213,122 @@ pub fn find_file(
             }
         }
 
-        directory_cluster = next_cluster(directory_cluster);
+        directory_cluster = next_dir_cluster(directory_cluster);
     }
 
     (None, post_slash)
 }
 
 pub fn create_file(path: &str) -> Result<&'static mut DirectoryEntry> {
-    let mut parent_dir_cluster = BOOT_VOLUME_IMAGE.get().root_clus() as u64;
+    let (parent_dir_cluster, filename) = split_parent(path)?;
 
-    let filename = if let Some((parent_dir_name, filename)) = path.rsplit_once('/') {
-        if filename.is_empty() {
-            return Err(make_error!(Code::IsDirectory));
-        }
+    let dir = allocate_entry(parent_dir_cluster)?;
+    set_file_name(dir, filename);
+    dir.set_created(&rtc::now());
 
-        if !parent_dir_name.is_empty() {
-            let (Some(parent_dir), _) = find_file(parent_dir_name, 0) else {
-                return Err(make_error!(Code::NoSuchEntry));
-            };
-            parent_dir_cluster = parent_dir.first_cluster() as _;
+    Ok(dir)
+}
+
+/// 絶対パス `path` に空のディレクトリを作る。
+pub fn make_dir(path: &str) -> Result<()> {
+    let path = path.trim_end_matches('/');
+    match find_file(path, 0) {
+        // 末尾の `/` は取り除いてあるので、`/` が続くのはパスの途中がディレクトリでない場合
+        (Some(_), true) => return Err(make_error!(Code::NotDirectory)),
+        (Some(_), false) => return Err(make_error!(Code::AlreadyExists)),
+        (None, _) => {}
+    }
+    let (parent_dir_cluster, name) = split_parent(path)?;
+
+    let cluster = allocate_cluster_chain(1)?;
+    get_sector_by_cluster::<u8>(cluster, BYTES_PER_CLUSTER.get() as _).fill(0);
+    let dir = match allocate_entry(parent_dir_cluster) {
+        Ok(dir) => dir,
+        Err(e) => {
+            free_cluster_chain(cluster);
+            return Err(e);
         }
-        filename
+    };
+    let now = rtc::now();
+    set_file_name(dir, name);
+    dir.attr = Attribute::Directory as _;
+    dir.set_first_cluster(cluster as _);
+    dir.set_created(&now);
+
+    // `..` はルートディレクトリを指す場合、FAT32 でもクラスタ番号を 0 にする
+    let parent = if parent_dir_cluster == root_dir_cluster() {
+        0
     } else {
-        path
+        parent_dir_cluster
     };
+    let entries = get_dir_entries(cluster);
+    let dots = [(*b".          ", cluster), (*b"..         ", parent)];
+    for (entry, (name, cluster)) in entries.iter_mut().zip(dots) {
+        entry.name = name;
+        entry.attr = Attribute::Directory as _;
+        entry.set_first_cluster(cluster as _);
+        entry.set_created(&now);
+    }
+    Ok(())
+}
 
-    let dir = allocate_entry(parent_dir_cluster);
-    set_file_name(dir, filename);
+/// 絶対パス `path` が指すファイルもしくは空のディレクトリを削除し、使っていたクラスタを解放する。
+///
+/// 削除するファイルを開いているファイルディスクリプタがあっても考慮しない。
+pub fn remove(path: &str) -> Result<()> {
+    let (Some(entry), post_slash) = find_file(path, 0) else {
+        return Err(make_error!(Code::NoSuchEntry));
+    };
+    let is_dir = entry.attr == Attribute::Directory as u8;
+    if post_slash && !is_dir {
+        return Err(make_error!(Code::NotDirectory));
+    }
+    if is_dir && DirectoryIter::new(entry.first_cluster() as _).any(|e| e.name[0] != b'.') {
+        return Err(make_error!(Code::NotEmpty));
+    }
 
-    Ok(dir)
+    free_cluster_chain(entry.first_cluster() as _);
+    entry.name[0] = 0xe5;
+    Ok(())
 }
 
-pub fn allocate_cluster_chain(n: usize) -> Result<u64> {
-    let fat = get_fat();
-    let first_cluster = 'l: {
-        for (clus, clus_fat) in fat.iter_mut().skip(2).enumerate() {
-            if *clus_fat == 0 {
-                *clus_fat = END_OF_CLUSTER_CHAIN as _;
-                break 'l clus as u64;
-            }
+/// ファイル `entry` を空にし、使っていたクラスタを解放する。
+pub fn truncate(entry: &mut DirectoryEntry) {
+    free_cluster_chain(entry.first_cluster() as _);
+    entry.set_first_cluster(0);
+    entry.file_size = 0;
+    entry.set_modified(&rtc::now());
+}
+
+/// 絶対パス `path` を、親ディレクトリのクラスタ番号と最後の要素に分ける。
+fn split_parent(path: &str) -> Result<(u64, &str)> {
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err(make_error!(Code::IsDirectory));
+    }
+    if parent.is_empty() {
+        return Ok((root_dir_cluster(), name));
+    }
+
+    match find_file(parent, 0) {
+        (Some(dir), _) if dir.attr == Attribute::Directory as u8 => {
+            // `..` を辿った場合などは、ルートディレクトリが 0 で表される
+            let cluster = match dir.first_cluster() {
+                0 => root_dir_cluster(),
+                cluster => cluster as _,
+            };
+            Ok((cluster, name))
         }
+        (Some(_), _) => Err(make_error!(Code::NotDirectory)),
+        (None, _) => Err(make_error!(Code::NoSuchEntry)),
+    }
+}
+
+pub fn allocate_cluster_chain(n: usize) -> Result<u64> {
+    let Some(first_cluster) = (2..cluster_end()).find(|&clus| fat_entry(clus) == 0) else {
         return Err(make_error!(Code::NoEnoughMemory));
     };
+    set_fat_entry(first_cluster, END_OF_CLUSTER_CHAIN as _);
 
     if n > 1 {
         extend_cluster(first_cluster, n - 1);
@@ -151,54 +336,60 @@ pub fn allocate_cluster_chain(n: usize) -> Result<u64> {
     Ok(first_cluster)
 }
 
-fn allocate_entry(mut dir_cluster: u64) -> &'static mut DirectoryEntry {
+/// `dir_cluster` が表すディレクトリの空きエントリを返す。
+/// 空きがなければディレクトリのクラスタを1つ伸ばす。
+/// 削除済みのエントリを再利用する場合は、全て 0 で埋めてから返す。
+///
+/// FAT12/16 のルートディレクトリは伸ばせないので、空きがなければエラーを返す。
+fn allocate_entry(mut dir_cluster: u64) -> Result<&'static mut DirectoryEntry> {
     loop {
-        let dir = get_sector_by_cluster::<DirectoryEntry>(
-            dir_cluster,
-            BYTES_PER_CLUSTER.get() as usize / mem::size_of::<DirectoryEntry>(),
-        );
-        for entry in dir {
+        for entry in get_dir_entries(dir_cluster) {
             if entry.name[0] == 0 || entry.name[0] == 0xe5 {
-                #[allow(clippy::missing_transmute_annotations)]
-                return unsafe { mem::transmute(entry as *const _ as usize) };
+                unsafe { ptr::write_bytes(entry as *mut DirectoryEntry, 0, 1) };
+                return Ok(entry);
             }
         }
-        dir_cluster = match next_cluster(dir_cluster) {
+        dir_cluster = match next_dir_cluster(dir_cluster) {
             END_OF_CLUSTER_CHAIN => break,
             clus => clus,
         };
     }
 
+    if dir_cluster == 0 {
+        return Err(make_error!(Code::Full, "root directory is full"));
+    }
+
     dir_cluster = extend_cluster(dir_cluster, 1);
-    let dir = get_sector_by_cluster::<DirectoryEntry>(dir_cluster, 1)
-        .first()
-        .unwrap();
+    let dir = get_sector_by_cluster::<u8>(dir_cluster, BYTES_PER_CLUSTER.get() as _);
+    dir.fill(0);
+    Ok(&mut get_dir_entries(dir_cluster)[0])
+}
 
-    unsafe {
-        #[allow(clippy::useless_transmute)]
-        let ptr: *mut u64 = mem::transmute(dir);
-        ptr::write_bytes(ptr, 0, BYTES_PER_CLUSTER.get() as usize / 8);
+/// `cluster` から始まるクラスタチェーンを全て空きに戻す。
+pub fn free_cluster_chain(mut cluster: u64) {
+    while cluster != 0 && cluster != END_OF_CLUSTER_CHAIN {
+        let next = next_cluster(cluster);
+        set_fat_entry(cluster, 0);
+        cluster = next;
     }
-    unsafe { mem::transmute(dir as *const _ as usize) }
 }
 
 pub fn extend_cluster(eoc_cluster: u64, n: usize) -> u64 {
-    let mut eoc_cluster = eoc_cluster as usize;
-    let fat = get_fat();
-    while !is_end_of_cluster_chain(fat[eoc_cluster]) {
-        eoc_cluster = fat[eoc_cluster] as _;
+    let mut eoc_cluster = eoc_cluster;
+    while next_cluster(eoc_cluster) != END_OF_CLUSTER_CHAIN {
+        eoc_cluster = next_cluster(eoc_cluster);
     }
 
     let mut num_allocated = 0;
     let mut current = eoc_cluster;
 
-    for candidate in 2..fat.len() {
+    for candidate in 2..cluster_end() {
         // candidate クラスタは既に使われている
-        if fat[candidate] != 0 {
+        if fat_entry(candidate) != 0 {
             continue;
         }
 
-        fat[current] = candidate as _;
+        set_fat_entry(current, candidate as _);
         current = candidate;
         num_allocated += 1;
 
@@ -206,8 +397,8 @@ pub fn extend_cluster(eoc_cluster: u64, n: usize) -> u64 {
             break;
         }
     }
-    fat[current] = END_OF_CLUSTER_CHAIN as _;
-    current as _
+    set_fat_entry(current, END_OF_CLUSTER_CHAIN as _);
+    current
 }
 
 pub fn set_file_name(entry: &mut DirectoryEntry, name: &str) {
@@ -235,23 +426,51 @@ pub fn set_file_name(entry: &mut DirectoryEntry, name: &str) {
 }
 
 pub fn next_cluster(cluster: u64) -> u64 {
-    let image = BOOT_VOLUME_IMAGE.get();
-    let fat_offset = image.rsvd_sec_cnt() * image.byts_per_sec();
-    let fat = unsafe {
-        &*ptr::slice_from_raw_parts(
-            (image.as_ptr().byte_add(fat_offset as usize)) as *const u32,
-            image.fat_sz32() as usize * image.byts_per_sec() as usize / mem::size_of::<u32>(),
-        )
-    };
-
-    let next = fat[cluster as usize];
-    if next >= 0x0fff_fff8 {
+    let next = fat_entry(cluster);
+    if next >= FAT_TYPE.get().end_of_chain_min() {
         END_OF_CLUSTER_CHAIN
     } else {
         next as u64
     }
 }
 
+/// ルートディレクトリを表すクラスタ番号を返す。
+///
+/// FAT12/16 のルートディレクトリはデータ領域の外にあるので、`0` で表す。
+pub fn root_dir_cluster() -> u64 {
+    match FAT_TYPE.get() {
+        FatType::Fat32 => BOOT_VOLUME_IMAGE.get().root_clus() as _,
+        FatType::Fat12 | FatType::Fat16 => 0,
+    }
+}
+
+/// `cluster` に含まれるディレクトリエントリを返す。
+/// `cluster` が `0` の場合は FAT12/16 のルートディレクトリ領域全体を返す。
+fn get_dir_entries(cluster: u64) -> &'static mut [DirectoryEntry] {
+    let entry_size = mem::size_of::<DirectoryEntry>();
+    if cluster == 0 {
+        let image = BOOT_VOLUME_IMAGE.get();
+        let offset = image.root_dir_start_sector() * image.byts_per_sec() as u64;
+        unsafe {
+            slice::from_raw_parts_mut(
+                image.as_ptr().byte_add(offset as usize) as *mut DirectoryEntry,
+                image.root_ent_cnd() as usize,
+            )
+        }
+    } else {
+        get_sector_by_cluster(cluster, BYTES_PER_CLUSTER.get() as usize / entry_size)
+    }
+}
+
+/// ディレクトリを構成するクラスタチェーンの次のクラスタを返す。
+fn next_dir_cluster(cluster: u64) -> u64 {
+    if cluster == 0 {
+        END_OF_CLUSTER_CHAIN
+    } else {
+        next_cluster(cluster)
+    }
+}
+
 pub fn load_file(entry: &DirectoryEntry) -> Vec<u8> {
     let mut cluster = entry.first_cluster() as u64;
     let mut remain_bytes = entry.file_size as _;
@@ -269,17 +488,73 @@ pub fn load_file(entry: &DirectoryEntry) -> Vec<u8> {
     buf
 }
 
-/// FAT の全体を返す。
-fn get_fat() -> &'static mut [u32] {
+/// 有効なクラスタ番号の上限（この値は含まない）を返す。
+fn cluster_end() -> u64 {
+    BOOT_VOLUME_IMAGE.get().count_of_clusters() + 2
+}
+
+/// 1つ目の FAT の先頭を指すポインタを返す。
+fn fat_head(fat_index: u64) -> *mut u8 {
     let image = BOOT_VOLUME_IMAGE.get();
-    let head = image.as_ptr() as usize + (image.rsvd_sec_cnt * image.byts_per_sec) as usize;
-    let len = (image.fat_sz32 * image.byts_per_sec as u32) / 4;
+    let sector = image.rsvd_sec_cnt() as u64 + fat_index * image.fat_sz();
+    unsafe { (image.as_ptr() as *mut u8).byte_add((sector * image.byts_per_sec() as u64) as usize) }
+}
 
-    unsafe { slice::from_raw_parts_mut(head as _, len as _) }
+/// `cluster` に対応する FAT エントリの値を返す。
+fn fat_entry(cluster: u64) -> u32 {
+    let head = fat_head(0);
+    let fat_type = FAT_TYPE.get();
+    let value = unsafe {
+        match fat_type {
+            FatType::Fat12 => {
+                let v = ptr::read_unaligned(head.add((cluster + cluster / 2) as _) as *const u16);
+                if cluster.is_multiple_of(2) {
+                    v as u32 & 0x0fff
+                } else {
+                    v as u32 >> 4
+                }
+            }
+            FatType::Fat16 => {
+                ptr::read_unaligned(head.add(cluster as usize * 2) as *const u16) as _
+            }
+            FatType::Fat32 => ptr::read_unaligned(head.add(cluster as usize * 4) as *const u32),
+        }
+    };
+    value & fat_type.entry_mask()
 }
 
-fn is_end_of_cluster_chain(clus: u32) -> bool {
-    clus >= 0x00FF_FFF8
+/// `cluster` に対応する FAT エントリに `value` を書き込む。
+/// 他のツールとの互換性のため、全ての FAT に同じ値を書き込む。
+fn set_fat_entry(cluster: u64, value: u32) {
+    let fat_type = FAT_TYPE.get();
+    let value = value & fat_type.entry_mask();
+
+    for i in 0..BOOT_VOLUME_IMAGE.get().num_fats() as u64 {
+        let head = fat_head(i);
+        unsafe {
+            match fat_type {
+                FatType::Fat12 => {
+                    let p = head.add((cluster + cluster / 2) as _) as *mut u16;
+                    let old = ptr::read_unaligned(p);
+                    let new = if cluster.is_multiple_of(2) {
+                        (old & 0xf000) | value as u16
+                    } else {
+                        (old & 0x000f) | (value as u16) << 4
+                    };
+                    ptr::write_unaligned(p, new);
+                }
+                FatType::Fat16 => {
+                    ptr::write_unaligned(head.add(cluster as usize * 2) as *mut u16, value as _)
+                }
+                FatType::Fat32 => {
+                    // 上位4ビットは予約されているので保持する
+                    let p = head.add(cluster as usize * 4) as *mut u32;
+                    let old = ptr::read_unaligned(p);
+                    ptr::write_unaligned(p, (old & !fat_type.entry_mask()) | value);
+                }
+            }
+        }
+    }
 }
 
 #[repr(packed)]
@@ -399,6 +674,44 @@ impl BPB {
     pub fn as_ptr(&self) -> *const Self {
         self as *const _
     }
+
+    /// 1つの FAT が占めるセクタ数を返す。
+    pub fn fat_sz(&self) -> u64 {
+        match self.fat_sz16() {
+            0 => self.fat_sz32() as _,
+            sz => sz as _,
+        }
+    }
+
+    /// ボリューム全体のセクタ数を返す。
+    pub fn tot_sec(&self) -> u64 {
+        match self.tot_sec16() {
+            0 => self.tot_sec32() as _,
+            sec => sec as _,
+        }
+    }
+
+    /// ルートディレクトリ領域が占めるセクタ数を返す。
+    /// FAT32 の場合は `0` になる。
+    pub fn root_dir_sectors(&self) -> u64 {
+        let byts_per_sec = self.byts_per_sec() as u64;
+        (self.root_ent_cnd() as u64 * 32).div_ceil(byts_per_sec)
+    }
+
+    /// ルートディレクトリ領域の先頭セクタを返す。
+    pub fn root_dir_start_sector(&self) -> u64 {
+        self.rsvd_sec_cnt() as u64 + self.num_fats() as u64 * self.fat_sz()
+    }
+
+    /// データ領域の先頭セクタを返す。
+    pub fn data_start_sector(&self) -> u64 {
+        self.root_dir_start_sector() + self.root_dir_sectors()
+    }
+
+    /// データ領域のクラスタ数を返す。
+    pub fn count_of_clusters(&self) -> u64 {
+        (self.tot_sec() - self.data_start_sector()) / self.sec_per_clus() as u64
+    }
 }
 
 #[repr(C)]
@@ -427,6 +740,35 @@ impl DirectoryEntry {
         self.fst_clus_hl = clus.get_bits(16..) as _;
     }
 
+    /// 作成日時を `t` にする。更新日時とアクセス日も同じにする。
+    pub fn set_created(&mut self, t: &DateTime) {
+        // 10 ms 単位で、2 秒単位の crt_time に収まらない端数の秒を表す
+        self.crt_time_tenth = t.second % 2 * 100;
+        self.crt_time = t.fat_time();
+        self.crt_date = t.fat_date();
+        self.set_modified(t);
+    }
+
+    /// 更新日時を `t` にする。アクセス日も同じにする。
+    pub fn set_modified(&mut self, t: &DateTime) {
+        self.wrt_time = t.fat_time();
+        self.wrt_date = t.fat_date();
+        self.set_accessed(t);
+    }
+
+    pub fn set_accessed(&mut self, t: &DateTime) {
+        self.lst_acc_date = t.fat_date();
+    }
+
+    /// 更新日時を返す。記録されていない場合は `None` を返す。
+    pub fn modified(&self) -> Option<DateTime> {
+        if self.wrt_date == 0 {
+            None
+        } else {
+            Some(DateTime::from_fat(self.wrt_date, self.wrt_time))
+        }
+    }
+
     fn name_is_equal(&self, name: &str) -> bool {
         // `name` を名前と拡張子に分割
         let (base, ext) = match name.rsplit_once('.') {
@@ -481,9 +823,7 @@ pub enum Attribute {
 
 fn get_cluster_addr(cluster: u64) -> *const u32 {
     let image = BOOT_VOLUME_IMAGE.get();
-    let sector_num = image.rsvd_sec_cnt() as u64
-        + image.num_fats() as u64 * image.fat_sz32() as u64
-        + (cluster - 2) * image.sec_per_clus() as u64;
+    let sector_num = image.data_start_sector() + (cluster - 2) * image.sec_per_clus() as u64;
     let offset = sector_num * image.byts_per_sec() as u64;
 
     unsafe { (image.as_ptr() as *const u32).byte_add(offset as usize) }
@@ -3,16 +3,19 @@ use core::{
     ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not},
 };
 
-use alloc::{string::String, sync::Arc};
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
 
 use crate::{
-    bitfield::BitField,
-    error::Result,
-    fat::{self, DirectoryEntry, BYTES_PER_CLUSTER, END_OF_CLUSTER_CHAIN},
-    keyboard::{LCONTROL_BIT, RCONTROL_BIT},
-    message::MessageType,
+    devfs::Device,
+    error::{Code, Result},
+    ext2::{self, Inode},
+    fat::{self, DirectoryEntry, DirectoryIter, BYTES_PER_CLUSTER, END_OF_CLUSTER_CHAIN},
+    make_error,
+    pipe::{self, Fifo, PipeReader, PipeWriter},
+    rtc,
     task::Task,
     terminal::TerminalRef,
+    tmpfs::FileData,
 };
 
 pub struct FileDescriptor {
@@ -35,27 +38,88 @@ impl FileDescriptor {
         }
     }
 
-    pub fn new_term(task: Arc<Task>, term: TerminalRef) -> Self {
+    /// `dir_cluster` が `0` のときはルートディレクトリを指す。
+    pub fn new_dir(dir_cluster: u64) -> Self {
         Self {
-            inner: InnerFileDescriptor::Terminal { task, term },
+            inner: InnerFileDescriptor::Dir {
+                iter: DirectoryIter::new(dir_cluster),
+            },
         }
     }
 
-    pub fn new_pipe(task: Arc<Task>) -> Self {
+    /// ファイルシステム上に実体を持たないディレクトリを、そのエントリの一覧 `entries` から作る。
+    pub fn new_virtual_dir(entries: Vec<DirEntryInfo>) -> Self {
         Self {
-            inner: InnerFileDescriptor::Pipe {
-                task,
-                data: [0; 16],
-                len: 0,
-                closed: false,
+            inner: InnerFileDescriptor::VirtualDir {
+                entries: entries.into(),
             },
         }
     }
 
+    /// 開いた時点の内容 `data` を読み込み専用で読み出せるファイルを作る。
+    pub fn new_snapshot(data: Vec<u8>) -> Self {
+        Self {
+            inner: InnerFileDescriptor::Snapshot { data, off: 0 },
+        }
+    }
+
+    pub fn new_tmp(data: FileData) -> Self {
+        Self {
+            inner: InnerFileDescriptor::Tmp {
+                data,
+                rd_off: 0,
+                wr_off: 0,
+            },
+        }
+    }
+
+    pub fn new_ext2(inode: Inode) -> Self {
+        Self {
+            inner: InnerFileDescriptor::Ext2 { inode, off: 0 },
+        }
+    }
+
+    pub fn new_device(dev: Device) -> Self {
+        Self {
+            inner: InnerFileDescriptor::Device { dev, off: 0 },
+        }
+    }
+
+    pub fn new_term(task: Arc<Task>, term: TerminalRef) -> Self {
+        Self {
+            inner: InnerFileDescriptor::Terminal { task, term },
+        }
+    }
+
+    /// 新しいパイプを作り、その読み込み側と書き込み側を返す。
+    pub fn new_pipe() -> (Self, Self) {
+        let (reader, writer) = pipe::new();
+        (
+            Self {
+                inner: InnerFileDescriptor::PipeReader(reader),
+            },
+            Self {
+                inner: InnerFileDescriptor::PipeWriter(writer),
+            },
+        )
+    }
+
+    /// FIFO `fifo` を `flags` に従って読み込み側もしくは書き込み側として開く。
+    ///
+    /// 相手側が開かれるまでブロックする。読み書き両用では開けない。
+    pub fn new_fifo(fifo: &Fifo, flags: FileFlags) -> Result<Self> {
+        let inner = match flags & FileFlags::ACCMODE {
+            FileFlags::RDONLY => InnerFileDescriptor::PipeReader(fifo.open_reader()),
+            FileFlags::WRONLY => InnerFileDescriptor::PipeWriter(fifo.open_writer()),
+            _ => return Err(make_error!(Code::InvalidFile)),
+        };
+        Ok(Self { inner })
+    }
+
     pub fn read(&mut self, buf: &mut [u8]) -> usize {
         match self.inner {
             InnerFileDescriptor::Fat {
-                ref fat_entry,
+                ref mut fat_entry,
                 ref mut rd_off,
                 ref mut rd_cluster,
                 ref mut rd_cluster_off,
@@ -79,106 +143,52 @@ impl FileDescriptor {
                 }
 
                 *rd_off += total;
+                fat_entry.set_accessed(&rtc::now());
                 total
             }
             InnerFileDescriptor::Terminal {
                 ref task,
                 ref mut term,
+            } => term.read_input(task, buf),
+            InnerFileDescriptor::PipeReader(ref reader) => reader.read(buf),
+            InnerFileDescriptor::PipeWriter(_) => 0,
+            InnerFileDescriptor::Device {
+                ref dev,
+                ref mut off,
             } => {
-                loop {
-                    // Task::recieve_message は Mutex でガードされているので、
-                    // 割り込みは禁止しなくて良い
-                    let msg = match task.receive_message() {
-                        Some(m) => m,
-                        None => {
-                            task.sleep();
-                            continue;
-                        }
-                    };
-                    if let MessageType::KeyPush {
-                        ascii,
-                        press,
-                        modifier,
-                        keycode,
-                    } = msg.ty
-                    {
-                        if !press {
-                            continue;
-                        }
-                        if modifier.get_bit(LCONTROL_BIT) | modifier.get_bit(RCONTROL_BIT) {
-                            let mut s = [b'^', 0];
-                            s[1] = ascii.to_ascii_uppercase();
-                            // Safety: キーボードから入力できる文字と ^ から構成されている
-                            let s = unsafe { core::str::from_utf8_unchecked(&s) };
-                            term.print(s);
-                            // D
-                            if keycode == 7 {
-                                // EOT
-                                return 0;
-                            }
-                            continue;
-                        }
-
-                        buf[0] = ascii;
-                        // Safety: キーボードから入力できる文字から構成されている
-                        let buf = unsafe { core::str::from_utf8_unchecked(&buf[..1]) };
-                        term.print(buf);
-                        term.redraw();
-                        return 1;
-                    }
-                }
+                let len = dev.read(buf, *off);
+                *off += len;
+                len
             }
-            InnerFileDescriptor::Pipe {
-                ref task,
-                ref mut data,
-                ref mut len,
-                ref mut closed,
+            InnerFileDescriptor::Snapshot {
+                ref data,
+                ref mut off,
             } => {
-                // パイプ内に溜まっているデータがあればそれを渡す
-                if *len > 0 {
-                    let copy_bytes = cmp::min(buf.len(), *len);
-                    buf[..copy_bytes].copy_from_slice(&data[..copy_bytes]);
-                    *len -= copy_bytes;
-                    let (dest, src) = data.split_at_mut(*len);
-                    dest.copy_from_slice(&src[..*len]);
-                    return copy_bytes;
-                }
-                // 溜まっているデータの処理を終えて closed なら終了
-                if *closed {
-                    return 0;
-                }
-
-                // パイプ内にデータは無いので、Pipe メッセージを受け取るのを待つ
-                let msg = loop {
-                    if let Some(msg) = task.receive_message() {
-                        break msg;
-                    } else {
-                        task.sleep();
-                        continue;
-                    }
-                };
-
-                if let MessageType::Pipe {
-                    data: received_data,
-                    len: received_len,
-                } = msg.ty
-                {
-                    // Pipe メッセージ長が 0 の場合はこのパイプは閉じられたので終了
-                    if received_len == 0 {
-                        *closed = true;
-                        return 0;
-                    }
-                    // それ以外の場合は、buf が保持できる分は渡し、残りは自分に溜めておいて返す
-                    let received_len = received_len as _;
-                    let copy_bytes = cmp::min(received_len, buf.len());
-                    buf[..copy_bytes].copy_from_slice(&received_data[..copy_bytes]);
-                    *len = received_len - copy_bytes;
-                    data[..*len].copy_from_slice(&received_data[copy_bytes..copy_bytes + *len]);
-                    copy_bytes
-                } else {
-                    0
-                }
+                let len = cmp::min(buf.len(), data.len() - *off);
+                buf[..len].copy_from_slice(&data[*off..*off + len]);
+                *off += len;
+                len
+            }
+            InnerFileDescriptor::Tmp {
+                ref data,
+                ref mut rd_off,
+                ..
+            } => {
+                let data = data.lock_wait();
+                let len = cmp::min(buf.len(), data.len().saturating_sub(*rd_off));
+                buf[..len].copy_from_slice(&data[*rd_off..*rd_off + len]);
+                *rd_off += len;
+                len
             }
+            InnerFileDescriptor::Ext2 {
+                ref inode,
+                ref mut off,
+            } => {
+                let len = ext2::read(inode, *off, buf);
+                *off += len;
+                len
+            }
+            InnerFileDescriptor::Dir { .. } | InnerFileDescriptor::VirtualDir { .. } => 0,
         }
     }
 
@@ -225,6 +235,7 @@ impl FileDescriptor {
 
                 *wr_off += buf.len();
                 fat_entry.file_size = *wr_off as _;
+                fat_entry.set_modified(&rtc::now());
                 Ok(total)
             }
             InnerFileDescriptor::Terminal { ref mut term, .. } => {
@@ -233,28 +244,104 @@ impl FileDescriptor {
                 term.redraw();
                 Ok(buf.len())
             }
-            InnerFileDescriptor::Pipe { ref task, .. } => {
-                let mut sent_bytes = 0;
-                while sent_bytes < buf.len() {
-                    let mut data = [0; 16];
-                    let len = cmp::min(buf.len() - sent_bytes, data.len());
-                    data[..len].copy_from_slice(&buf[sent_bytes..sent_bytes + len]);
-                    let msg = MessageType::Pipe {
-                        data,
-                        len: len as _,
-                    }
-                    .into();
-                    sent_bytes += len;
-                    task.send_message(msg);
+            InnerFileDescriptor::PipeWriter(ref writer) => writer.write(buf),
+            InnerFileDescriptor::PipeReader(_) => Err(make_error!(Code::InvalidFile)),
+            InnerFileDescriptor::Device {
+                ref dev,
+                ref mut off,
+            } => {
+                let len = dev.write(buf, *off)?;
+                *off += len;
+                Ok(len)
+            }
+            InnerFileDescriptor::Tmp {
+                ref data,
+                ref mut wr_off,
+                ..
+            } => {
+                let mut data = data.lock_wait();
+                let end = *wr_off + buf.len();
+                if data.len() < end {
+                    data.resize(end, 0);
                 }
+                data[*wr_off..end].copy_from_slice(buf);
+                *wr_off = end;
                 Ok(buf.len())
             }
+            InnerFileDescriptor::Snapshot { .. } | InnerFileDescriptor::Ext2 { .. } => {
+                Err(make_error!(Code::ReadOnly))
+            }
+            InnerFileDescriptor::Dir { .. } | InnerFileDescriptor::VirtualDir { .. } => {
+                Err(make_error!(Code::IsDirectory))
+            }
+        }
+    }
+
+    /// ディレクトリ内のエントリを `entries` に読み込み、読み込んだ数を返す。
+    /// 全て読み終わっている場合は `0` を返す。
+    ///
+    /// ディレクトリでない場合はエラーを返す。
+    pub fn read_dir(&mut self, entries: &mut [DirEntryInfo]) -> Result<usize> {
+        let mut count = 0;
+        match self.inner {
+            InnerFileDescriptor::Dir { ref mut iter } => {
+                for (info, entry) in entries.iter_mut().zip(iter) {
+                    *info = DirEntryInfo::from_fat(entry);
+                    count += 1;
+                }
+            }
+            InnerFileDescriptor::VirtualDir {
+                entries: ref mut remain,
+            } => {
+                for (info, entry) in entries.iter_mut().zip(remain.drain(..)) {
+                    *info = entry;
+                    count += 1;
+                }
+            }
+            _ => return Err(make_error!(Code::NotDirectory)),
         }
+        Ok(count)
+    }
+
+    /// ファイルディスクリプタの種類を表す名前を返す。
+    pub fn kind(&self) -> &'static str {
+        match self.inner {
+            InnerFileDescriptor::Fat { .. }
+            | InnerFileDescriptor::Tmp { .. }
+            | InnerFileDescriptor::Ext2 { .. } => "file",
+            InnerFileDescriptor::Terminal { .. } => "terminal",
+            InnerFileDescriptor::PipeReader(_) | InnerFileDescriptor::PipeWriter(_) => "pipe",
+            InnerFileDescriptor::Dir { .. } | InnerFileDescriptor::VirtualDir { .. } => "dir",
+            InnerFileDescriptor::Device { .. } => "device",
+            InnerFileDescriptor::Snapshot { .. } => "snapshot",
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        matches!(
+            self.inner,
+            InnerFileDescriptor::Dir { .. } | InnerFileDescriptor::VirtualDir { .. }
+        )
     }
 
     pub fn size(&self) -> usize {
         match self.inner {
             InnerFileDescriptor::Fat { ref fat_entry, .. } => fat_entry.file_size as _,
+            InnerFileDescriptor::Device { ref dev, .. } => dev.size(),
+            InnerFileDescriptor::Snapshot { ref data, .. } => data.len(),
+            InnerFileDescriptor::Tmp { ref data, .. } => data.lock_wait().len(),
+            InnerFileDescriptor::Ext2 { ref inode, .. } => inode.size(),
+            _ => 0,
+        }
+    }
+
+    /// 最終更新日時を UNIX 時間で返す。不明な場合は `0` を返す。
+    pub fn mtime(&self) -> u64 {
+        match self.inner {
+            InnerFileDescriptor::Fat { ref fat_entry, .. } => {
+                fat_entry.modified().map_or(0, |t| t.to_unix())
+            }
+            InnerFileDescriptor::Ext2 { ref inode, .. } => inode.mtime(),
             _ => 0,
         }
     }
@@ -288,24 +375,75 @@ impl FileDescriptor {
                 let mut fd = Self { inner };
                 fd.read(buf)
             }
+            InnerFileDescriptor::Tmp { ref data, .. } => {
+                let data = data.lock_wait();
+                let len = cmp::min(buf.len(), data.len().saturating_sub(offset));
+                buf[..len].copy_from_slice(&data[offset..offset + len]);
+                len
+            }
+            InnerFileDescriptor::Ext2 { ref inode, .. } => ext2::read(inode, offset, buf),
             _ => 0,
         }
     }
 
-    pub fn set_terminal(&mut self, terminal: TerminalRef) {
-        if let InnerFileDescriptor::Terminal { ref mut term, .. } = self.inner {
-            *term = terminal;
+    /// 書き込みのオフセットをファイルの末尾に移動する。
+    pub fn seek_end(&mut self) {
+        match self.inner {
+            InnerFileDescriptor::Fat {
+                ref fat_entry,
+                ref mut wr_off,
+                ref mut wr_cluster,
+                ref mut wr_cluster_off,
+                ..
+            } => {
+                let bytes_per_cluster = BYTES_PER_CLUSTER.get() as usize;
+                *wr_off = fat_entry.file_size as _;
+
+                // クラスタが割り当てられていない空のファイルは、そのまま先頭から書けばよい
+                let mut cluster = fat_entry.first_cluster() as u64;
+                if cluster == 0 {
+                    return;
+                }
+                // 末尾がクラスタ境界にある場合は、次の書き込みで新しいクラスタに進む
+                let mut off = *wr_off;
+                while off > bytes_per_cluster {
+                    off -= bytes_per_cluster;
+                    cluster = fat::next_cluster(cluster);
+                }
+                *wr_cluster = cluster;
+                *wr_cluster_off = off;
+            }
+            InnerFileDescriptor::Tmp {
+                ref data,
+                ref mut wr_off,
+                ..
+            } => *wr_off = data.lock_wait().len(),
+            _ => {}
         }
     }
 
-    pub fn finish_write(&self) {
-        if let InnerFileDescriptor::Pipe { ref task, .. } = self.inner {
-            let msg = MessageType::Pipe {
-                data: [0; 16],
-                len: 0,
-            }
-            .into();
-            task.send_message(msg);
+    /// ターミナルを指している場合は、そのターミナルを返す。
+    pub fn terminal(&self) -> Option<TerminalRef> {
+        match self.inner {
+            InnerFileDescriptor::Terminal { term, .. } => Some(term),
+            _ => None,
+        }
+    }
+
+    /// `/dev/tty<id>` を指している場合は、そのターミナルを持つタスクの ID を返す。
+    pub fn tty_id(&self) -> Option<u64> {
+        match self.inner {
+            InnerFileDescriptor::Device {
+                dev: Device::Terminal(id),
+                ..
+            } => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn set_terminal(&mut self, terminal: TerminalRef) {
+        if let InnerFileDescriptor::Terminal { ref mut term, .. } = self.inner {
+            *term = terminal;
         }
     }
 }
@@ -331,14 +469,98 @@ enum InnerFileDescriptor {
         task: Arc<Task>,
         term: TerminalRef,
     },
-    Pipe {
-        task: Arc<Task>,
-        data: [u8; 16],
-        len: usize,
-        closed: bool,
+    PipeReader(PipeReader),
+    PipeWriter(PipeWriter),
+    Dir {
+        /// 次に読み込むディレクトリエントリの位置。
+        iter: DirectoryIter,
+    },
+    VirtualDir {
+        /// まだ読み込まれていないエントリ。
+        entries: VecDeque<DirEntryInfo>,
+    },
+    Device {
+        dev: Device,
+        /// デバイス先頭からの読み書きのオフセット。
+        off: usize,
+    },
+    Tmp {
+        data: FileData,
+        /// 読み込みのオフセット。
+        rd_off: usize,
+        /// 書き込みのオフセット。
+        wr_off: usize,
+    },
+    Snapshot {
+        data: Vec<u8>,
+        /// 読み込みのオフセット。
+        off: usize,
+    },
+    Ext2 {
+        inode: Inode,
+        /// 読み込みのオフセット。
+        off: usize,
     },
 }
 
+/// ディレクトリエントリの名前の最大長（ヌル文字を含む）。
+pub const DIR_ENTRY_NAME_MAX: usize = 256;
+
+/// FIFO を表すファイル属性。
+/// FAT のディスク上では使われないビットを流用する。
+pub const ATTR_FIFO: u8 = 0x40;
+
+/// `read_dir` システムコールでアプリに渡すディレクトリエントリの情報。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DirEntryInfo {
+    /// ヌル終端されたファイル名。
+    pub name: [u8; DIR_ENTRY_NAME_MAX],
+    /// ファイル属性（[fat::Attribute] の組み合わせ）。
+    pub attr: u8,
+    /// ファイルサイズ（バイト）。
+    pub size: u64,
+    /// ファイルの先頭クラスタ番号。
+    pub first_cluster: u64,
+    /// 最終更新日時（UNIX 時間）。不明な場合は `0`。
+    pub mtime: u64,
+}
+
+impl DirEntryInfo {
+    /// `name` が長すぎる場合は切り詰める。
+    pub fn new(name: &str, attr: u8, size: u64) -> Self {
+        let mut buf = [0; DIR_ENTRY_NAME_MAX];
+        let len = cmp::min(name.len(), DIR_ENTRY_NAME_MAX - 1);
+        buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+        Self {
+            name: buf,
+            attr,
+            size,
+            first_cluster: 0,
+            mtime: 0,
+        }
+    }
+
+    pub fn from_fat(entry: &DirectoryEntry) -> Self {
+        Self {
+            first_cluster: entry.first_cluster() as _,
+            mtime: entry.modified().map_or(0, |t| t.to_unix()),
+            ..Self::new(&fat::format_name(entry), entry.attr, entry.file_size as _)
+        }
+    }
+
+    /// ヌル文字の手前までの名前を返す。
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(DIR_ENTRY_NAME_MAX);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FileFlags(i32);
 
@@ -352,6 +574,8 @@ impl FileFlags {
     pub const WRONLY: Self = Self(1);
     pub const RDWR: Self = Self(2);
     pub const CREAT: Self = Self(0o100);
+    pub const TRUNC: Self = Self(0o1000);
+    pub const APPEND: Self = Self(0o2000);
 }
 
 impl From<FileFlags> for i32 {
@@ -4,8 +4,8 @@ use crate::{
     error::{Code, Result},
     frame_buffer_config::{FrameBufferConfig, PixelFormat},
     graphics::{
-        BgrResv8BitPerColorPixelWriter, PixelWrite, Rectangle, RgbResv8BitPerColorPixelWriter,
-        Vector2D,
+        BgrResv8BitPerColorPixelWriter, PixelColor, PixelWrite, Rectangle,
+        RgbResv8BitPerColorPixelWriter, Vector2D,
     },
     make_error,
 };
@@ -137,6 +137,35 @@ impl FrameBuffer {
         Ok(())
     }
 
+    /// 指定された位置のピクセルの色を返す。範囲外の場合は `None`。
+    pub fn read(&self, pos: Vector2D<i32>) -> Option<PixelColor> {
+        if !(0..self.horizontal_resolution() as i32).contains(&pos.x())
+            || !(0..self.vertical_resolution() as i32).contains(&pos.y())
+        {
+            return None;
+        }
+
+        let config = FrameBufferConfig {
+            frame_buffer: self.frame_buffer(),
+            pixels_per_scan_line: self.pixels_per_scan_line(),
+            horizontal_resolution: self.horizontal_resolution(),
+            vertical_resolution: self.vertical_resolution(),
+            pixel_format: self.pixel_format,
+        };
+        let pixel = unsafe { core::slice::from_raw_parts(frame_addr_at(pos, &config), 3) };
+        Some(match self.pixel_format {
+            PixelFormat::Rgb => PixelColor::new(pixel[0], pixel[1], pixel[2]),
+            PixelFormat::Bgr => PixelColor::new(pixel[2], pixel[1], pixel[0]),
+        })
+    }
+
+    /// 指定された位置のピクセルに、`color` を不透明度 `alpha` で重ねる。
+    pub fn blend(&mut self, pos: Vector2D<i32>, color: &PixelColor, alpha: u8) {
+        if let Some(dst) = self.read(pos) {
+            self.writer.write(pos, &color.blend(&dst, alpha));
+        }
+    }
+
     pub fn r#move(&mut self, dst_pos: Vector2D<i32>, src: &Rectangle<i32>) {
         use core::ptr::copy_nonoverlapping;
 
@@ -225,7 +254,7 @@ fn bytes_per_pixel(format: &PixelFormat) -> usize {
     }
 }
 
-fn bytes_per_scan_line(config: &FrameBufferConfig) -> usize {
+pub fn bytes_per_scan_line(config: &FrameBufferConfig) -> usize {
     bytes_per_pixel(&config.pixel_format) * config.pixels_per_scan_line
 }
 
@@ -1,5 +1,5 @@
 use core::{
-    ops::{Add, AddAssign, BitAnd, Sub, SubAssign},
+    ops::{Add, AddAssign, BitAnd, BitOr, Sub, SubAssign},
     slice,
 };
 
@@ -8,30 +8,72 @@ use crate::{console::DESKTOP_BG_COLOR, frame_buffer_config::FrameBufferConfig, u
 /// フレームバッファ情報。
 pub static FB_CONFIG: OnceStatic<FrameBufferConfig> = OnceStatic::new();
 
-#[derive(PartialEq, Eq, Clone, Default, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub struct PixelColor {
     r: u8,
     g: u8,
     b: u8,
+    /// 不透明度。`0` で完全に透明、`255` で不透明。
+    a: u8,
 }
 
 /// ピクセルの色情報を持つ。
 impl PixelColor {
-    /// 初期化。
+    /// 初期化。不透明な色になる。
     pub const fn new(r: u8, g: u8, b: u8) -> Self {
-        Self { r, g, b }
+        Self { r, g, b, a: 255 }
     }
 
-    /// 32 bit 情報から [PixelColor] へ変換する。
+    /// 32 bit 情報から [PixelColor] へ変換する。不透明な色になる。
     pub fn to_color(c: u32) -> Self {
+        Self::new((c >> 16) as u8, (c >> 8) as u8, c as u8)
+    }
+
+    /// 不透明度を `a` に変えた色を返す。
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    /// 不透明度を返す。
+    pub fn alpha(&self) -> u8 {
+        self.a
+    }
+
+    /// `dst` の上に自身を不透明度 `alpha` で重ねた色を返す。結果は不透明になる。
+    pub fn blend(&self, dst: &PixelColor, alpha: u8) -> Self {
+        let mix = |src: u8, dst: u8| {
+            ((src as u32 * alpha as u32 + dst as u32 * (255 - alpha as u32)) / 255) as u8
+        };
+        Self::new(mix(self.r, dst.r), mix(self.g, dst.g), mix(self.b, dst.b))
+    }
+
+    /// `dst` の上に自身を自身の不透明度で重ねた色を返す (source-over)。
+    ///
+    /// 結果の不透明度は 2 つを合わせたものになる。`dst` が不透明なら [PixelColor::blend] と同じ色になる。
+    pub fn over(&self, dst: &PixelColor) -> Self {
+        let src_a = self.a as u32;
+        let dst_a = dst.a as u32 * (255 - src_a) / 255;
+        let a = src_a + dst_a;
+        if a == 0 {
+            return self.with_alpha(0);
+        }
+        let mix = |src: u8, dst: u8| ((src as u32 * src_a + dst as u32 * dst_a) / a) as u8;
         Self {
-            r: (c >> 16) as u8,
-            g: (c >> 8) as u8,
-            b: c as u8,
+            r: mix(self.r, dst.r),
+            g: mix(self.g, dst.g),
+            b: mix(self.b, dst.b),
+            a: a as u8,
         }
     }
 }
 
+impl Default for PixelColor {
+    /// 不透明な黒。
+    fn default() -> Self {
+        Self::new(0, 0, 0)
+    }
+}
+
 /// ピクセルを塗るための色々を提供する。
 pub trait PixelWrite {
     /// ピクセルを塗る手段を提供する。
@@ -330,33 +372,42 @@ where
     }
 }
 
+/// 両方を含む最小の長方形を返す。
+impl<T> BitOr for Rectangle<T>
+where
+    T: Add<Output = T> + Sub<Output = T> + Ord + Copy,
+{
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        use core::cmp::{max, min};
+
+        let self_end = self.pos + self.size;
+        let rhs_end = rhs.pos + rhs.size;
+
+        let new_pos = Vector2D {
+            x: min(self.pos.x, rhs.pos.x),
+            y: min(self.pos.y, rhs.pos.y),
+        };
+        let new_end = Vector2D {
+            x: max(self_end.x, rhs_end.x),
+            y: max(self_end.y, rhs_end.y),
+        };
+        Self {
+            pos: new_pos,
+            size: new_end - new_pos,
+        }
+    }
+}
+
 /// デスクトップ背景を描画する。
 pub fn draw_desktop(writer: &mut dyn PixelWrite) {
     let frame_width = writer.horizontal_resolution() as i32;
     let frame_height = writer.vertical_resolution() as i32;
 
-    // デスクトップ背景の描画
+    // デスクトップ背景の描画。タスクバーは taskbar モジュールが別のレイヤーとして描く
     writer.fill_rectangle(
         Vector2D::new(0, 0),
-        Vector2D::new(frame_width, frame_height - 50),
+        Vector2D::new(frame_width, frame_height),
         &DESKTOP_BG_COLOR,
     );
-    // タスクバーの表示
-    writer.fill_rectangle(
-        Vector2D::new(0, frame_height - 50),
-        Vector2D::new(frame_width, 50),
-        &PixelColor::new(1, 8, 17),
-    );
-    // （多分）Windows の検索窓
-    writer.fill_rectangle(
-        Vector2D::new(0, frame_height - 50),
-        Vector2D::new(frame_width / 5, 50),
-        &PixelColor::new(80, 80, 80),
-    );
-    // （多分）Windows のスタートボタン
-    writer.fill_rectangle(
-        Vector2D::new(10, frame_height - 40),
-        Vector2D::new(30, 30),
-        &PixelColor::new(160, 160, 160),
-    );
 }
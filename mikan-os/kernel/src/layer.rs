@@ -1,25 +1,43 @@
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::mem;
 
 use crate::{
-    asmfunc,
+    asmfunc, bga,
     error::{Code, Result},
-    frame_buffer::FrameBuffer,
+    frame_buffer::{self, FrameBuffer},
     frame_buffer_config::{FrameBufferConfig, PixelFormat},
-    graphics::{self, PixelWrite as _, Rectangle, Vector2D, FB_CONFIG},
+    graphics::{self, PixelColor, PixelWrite as _, Rectangle, Vector2D, FB_CONFIG},
     make_error,
     message::{LayerOperation, MessageType},
     sync::{Mutex, OnceMutex, SharedLock},
     task,
+    timer::TIMER_FREQ,
     window::Window,
 };
 
 pub static LAYER_MANAGER: OnceMutex<LayerManager> = OnceMutex::new();
 
 /// 本当のフレームバッファを表す `FrameBuffer`。
+///
+/// ページフリップを使う場合は、その時点で表示している側のページを指す。
+/// 直接描き込んだ内容は、次にページを切り替えたときに見えなくなる。
 pub static SCREEN: OnceMutex<FrameBuffer> = OnceMutex::new();
 
 pub static LAYER_TASK_MAP: Mutex<BTreeMap<u32, u64>> = Mutex::new(BTreeMap::new());
 
+/// 画面を合成する間隔（タイマーのティック数）。約 50 fps になる。
+pub const FRAME_INTERVAL: u64 = TIMER_FREQ / 50;
+
+/// トップレベルウィンドウの影をずらす量。
+const SHADOW_OFFSET: i32 = 6;
+/// 影の縁をぼかす幅。
+const SHADOW_BLUR: i32 = 6;
+/// 影の最も濃い部分の不透明度。
+const SHADOW_ALPHA: u32 = 96;
+
+/// 1 フレームの間に記録しておく、描き直す領域の数の上限。
+const MAX_DAMAGE_RECTS: usize = 16;
+
 pub fn init() {
     let fb_config = FB_CONFIG.as_ref().clone();
     let frame_width = fb_config.horizontal_resolution as u32;
@@ -72,18 +90,27 @@ pub fn close_layer(layer_id: u32) -> Result<()> {
         return Err(make_error!(Code::NoSuchEntry));
     };
 
-    let pos = layer.pos();
-    let size = layer.window().read().base().size();
+    let area = layer.area();
 
     // LAYER_MANAGER も LAYER_TASK_MAP もロックを必要とするため割り込みは禁止しない
     manager.activate(0);
     manager.remove_layer(layer_id);
-    manager.draw(&Rectangle { pos, size });
+    manager.draw(&area);
     LAYER_TASK_MAP.lock_wait().remove(&layer_id);
 
     Ok(())
 }
 
+/// ページフリップで表示していない側のページ。
+struct PageFlip {
+    /// 表示していない側のページに描き込むフレームバッファ。
+    back: FrameBuffer,
+    /// 表示していない側のページが、VRAM の何行目から始まるか。
+    back_y: u16,
+    /// 前のフレームで描き直した領域。
+    prev_damage: Vec<Rectangle<i32>>,
+}
+
 /// 全レイヤーを管理する構造体。
 pub struct LayerManager {
     /// レイヤーを描画するライター。
@@ -96,10 +123,18 @@ pub struct LayerManager {
     layer_stack: Vec<u32>,
     /// レイヤーに割り振った最新の ID。
     latest_id: u32,
-    /// バックバッファ。
+    /// バックバッファ。合成はここで行い、[LayerManager::compose] の最後に画面へまとめて転送する。
     back_buffer: FrameBuffer,
+    /// 次の合成で描き直す、画面上の領域。重なる領域はまとめておく。
+    damage: Vec<Rectangle<i32>>,
+    /// ページフリップに使う、表示していない側のページ。使えない場合は `None`。
+    flip: Option<PageFlip>,
+    /// 合成したフレームの数、合成したピクセルの数の合計、画面に転送したピクセルの数の合計。
+    compose_stats: (u64, u64, u64),
     /// マウスレイヤ ID。
     mouse_layer: u32,
+    /// タスクバーのレイヤ ID。タスクバーがない場合は `0`。
+    taskbar_layer: u32,
     /// アクティブレイヤ ID。
     active_layer: u32,
 }
@@ -129,7 +164,11 @@ impl LayerManager {
             layer_stack: Vec::new(),
             latest_id: 0,
             back_buffer,
+            damage: Vec::new(),
+            flip: None,
+            compose_stats: (0, 0, 0),
             mouse_layer: 0,
+            taskbar_layer: 0,
             active_layer: 0,
         }
     }
@@ -173,16 +212,12 @@ impl LayerManager {
     /// 有効な ID を指定していない場合は `panic` する。
     pub fn r#move(&mut self, id: u32, new_position: Vector2D<i32>) {
         let layer = self.layer(id);
-        let window_size = layer.window().read().base().size();
-        let old_pos = layer.pos;
+        let old_area = layer.area();
 
         layer.r#move(new_position);
 
         // 過去いた領域を消すために上書きする
-        self.draw(&Rectangle {
-            pos: old_pos,
-            size: window_size,
-        });
+        self.draw(&old_area);
 
         self.draw_id(id);
     }
@@ -194,40 +229,130 @@ impl LayerManager {
     /// 有効な ID を指定していない場合は `panic` する。
     pub fn move_relative(&mut self, id: u32, pos_diff: Vector2D<i32>) {
         let layer = self.find_layer_mut(id).unwrap();
-        let window_size = layer.window().read().base().size();
-        let old_pos = layer.pos;
+        let old_area = layer.area();
         layer.move_relative(pos_diff);
 
         // 過去いた領域を消すために上書きする
-        self.draw(&Rectangle {
-            pos: old_pos,
-            size: window_size,
-        });
+        self.draw(&old_area);
+
+        self.draw_id(id);
+    }
+
+    /// 指定されたレイヤーのウィンドウ全体の大きさを `size` に変え、描き直す。
+    pub fn resize(&mut self, id: u32, size: Vector2D<i32>) {
+        let active = self.active_layer == id;
+        let layer = self.layer(id);
+        let old_area = layer.area();
+        let window = layer.window();
+        {
+            let mut window = window.write();
+            window.resize(size.x() as _, size.y() as _);
+            if active {
+                window.activate();
+            }
+        }
 
+        // 縮んだ場合に元の領域を消すために上書きする
+        self.draw(&old_area);
         self.draw_id(id);
     }
 
-    /// 指定領域のレイヤーを画面に描画する。
+    /// ウィンドウを最大化したときに占める領域。タスクバーに重ならない部分になる。
+    pub fn work_area(&self) -> Rectangle<i32> {
+        let screen_size = self.screen_size();
+        let height = match self.layers.get(&self.taskbar_layer) {
+            Some(taskbar) => taskbar.pos.y(),
+            None => screen_size.y(),
+        };
+        Rectangle {
+            pos: Vector2D::new(0, 0),
+            size: Vector2D::new(screen_size.x(), height),
+        }
+    }
+
+    /// 指定されたレイヤーを最大化する。最大化している場合は、最大化する前の位置と大きさに戻す。
+    ///
+    /// ここでは位置だけを変え、変えるべきウィンドウ全体の大きさを返す。
+    /// 大きさは呼び出し側が変える（[MessageType::WindowResize] を参照）。
+    pub fn toggle_maximize(&mut self, id: u32) -> Vector2D<i32> {
+        let work_area = self.work_area();
+        let layer = self.layer(id);
+        let area = match layer.restore_area.take() {
+            Some(area) => area,
+            None => {
+                layer.restore_area = Some(Rectangle {
+                    pos: layer.pos,
+                    size: layer.window.read().base().size(),
+                });
+                work_area
+            }
+        };
+        self.r#move(id, area.pos);
+        area.size
+    }
+
+    /// 指定されたレイヤーを最小化し、表示しないようにする。
+    pub fn minimize(&mut self, id: u32) {
+        let layer = self.layer(id);
+        layer.minimized = true;
+        let area = layer.area();
+        if self.active_layer == id {
+            self.activate(0);
+        }
+        self.hide(id);
+        self.draw(&area);
+    }
+
+    /// 最小化しているレイヤーを元に戻し、アクティブにする。
+    pub fn restore(&mut self, id: u32) {
+        self.layer(id).minimized = false;
+        self.activate(id);
+    }
+
+    /// 最小化している全てのレイヤーを元に戻す。
+    pub fn restore_all(&mut self) {
+        let ids: Vec<_> = self
+            .layers
+            .iter()
+            .filter(|(_, layer)| layer.minimized)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            self.restore(id);
+        }
+    }
+
+    /// 指定領域を、次の合成で描き直すよう記録する。
     pub fn draw(&mut self, area: &Rectangle<i32>) {
-        for layer_id in &self.layer_stack {
-            self.layers
-                .get_mut(layer_id)
-                .unwrap()
-                .draw_to(&mut self.back_buffer, area)
+        let mut area = *area;
+        if area.size.x() <= 0 || area.size.y() <= 0 {
+            return;
+        }
+
+        // 重なる領域は 1 つにまとめる
+        while let Some(i) = self.damage.iter().position(|&d| {
+            let overlap = d & area;
+            overlap.size.x() > 0 && overlap.size.y() > 0
+        }) {
+            area = area | self.damage.swap_remove(i);
         }
+        self.damage.push(area);
 
-        // バックバッファをフレームバッファにコピー
-        self.screen
-            .lock_wait()
-            .copy(area.pos, &self.back_buffer, area)
-            .unwrap();
+        // 細かい領域が増えすぎた場合は、全てを含む 1 つの領域にする
+        if self.damage.len() > MAX_DAMAGE_RECTS {
+            let all = self.damage.iter().copied().reduce(|a, b| a | b).unwrap();
+            self.damage.clear();
+            self.damage.push(all);
+        }
     }
 
-    /// 指定されたレイヤーより上のレイヤーを描画する。
+    /// 指定されたレイヤーのウィンドウ全体を、次の合成で描き直すよう記録する。
     ///
     /// # Remarks
     /// 有効な ID を指定していない場合は `panic` する。
     pub fn draw_id(&mut self, id: u32) {
+        // ウィンドウ全体を描き直すので、ウィンドウに記録された部分は要らない
+        self.layer(id).window().write().base_mut().take_damage();
         self.draw_area(
             id,
             Rectangle {
@@ -237,37 +362,155 @@ impl LayerManager {
         )
     }
 
+    /// 指定されたレイヤーのウィンドウのうち `area` の部分を、次の合成で描き直すよう記録する。
+    /// `area.size` が負の場合はウィンドウ全体。
+    ///
+    /// 表示されていないレイヤーの場合は何もしない。
     pub fn draw_area(&mut self, id: u32, mut area: Rectangle<i32>) {
-        let mut window_area = None;
-        for layer_id in &self.layer_stack {
-            // layer_stack の中に入っているのは layers の中に入っているものに限られるため、
-            // unwrap は必ず成功する
-            let layer = self.layers.get_mut(layer_id).unwrap();
-            if *layer_id == id {
-                let mut wnd_area = Rectangle {
-                    pos: layer.pos,
-                    size: layer.window.read().base().size(),
-                };
-                // area.size が正の場合は area.pos から area.size 分だけ描画する
-                if area.size.x() >= 0 || area.size.y() >= 0 {
-                    area.pos += wnd_area.pos;
-                    wnd_area = wnd_area & area;
-                }
-                window_area = Some(wnd_area);
+        if self.get_height(id) < 0 {
+            return;
+        }
+        // 表示されているので必ず見つかる
+        let layer = &self.layers[&id];
+        // area.size が正の場合は area.pos から area.size 分だけ描画する
+        // そうでない場合は影も含めて描画する
+        let window_area = if area.size.x() >= 0 || area.size.y() >= 0 {
+            area.pos += layer.pos;
+            Rectangle {
+                pos: layer.pos,
+                size: layer.window.read().base().size(),
+            } & area
+        } else {
+            layer.area()
+        };
+        self.draw(&window_area);
+    }
+
+    /// 指定されたレイヤーのウィンドウに描き込まれた部分だけを、次の合成で描き直すよう記録する。
+    pub fn draw_damage(&mut self, id: u32) {
+        let Some(layer) = self.layers.get(&id) else {
+            return;
+        };
+        let Some(damage) = layer.window.write().base_mut().take_damage() else {
+            return;
+        };
+        self.draw_area(id, damage);
+    }
+
+    /// 画面が対応していれば、ページフリップで画面を更新するようにする。
+    ///
+    /// VRAM のフレームバッファの後ろに同じ大きさのページをもう 1 枚置き、
+    /// [LayerManager::compose] では表示していない側に描いてから表示するページを切り替える。
+    pub fn enable_page_flip(&mut self) -> bool {
+        let config = {
+            let screen = self.screen.lock_wait();
+            FrameBufferConfig {
+                frame_buffer: screen.frame_buffer(),
+                pixels_per_scan_line: screen.pixels_per_scan_line(),
+                horizontal_resolution: screen.horizontal_resolution(),
+                vertical_resolution: screen.vertical_resolution(),
+                pixel_format: screen.pixel_format(),
             }
-            // layer_id より上のレイヤーでは window_area が Some になっているはずなので、
-            // 上のレイヤーは全て描画される
-            if let Some(area) = window_area {
-                layer.draw_to(&mut self.back_buffer, &area);
+        };
+        if self.flip.is_some() || !bga::supports_page_flip(&config) {
+            return false;
+        }
+
+        let back_config = FrameBufferConfig {
+            frame_buffer: config.frame_buffer
+                + frame_buffer::bytes_per_scan_line(&config) * config.vertical_resolution,
+            ..config
+        };
+        let Ok(back) = FrameBuffer::new(back_config) else {
+            return false;
+        };
+        // 裏のページは何も描かれていないので、最初の切り替えでは全体を転送させる
+        let screen_area = Rectangle {
+            pos: Vector2D::new(0, 0),
+            size: self.screen_size(),
+        };
+        self.flip = Some(PageFlip {
+            back,
+            back_y: config.vertical_resolution as u16,
+            prev_damage: Vec::from([screen_area]),
+        });
+        true
+    }
+
+    /// 記録された領域のレイヤーを全てバックバッファで合成し終えてから、画面に転送する。
+    ///
+    /// 一定間隔で呼ぶことで、何度描き込まれても合成と転送は 1 フレームに 1 回で済む。
+    ///
+    /// ページフリップが使える場合は、表示していないページに転送してから、
+    /// 垂直帰線期間を待って表示するページを切り替える。
+    /// 合成の途中や転送の途中の状態が画面に出ることはない。
+    /// 使えない場合は、記録された領域ごとに表示中のフレームバッファへ直接転送する。
+    pub fn compose(&mut self) {
+        if self.damage.is_empty() {
+            return;
+        }
+
+        let screen_area = Rectangle {
+            pos: Vector2D::new(0, 0),
+            size: self.screen_size(),
+        };
+        let damage: Vec<_> = self
+            .damage
+            .drain(..)
+            .map(|area| area & screen_area)
+            .filter(|area| area.size.x() > 0 && area.size.y() > 0)
+            .collect();
+
+        for area in &damage {
+            for layer_id in &self.layer_stack {
+                let layer = &self.layers[layer_id];
+                layer.draw_shadow_to(&mut self.back_buffer, area);
+                layer.draw_to(&mut self.back_buffer, area);
             }
+            self.compose_stats.1 += (area.size.x() * area.size.y()) as u64;
         }
+        self.compose_stats.0 += 1;
+
+        let mut screen = self.screen.lock_wait();
+        let Some(flip) = &mut self.flip else {
+            // 離れた領域をまとめると転送量が増えるので、領域ごとにコピーする
+            for area in &damage {
+                screen.copy(area.pos, &self.back_buffer, area).unwrap();
+                self.compose_stats.2 += (area.size.x() * area.size.y()) as u64;
+            }
+            return;
+        };
 
-        if let Some(area) = window_area {
-            self.screen
-                .lock_wait()
-                .copy(area.pos, &self.back_buffer, &area)
-                .unwrap();
+        // 裏のページは 2 フレーム前の内容なので、前のフレームで変わった部分も転送する
+        for area in damage.iter().chain(&flip.prev_damage) {
+            flip.back.copy(area.pos, &self.back_buffer, area).unwrap();
+            self.compose_stats.2 += (area.size.x() * area.size.y()) as u64;
         }
+        bga::wait_vertical_retrace();
+        bga::set_y_offset(flip.back_y);
+        mem::swap(&mut *screen, &mut flip.back);
+        flip.back_y = if flip.back_y == 0 {
+            screen.vertical_resolution() as u16
+        } else {
+            0
+        };
+        flip.prev_damage = damage;
+    }
+
+    /// 指定されたレイヤーのウィンドウ全体の不透明度を設定し、描き直す。`255` で不透明。
+    pub fn set_opacity(&mut self, id: u32, opacity: u8) {
+        self.layer(id)
+            .window()
+            .write()
+            .base_mut()
+            .set_opacity(opacity);
+        self.draw_id(id);
+    }
+
+    /// これまでに合成したフレームの数、合成したピクセルの数の合計、
+    /// 画面に転送したピクセルの数の合計を返す。
+    pub fn compose_stats(&self) -> (u64, u64, u64) {
+        self.compose_stats
     }
 
     /// 指定されたレイヤを非表示にする。
@@ -366,6 +609,19 @@ impl LayerManager {
         self.mouse_layer = id;
     }
 
+    pub fn set_taskbar_layer(&mut self, id: u32) {
+        self.taskbar_layer = id;
+    }
+
+    /// タイトルを持つウィンドウのレイヤー ID を、作られた順に返す。最小化しているものも含む。
+    pub fn toplevel_layers(&self) -> Vec<u32> {
+        self.layers
+            .iter()
+            .filter(|(_, layer)| layer.window.read().title().is_some())
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
     pub fn get_active(&self) -> u32 {
         self.active_layer
     }
@@ -385,10 +641,16 @@ impl LayerManager {
         self.active_layer = id;
         if id > 0 {
             self.layer(id).window().write().activate();
+            // タスクバーがある場合は、その下に置く
+            let top = if self.get_height(self.taskbar_layer) >= 0 {
+                self.taskbar_layer
+            } else {
+                self.mouse_layer
+            };
             if self.layer_stack.iter().any(|&layer| layer == id) {
-                self.up_down(id, self.get_height(self.mouse_layer) - 1);
+                self.up_down(id, self.get_height(top) - 1);
             } else {
-                self.up_down(id, self.get_height(self.mouse_layer));
+                self.up_down(id, self.get_height(top));
             }
             self.draw_id(id);
             let _ = Self::send_window_active_message(id, true);
@@ -422,6 +684,10 @@ pub struct Layer {
     /// ドラッグ可能かどうかを表すフラグ。
     /// デフォルトは `false`。
     dragable: bool,
+    /// 最大化する前のウィンドウ全体の位置と大きさ。最大化していなければ `None`。
+    restore_area: Option<Rectangle<i32>>,
+    /// 最小化しているかどうか。
+    minimized: bool,
 }
 
 impl Layer {
@@ -433,6 +699,8 @@ impl Layer {
             window: Arc::new(SharedLock::new(window)),
             pos: Default::default(),
             dragable: false,
+            restore_area: None,
+            minimized: false,
         }
     }
 
@@ -463,6 +731,66 @@ impl Layer {
         self.window.read().base().draw_to(screen, self.pos, area);
     }
 
+    /// ウィンドウと、その影が占める領域を返す。
+    pub fn area(&self) -> Rectangle<i32> {
+        let window = self.window.read();
+        let window_area = Rectangle {
+            pos: self.pos,
+            size: window.base().size(),
+        };
+        if window.title().is_none() {
+            return window_area;
+        }
+        window_area | self.shadow_area(window_area)
+    }
+
+    fn shadow_area(&self, window_area: Rectangle<i32>) -> Rectangle<i32> {
+        Rectangle {
+            pos: window_area.pos + Vector2D::new(SHADOW_OFFSET, SHADOW_OFFSET),
+            size: window_area.size,
+        }
+    }
+
+    /// トップレベルウィンドウの場合は、ウィンドウの右下にずらした影を描画する。
+    /// ウィンドウ自身に隠れる部分は描画しない。
+    pub fn draw_shadow_to(&self, screen: &mut FrameBuffer, area: &Rectangle<i32>) {
+        let window = self.window.read();
+        if window.title().is_none() {
+            return;
+        }
+        let window_area = Rectangle {
+            pos: self.pos,
+            size: window.base().size(),
+        };
+        let opacity = window.base().opacity() as u32;
+        // 下が透けて見えるウィンドウでは、ウィンドウに隠れる部分の影も見える
+        let opaque = window.base().is_opaque();
+        drop(window);
+
+        let shadow_area = self.shadow_area(window_area);
+        let shadow_end = shadow_area.pos + shadow_area.size;
+        let window_end = window_area.pos + window_area.size;
+        let target = shadow_area & *area;
+        let black = PixelColor::new(0, 0, 0);
+        for y in target.pos.y()..target.pos.y() + target.size.y() {
+            for x in target.pos.x()..target.pos.x() + target.size.x() {
+                if opaque && x < window_end.x() && y < window_end.y() {
+                    continue;
+                }
+                // 影の縁に近いほど薄くする
+                let edge = (x - shadow_area.pos.x())
+                    .min(shadow_end.x() - 1 - x)
+                    .min(y - shadow_area.pos.y())
+                    .min(shadow_end.y() - 1 - y)
+                    + 1;
+                let alpha = SHADOW_ALPHA * edge.min(SHADOW_BLUR) as u32 / SHADOW_BLUR as u32
+                    * opacity
+                    / 255;
+                screen.blend(Vector2D::new(x, y), &black, alpha as u8);
+            }
+        }
+    }
+
     /// ドラッグ可能かどうかを設定する。
     pub fn set_draggable(&mut self, dragable: bool) -> &mut Self {
         self.dragable = dragable;
@@ -473,4 +801,30 @@ impl Layer {
     pub fn is_draggable(&self) -> bool {
         self.dragable
     }
+
+    /// 枠のドラッグや最大化で大きさを変えられるかどうかを設定する。
+    pub fn set_resizable(&mut self, resizable: bool) -> &mut Self {
+        self.window.write().set_resizable(resizable);
+        self
+    }
+
+    /// 枠のドラッグや最大化で大きさを変えられるかどうかを返す。
+    pub fn is_resizable(&self) -> bool {
+        self.window.read().is_resizable()
+    }
+
+    /// 最大化しているかどうかを返す。
+    pub fn is_maximized(&self) -> bool {
+        self.restore_area.is_some()
+    }
+
+    /// 最大化していない状態として扱う。枠をドラッグして大きさを変えたときに呼ぶ。
+    pub fn clear_maximized(&mut self) {
+        self.restore_area = None;
+    }
+
+    /// 最小化しているかどうかを返す。
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
 }
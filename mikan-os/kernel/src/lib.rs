@@ -3,14 +3,19 @@
 extern crate alloc;
 
 pub mod acpi;
+pub mod ansi;
 pub mod app_event;
 pub mod asmfunc;
+pub mod bga;
 pub mod bitfield;
+pub mod clipboard;
 pub mod collections;
 pub mod console;
+pub mod devfs;
 pub mod elf;
 pub mod errno;
 pub mod error;
+pub mod ext2;
 pub mod fat;
 pub mod file;
 pub mod font;
@@ -28,15 +33,23 @@ pub mod message;
 pub mod mouse;
 pub mod msr;
 pub mod paging;
+pub mod path;
 pub mod pci;
+pub mod pipe;
+pub mod procfs;
+pub mod rtc;
 pub mod segment;
+pub mod shell;
 pub mod sync;
 pub mod syscall;
 pub mod task;
+pub mod taskbar;
 pub mod terminal;
 pub mod timer;
+pub mod tmpfs;
 pub mod usb;
 pub mod util;
+pub mod vfs;
 pub mod window;
 pub mod x86_descriptor;
 pub mod xhci;
@@ -1,11 +1,17 @@
-use alloc::{format, string::String};
+use alloc::vec::Vec;
 use core::{
+    cmp,
     ffi::{c_char, CStr},
+    fmt::{self, Write as _},
     mem::size_of,
-    panic,
+    panic, str,
 };
 
-use crate::{printk, sync::RwLock};
+use crate::{
+    asmfunc,
+    console::{Console, CONSOLE},
+    sync::{Mutex, RwLock},
+};
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 #[repr(C)]
@@ -54,6 +60,100 @@ impl_try_from_for_loglevel!(i8, u8, i16, u16, u32, i64, u64, i128, u128, isize,
 
 static LOG_LEVEL: RwLock<LogLevel> = RwLock::new(LogLevel::Warn);
 
+/// 保持しておくログの最大バイト数。
+const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+/// C++ 側からの 1 回のログの最大バイト数。これを超えた部分は捨てる。
+const LINE_BUFFER_SIZE: usize = 256;
+
+/// 出力したログを `/proc/log` から読めるように保持しておくリングバッファ。
+static LOG_BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer::new());
+
+/// 固定長のリングバッファ。溢れた場合は古いものから上書きする。
+struct LogBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    /// 最も古いバイトの位置。
+    head: usize,
+    len: usize,
+}
+
+impl LogBuffer {
+    const fn new() -> Self {
+        Self {
+            data: [0; LOG_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.data[(self.head + self.len) % LOG_BUFFER_SIZE] = b;
+            if self.len < LOG_BUFFER_SIZE {
+                self.len += 1;
+            } else {
+                self.head = (self.head + 1) % LOG_BUFFER_SIZE;
+            }
+        }
+    }
+
+    /// 保持している内容を古い順に返す。
+    fn to_vec(&self) -> Vec<u8> {
+        let end = self.head + self.len;
+        if end <= LOG_BUFFER_SIZE {
+            self.data[self.head..end].to_vec()
+        } else {
+            [&self.data[self.head..], &self.data[..end - LOG_BUFFER_SIZE]].concat()
+        }
+    }
+}
+
+/// コンソールとログバッファの両方に書き込む。
+struct LogWriter<'a> {
+    console: &'a mut Console,
+    buf: &'a mut LogBuffer,
+}
+
+impl fmt::Write for LogWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.buf.push(s.as_bytes());
+        // コンソールは ASCII 以外を表示できないが、ログバッファには残っているので無視する
+        let _ = self.console.write_str(s);
+        Ok(())
+    }
+}
+
+/// スタック上で 1 行分のログを組み立てるための固定長のバッファ。入りきらない部分は捨てる。
+struct LineBuffer {
+    data: [u8; LINE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl LineBuffer {
+    const fn new() -> Self {
+        Self {
+            data: [0; LINE_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        let bytes = &self.data[..self.len];
+        // 切り詰めたときに文字の途中で切れていた場合は、その前までにする
+        str::from_utf8(bytes)
+            .unwrap_or_else(|e| unsafe { str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) })
+    }
+}
+
+impl fmt::Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = cmp::min(s.len(), LINE_BUFFER_SIZE - self.len);
+        self.data[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
 pub fn set_log_level(level: LogLevel) {
     *LOG_LEVEL.write() = level;
 }
@@ -62,11 +162,40 @@ pub fn get_log_level() -> LogLevel {
     *LOG_LEVEL.read()
 }
 
+/// ログをコンソールに出力し、ログバッファにも記録する。
+pub fn write_log(args: fmt::Arguments) {
+    output(format_args!("{}\n", args));
+}
+
+/// 保持しているログを古い順に返す。
+pub fn log_buffer() -> Vec<u8> {
+    asmfunc::cli();
+    let buf = LOG_BUFFER.lock_wait().to_vec();
+    asmfunc::sti();
+    buf
+}
+
+/// `args` をヒープを使わずにコンソールとログバッファへ書き込む。
+/// 割り込みを禁止するのは、両方に書き込む間の 1 回だけにする。
+fn output(args: fmt::Arguments) {
+    asmfunc::cli();
+    {
+        let mut console = CONSOLE.lock_wait();
+        let mut buf = LOG_BUFFER.lock_wait();
+        let mut writer = LogWriter {
+            console: &mut console,
+            buf: &mut buf,
+        };
+        let _ = writer.write_fmt(args);
+    }
+    asmfunc::sti();
+}
+
 #[macro_export]
 macro_rules! log {
     ($level:expr, $($arg:tt)*) => {
         if $level <= $crate::logger::get_log_level() {
-            $crate::printkln!("{}", format_args!($($arg)*));
+            $crate::logger::write_log(format_args!($($arg)*));
         }
     }
 }
@@ -126,7 +255,7 @@ pub unsafe extern "sysv64" fn log_cpp(
     let mut digit = 0;
 
     // 最終的に表示する文字列
-    let mut str = String::with_capacity(s.len());
+    let mut line = LineBuffer::new();
     for c in s.chars() {
         if arg_maybe_needed {
             // 引数を u64 として取得
@@ -146,22 +275,22 @@ pub unsafe extern "sysv64" fn log_cpp(
                     let s = unsafe { CStr::from_ptr(arg as *const c_char) }
                         .to_str()
                         .unwrap();
-                    str.push_str(s);
+                    let _ = line.write_str(s);
                 }
                 // 10進整数
                 'd' => {
                     if padding {
                         if digit != 0 {
-                            str.push_str(&format!("{:0digit$}", arg as i64));
+                            let _ = write!(line, "{:0digit$}", arg as i64);
                         } else {
-                            str.push_str(&format!("{:0}", arg as i64));
+                            let _ = write!(line, "{:0}", arg as i64);
                         }
                     } else {
                         #[allow(clippy::collapsible_if)]
                         if digit != 0 {
-                            str.push_str(&format!("{:digit$}", arg as i64));
+                            let _ = write!(line, "{:digit$}", arg as i64);
                         } else {
-                            str.push_str(&format!("{}", arg as i64));
+                            let _ = write!(line, "{}", arg as i64);
                         }
                     }
                 }
@@ -169,16 +298,16 @@ pub unsafe extern "sysv64" fn log_cpp(
                 'x' => {
                     if padding {
                         if digit != 0 {
-                            str.push_str(&format!("{:0digit$x}", arg as i64));
+                            let _ = write!(line, "{:0digit$x}", arg as i64);
                         } else {
-                            str.push_str(&format!("{:0x}", arg as i64));
+                            let _ = write!(line, "{:0x}", arg as i64);
                         }
                     } else {
                         #[allow(clippy::collapsible_if)]
                         if digit != 0 {
-                            str.push_str(&format!("{:digit$x}", arg as i64));
+                            let _ = write!(line, "{:digit$x}", arg as i64);
                         } else {
-                            str.push_str(&format!("{:x}", arg as i64));
+                            let _ = write!(line, "{:x}", arg as i64);
                         }
                     }
                 }
@@ -186,27 +315,31 @@ pub unsafe extern "sysv64" fn log_cpp(
                 'u' => {
                     if padding {
                         if digit != 0 {
-                            str.push_str(&format!("{:0digit$}", arg));
+                            let _ = write!(line, "{:0digit$}", arg);
                         } else {
-                            str.push_str(&format!("{:0}", arg));
+                            let _ = write!(line, "{:0}", arg);
                         }
                     } else {
                         #[allow(clippy::collapsible_if)]
                         if digit != 0 {
-                            str.push_str(&format!("{:digit$}", arg));
+                            let _ = write!(line, "{:digit$}", arg);
                         } else {
-                            str.push_str(&format!("{}", arg));
+                            let _ = write!(line, "{}", arg);
                         }
                     }
                 }
                 // 文字
-                'c' => str.push(arg as u8 as char),
+                'c' => {
+                    let _ = line.write_char(arg as u8 as char);
+                }
                 // ポインタ
-                'p' => str.push_str(&format!("{:p}", arg as *const u8)),
+                'p' => {
+                    let _ = write!(line, "{:p}", arg as *const u8);
+                }
                 // % のエスケープ
                 '%' => {
                     arg_maybe_needed = false;
-                    str.push('%');
+                    let _ = line.write_char('%');
                     continue;
                 }
                 // C では long だが、Rust（特にこの実装）では関係ない
@@ -231,13 +364,13 @@ pub unsafe extern "sysv64" fn log_cpp(
             if c == '%' {
                 arg_maybe_needed = true;
             } else {
-                str.push(c);
+                let _ = line.write_char(c);
             }
         }
     }
 
     // 元々の関数が改行コードを勝手に付けないため、こちらもそのようにする
-    printk!("{}", str);
+    output(format_args!("{}", line.as_str()));
 
     0
 }
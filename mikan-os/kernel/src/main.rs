@@ -12,7 +12,7 @@ use kernel::{
     asmfunc::{self, cli, halt, sti},
     console::{self, PanicConsole},
     error::Result,
-    fat, font,
+    ext2, fat, font,
     frame_buffer_config::FrameBufferConfig,
     graphics::{PixelColor, PixelWrite, Vector2D, FB_CONFIG},
     interrupt, keyboard,
@@ -21,9 +21,9 @@ use kernel::{
     logger::{set_log_level, LogLevel},
     memory_manager::{GLOBAL, MEMORY_MANAGER},
     message::{Message, MessageType},
-    mouse, paging, pci, printk, printkln, segment, syscall,
+    mouse, paging, pci, printk, printkln, rtc, segment, syscall,
     task::{self, Stack},
-    terminal,
+    taskbar, terminal,
     timer::{self, Timer, TIMER_MANAGER},
     window::Window,
     xhci::{self, XHC},
@@ -92,18 +92,25 @@ fn kernel_entry(
     kernel_size: usize,
     acpi_table: &RSDP,
     volume_image: *mut c_void,
+    ext2_image: *mut c_void,
+    ext2_size: usize,
 ) {
     FB_CONFIG.init(frame_buffer_config.clone());
     // メモリアロケータの初期化
     MEMORY_MANAGER.init(memory_map, kernel_base, kernel_size);
     GLOBAL.init(64 * 512); // 128 MiB 確保
 
-    if let Err(err) = main(acpi_table, volume_image) {
+    if let Err(err) = main(acpi_table, volume_image, ext2_image, ext2_size) {
         printkln!("{}", err);
     }
 }
 
-fn main(acpi_table: &RSDP, volume_image: *mut c_void) -> Result<()> {
+fn main(
+    acpi_table: &RSDP,
+    volume_image: *mut c_void,
+    ext2_image: *mut c_void,
+    ext2_size: usize,
+) -> Result<()> {
     layer::init();
     console::init();
 
@@ -119,19 +126,33 @@ fn main(acpi_table: &RSDP, volume_image: *mut c_void) -> Result<()> {
     paging::init();
     interrupt::init();
 
-    fat::init(volume_image);
+    fat::init(volume_image)?;
+    // 2 つ目のボリュームは無くても起動できるので、失敗しても続ける
+    if !ext2_image.is_null() {
+        if let Err(err) = ext2::init(ext2_image, ext2_size) {
+            log!(LogLevel::Warn, "failed to mount ext2 volume: {}", err);
+        }
+    }
     font::init()?;
     pci::init()?;
+    if LAYER_MANAGER.lock_wait().enable_page_flip() {
+        log!(LogLevel::Info, "page flip enabled");
+    }
 
     let main_window_id = initialize_main_window();
     let text_window_id = initialize_text_window();
 
     // FIXME: 最初に登録されるレイヤーは背景ウィンドウなので、`layer_id` 1 を表示すれば
     //        必ず全て表示されるが、ハードコードは良くなさそう
-    LAYER_MANAGER.lock_wait().draw_id(1);
+    {
+        let mut layer_manager = LAYER_MANAGER.lock_wait();
+        layer_manager.draw_id(1);
+        layer_manager.compose();
+    }
 
     acpi_table.init()?;
     timer::init();
+    rtc::init();
 
     // カーソル点滅用のタイマを追加
     let textbox_cursor_timer = 1;
@@ -141,6 +162,12 @@ fn main(acpi_table: &RSDP, volume_image: *mut c_void) -> Result<()> {
         .add_timer(Timer::new(timer_05sec, textbox_cursor_timer, 1));
     let mut textbox_cursor_visible = false;
 
+    // 画面の合成用のタイマを追加
+    let frame_timer = 2;
+    TIMER_MANAGER
+        .lock_wait()
+        .add_timer(Timer::new(layer::FRAME_INTERVAL, frame_timer, 1));
+
     syscall::init();
 
     task::init();
@@ -153,6 +180,13 @@ fn main(acpi_table: &RSDP, volume_image: *mut c_void) -> Result<()> {
     mouse::init();
     keyboard::init();
 
+    // マウスカーソルのレイヤーの下に置くため、mouse::init() の後に起動する
+    asmfunc::cli();
+    task::new_task()
+        .init_context(taskbar::task_taskbar, 0, 0)
+        .wake_up(-1);
+    asmfunc::sti();
+
     // debug 時のみライブラリのテストを動かす。
     #[cfg(debug_assertions)]
     test_lib();
@@ -212,7 +246,14 @@ fn main(acpi_table: &RSDP, volume_image: *mut c_void) -> Result<()> {
                 }
             }
             MessageType::TimerTimeout { timeout, value } => {
-                if value == textbox_cursor_timer {
+                if value == frame_timer {
+                    TIMER_MANAGER.lock_wait().add_timer(Timer::new(
+                        timeout + layer::FRAME_INTERVAL,
+                        frame_timer,
+                        1,
+                    ));
+                    LAYER_MANAGER.lock_wait().compose();
+                } else if value == textbox_cursor_timer {
                     TIMER_MANAGER.lock_wait().add_timer(Timer::new(
                         timeout + timer_05sec,
                         textbox_cursor_timer,
@@ -287,6 +328,10 @@ fn main(acpi_table: &RSDP, volume_image: *mut c_void) -> Result<()> {
                     task::new_task()
                         .init_context(terminal::task_terminal, 0, 0)
                         .wake_up(-1);
+                }
+                // F3: 最小化したウィンドウを全て元に戻す
+                else if press && keycode == 60 {
+                    LAYER_MANAGER.lock_wait().restore_all();
                 } else if let Some(task_id) = LAYER_TASK_MAP
                     .lock_wait()
                     .iter()
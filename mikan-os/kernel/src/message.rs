@@ -1,4 +1,9 @@
-use crate::graphics::{Rectangle, Vector2D};
+use alloc::vec::Vec;
+
+use crate::{
+    graphics::{Rectangle, Vector2D},
+    terminal::Termios,
+};
 
 /// 発信元のタスクを知らせる必要がない場合は `src_task` を `0` にして使用する。
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -100,16 +105,44 @@ pub enum MessageType {
         press: bool,
         button: i32,
     },
+    /// マウスホイールの回転。`delta` は奥に回すと正。
+    MouseWheel {
+        x: i32,
+        y: i32,
+        delta: i32,
+    },
     WindowActive {
         activate: bool,
     },
-    Pipe {
-        data: [u8; 16],
-        len: u8,
+    /// アクティブにならないレイヤー（タスクバーなど）が左クリックされた。
+    /// `x`, `y` はレイヤー内での位置。
+    LayerClick {
+        layer_id: u32,
+        x: i32,
+        y: i32,
     },
+    /// タスクバーとランチャーの外側が左クリックされた。開いているランチャーを閉じる。
+    MenuClose,
     WindowClose {
         layer_id: u32,
     },
+    /// ウィンドウの枠がドラッグされた。ウィンドウ全体の大きさを `width` x `height` に変えるよう求める。
+    ///
+    /// アプリのウィンドウは送る前に大きさを変えてある。
+    /// ターミナルのウィンドウは、メッセージを受け取ったターミナルが文字の大きさに合わせて変える。
+    WindowResize {
+        layer_id: u32,
+        width: i32,
+        height: i32,
+    },
+    /// 他のタスクが `/dev/tty<id>` に書き込んだデータ。受け取ったターミナルが表示する。
+    TerminalWrite {
+        data: Vec<u8>,
+    },
+    /// 他のタスクが `/dev/tty<id>` に対して設定した端末の設定。
+    TerminalSetTermios {
+        termios: Termios,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -2,16 +2,60 @@ use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
 
 use crate::{
     bitfield::BitField as _,
-    graphics::{PixelColor, PixelWrite, Vector2D},
+    devfs,
+    graphics::{PixelColor, PixelWrite, Rectangle, Vector2D},
     layer::{LAYER_MANAGER, LAYER_TASK_MAP, SCREEN},
     message::{Message, MessageType},
-    task,
+    sync::Mutex,
+    task, taskbar,
     usb::HIDMouseDriver,
     window::{Window, WindowRegion},
 };
 
 pub static MOUSE_LAYER_ID: AtomicU32 = AtomicU32::new(0);
 
+/// 枠をドラッグして大きさを変えている途中のウィンドウ。
+static MOUSE_RESIZE: Mutex<Option<Resize>> = Mutex::new(None);
+
+/// ウィンドウの枠のドラッグ。
+struct Resize {
+    layer_id: u32,
+    /// ドラッグを始めたときのマウスの位置。
+    start: Vector2D<i32>,
+    /// ドラッグを始めたときのウィンドウ全体の位置と大きさ。
+    area: Rectangle<i32>,
+    /// ドラッグしている枠が左・上・右・下のどれか。角では 2 つが `true` になる。
+    edges: [bool; 4],
+}
+
+impl Resize {
+    /// マウスが `pos` にあるときの、ウィンドウ全体の位置と大きさを返す。
+    fn area(&self, pos: Vector2D<i32>) -> Rectangle<i32> {
+        let diff = pos - self.start;
+        let [left, top, right, bottom] = self.edges;
+        let min = Window::MIN_SIZE;
+        let mut begin = self.area.pos;
+        let mut end = self.area.pos + self.area.size;
+
+        if left {
+            begin = Vector2D::new((begin.x() + diff.x()).min(end.x() - min.x()), begin.y());
+        }
+        if top {
+            begin = Vector2D::new(begin.x(), (begin.y() + diff.y()).min(end.y() - min.y()));
+        }
+        if right {
+            end = Vector2D::new((end.x() + diff.x()).max(begin.x() + min.x()), end.y());
+        }
+        if bottom {
+            end = Vector2D::new(end.x(), (end.y() + diff.y()).max(begin.y() + min.y()));
+        }
+        Rectangle {
+            pos: begin,
+            size: end - begin,
+        }
+    }
+}
+
 pub fn init() {
     let mut mouse_window = Window::new_base(
         MOUSE_CURSOR_WIDTH as u32,
@@ -33,7 +77,7 @@ pub fn init() {
     HIDMouseDriver::set_default_observer(mouse_observer);
 }
 
-pub fn mouse_observer(buttons: u8, displacement_x: i8, displacement_y: i8) {
+pub fn mouse_observer(buttons: u8, displacement_x: i8, displacement_y: i8, wheel: i8) {
     static MOUSE_DRAG_LAYER_ID: AtomicU32 = AtomicU32::new(0);
     static PREVIOUS_BUTTONS: AtomicU8 = AtomicU8::new(0);
 
@@ -50,20 +94,58 @@ pub fn mouse_observer(buttons: u8, displacement_x: i8, displacement_y: i8) {
     layer_manager.r#move(layer_id, mouse_position);
 
     let mut close_layer_id = 0;
+    let mut resize_request = None;
+    // タイトルバーのボタンを押したときは、アプリにマウスの操作を送らない
+    let mut button_pressed = false;
+    // アクティブにせずにクリックを受け取るレイヤー（タスクバーなど）を押したときの送り先
+    let mut direct_press = None;
 
     let previous_left_pressed = PREVIOUS_BUTTONS.load(Ordering::Acquire).get_bit(0);
     let left_pressed = buttons.get_bit(0);
     if !previous_left_pressed && left_pressed {
-        if let Some(id) = layer_manager.find_layer_by_position(&mouse_position, layer_id) {
+        let pressed_layer = layer_manager.find_layer_by_position(&mouse_position, layer_id);
+        taskbar::notify_press(pressed_layer);
+        if let Some(id) = pressed_layer {
             let layer = layer_manager.layer(id);
             if layer.is_draggable() {
                 let pos_layer = mouse_position - layer.pos();
-                match layer.window().read().get_window_region(pos_layer) {
+                let window = layer.window();
+                let window = window.read();
+                let region = window.get_window_region(pos_layer);
+                match region {
                     WindowRegion::TitleBar => MOUSE_DRAG_LAYER_ID.store(id, Ordering::Release),
                     WindowRegion::CloseButton => close_layer_id = id,
+                    WindowRegion::Border(edges) if window.is_resizable() => {
+                        *MOUSE_RESIZE.lock_wait() = Some(Resize {
+                            layer_id: id,
+                            start: mouse_position,
+                            area: Rectangle {
+                                pos: layer.pos(),
+                                size: window.base().size(),
+                            },
+                            edges,
+                        });
+                    }
                     _ => {}
                 }
+                drop(window);
                 layer_manager.activate(id);
+
+                match region {
+                    WindowRegion::MinimizeButton => {
+                        layer_manager.minimize(id);
+                        button_pressed = true;
+                    }
+                    WindowRegion::MaximizeButton => {
+                        let size = layer_manager.toggle_maximize(id);
+                        resize_request = Some((id, size));
+                        button_pressed = true;
+                    }
+                    _ => {}
+                }
+            } else if let Some(&task_id) = LAYER_TASK_MAP.lock_wait().get(&id) {
+                direct_press = Some((task_id, id, mouse_position - layer.pos()));
+                button_pressed = true;
             } else {
                 layer_manager.activate(0);
             }
@@ -75,18 +157,46 @@ pub fn mouse_observer(buttons: u8, displacement_x: i8, displacement_y: i8) {
         }
     } else if previous_left_pressed && !left_pressed {
         MOUSE_DRAG_LAYER_ID.store(0, Ordering::Release);
+
+        // 大きさはボタンを離したときにまとめて変える
+        if let Some(resize) = MOUSE_RESIZE.lock_wait().take() {
+            let area = resize.area(mouse_position);
+            if area != resize.area {
+                layer_manager.layer(resize.layer_id).clear_maximized();
+            }
+            if area.pos != resize.area.pos {
+                layer_manager.r#move(resize.layer_id, area.pos);
+            }
+            if area.size != resize.area.size {
+                resize_request = Some((resize.layer_id, area.size));
+            }
+        }
     }
     drop(layer_manager);
 
+    if let Some((layer_id, size)) = resize_request {
+        send_resize_message(layer_id, size);
+    }
+    if let Some((task_id, layer_id, relpos)) = direct_press {
+        let msg = MessageType::LayerClick {
+            layer_id,
+            x: relpos.x(),
+            y: relpos.y(),
+        }
+        .into();
+        let _ = task::send_message(task_id, msg);
+    }
+
     // ウィンドウのドラッグを行っているときは、
     // アクティブウィンドウの相対位置が動かないため送らない
-    if MOUSE_DRAG_LAYER_ID.load(Ordering::Acquire) == 0 {
+    if MOUSE_DRAG_LAYER_ID.load(Ordering::Acquire) == 0 && !button_pressed {
         if close_layer_id == 0 {
             send_mouse_message(
                 newpos,
                 posdiff,
                 buttons,
                 PREVIOUS_BUTTONS.load(Ordering::Relaxed),
+                wheel,
             );
         } else {
             send_close_message();
@@ -157,11 +267,33 @@ fn send_close_message() {
     let _ = task::send_message(task_id, msg);
 }
 
+/// ウィンドウ全体の大きさを `size` に変え、ウィンドウを持つタスクに知らせる。
+///
+/// ターミナルのウィンドウは文字の大きさに合わせるため、大きさはターミナル自身が変える。
+/// アプリのウィンドウは、アプリがイベントを読むかどうかに関わらずここで変えておく。
+fn send_resize_message(layer_id: u32, size: Vector2D<i32>) {
+    let Some(&task_id) = LAYER_TASK_MAP.lock_wait().get(&layer_id) else {
+        return;
+    };
+    if !devfs::is_terminal_layer(layer_id) {
+        LAYER_MANAGER.lock_wait().resize(layer_id, size);
+    }
+
+    let msg = MessageType::WindowResize {
+        layer_id,
+        width: size.x(),
+        height: size.y(),
+    }
+    .into();
+    let _ = task::send_message(task_id, msg);
+}
+
 fn send_mouse_message(
     newpos: Vector2D<i32>,
     posdiff: Vector2D<i32>,
     buttons: u8,
     previou_buttons: u8,
+    wheel: i8,
 ) {
     let manager = match LAYER_MANAGER.lock() {
         Some(m) => m,
@@ -193,6 +325,18 @@ fn send_mouse_message(
         let _ = task::send_message(task_id, msg);
     }
 
+    if wheel != 0 {
+        let msg = Message {
+            src_task: 0,
+            ty: MessageType::MouseWheel {
+                x: relpos.x(),
+                y: relpos.y(),
+                delta: wheel as i32,
+            },
+        };
+        let _ = task::send_message(task_id, msg);
+    }
+
     if previou_buttons != buttons {
         let diff = previou_buttons ^ buttons;
         for i in 0..u8::BITS {
@@ -0,0 +1,33 @@
+use alloc::{string::String, vec::Vec};
+
+/// `path` を `cwd` を基準として解決し、`.` や `..` を含まない絶対パスを返す。
+///
+/// `path` が `/` から始まる場合は `cwd` を無視する。
+/// `cwd` は絶対パスであることを前提とする。
+/// `path` の末尾が `/` の場合は、結果の末尾にも `/` を残す（ルートディレクトリを除く）。
+pub fn resolve(cwd: &str, path: &str) -> String {
+    let mut elems = Vec::new();
+    let base = if path.starts_with('/') { "" } else { cwd };
+
+    for elem in base.split('/').chain(path.split('/')) {
+        match elem {
+            "" | "." => {}
+            ".." => {
+                elems.pop();
+            }
+            elem => elems.push(elem),
+        }
+    }
+
+    let mut resolved = String::from("/");
+    resolved.push_str(&elems.join("/"));
+    if path.ends_with('/') && !elems.is_empty() {
+        resolved.push('/');
+    }
+    resolved
+}
+
+/// `path` がルートディレクトリを指しているかどうかを返す。
+pub fn is_root(path: &str) -> bool {
+    !path.is_empty() && path.bytes().all(|b| b == b'/')
+}
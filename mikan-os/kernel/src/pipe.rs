@@ -0,0 +1,247 @@
+//! タスク間でデータを受け渡すパイプ。
+//!
+//! 1 ページ分のリングバッファを持ち、バッファが空の間は読み込みを、一杯の間は書き込みをブロックする。
+//! 書き込み側が全て閉じられると、読み込み側は残りのデータを読み終えた後に EOF を受け取る。
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::cmp;
+
+use crate::{
+    asmfunc,
+    error::{Code, Result},
+    make_error,
+    memory_manager::BYTES_PER_FRAME,
+    sync::Mutex,
+    task,
+};
+
+/// パイプのバッファのサイズ。
+pub const PIPE_BUF_SIZE: usize = BYTES_PER_FRAME;
+
+/// パイプの両端で共有される状態。
+///
+/// タスクスイッチで中断されたまま他のタスクがロックを待たないように、
+/// ロックは割り込みを禁止した状態でのみ取る。
+struct Pipe {
+    buf: Box<[u8; PIPE_BUF_SIZE]>,
+    /// 次に読み込む位置。
+    head: usize,
+    /// 溜まっているバイト数。
+    len: usize,
+    /// 開いている読み込み側の数。
+    readers: usize,
+    /// 開いている書き込み側の数。
+    writers: usize,
+    /// 読み書きできるようになるのを待っているタスクの ID。
+    waiters: Vec<u64>,
+}
+
+impl Pipe {
+    /// 溜まっているデータを `buf` に取り出し、取り出したバイト数を返す。
+    fn pop(&mut self, buf: &mut [u8]) -> usize {
+        let n = cmp::min(buf.len(), self.len);
+        let first = cmp::min(n, PIPE_BUF_SIZE - self.head);
+        buf[..first].copy_from_slice(&self.buf[self.head..self.head + first]);
+        buf[first..n].copy_from_slice(&self.buf[..n - first]);
+
+        self.head = (self.head + n) % PIPE_BUF_SIZE;
+        self.len -= n;
+        n
+    }
+
+    /// `buf` を空いている分だけ書き込み、書き込んだバイト数を返す。
+    fn push(&mut self, buf: &[u8]) -> usize {
+        let n = cmp::min(buf.len(), PIPE_BUF_SIZE - self.len);
+        let tail = (self.head + self.len) % PIPE_BUF_SIZE;
+        let first = cmp::min(n, PIPE_BUF_SIZE - tail);
+        self.buf[tail..tail + first].copy_from_slice(&buf[..first]);
+        self.buf[..n - first].copy_from_slice(&buf[first..n]);
+
+        self.len += n;
+        n
+    }
+
+    /// 待っているタスクを全て起こす。
+    fn wake_all(&mut self) {
+        for id in self.waiters.drain(..) {
+            let _ = task::wake_up(id, -1);
+        }
+    }
+}
+
+/// パイプの読み込み側。
+pub struct PipeReader(Arc<Mutex<Pipe>>);
+
+/// パイプの書き込み側。
+pub struct PipeWriter(Arc<Mutex<Pipe>>);
+
+/// 名前付きパイプ (FIFO) の実体。
+///
+/// 開くたびに読み込み側もしくは書き込み側を作る。
+#[derive(Clone)]
+pub struct Fifo(Arc<Mutex<Pipe>>);
+
+fn new_pipe(readers: usize, writers: usize) -> Arc<Mutex<Pipe>> {
+    Arc::new(Mutex::new(Pipe {
+        buf: Box::new([0; PIPE_BUF_SIZE]),
+        head: 0,
+        len: 0,
+        readers,
+        writers,
+        waiters: Vec::new(),
+    }))
+}
+
+/// 新しいパイプを作り、その読み込み側と書き込み側を返す。
+pub fn new() -> (PipeReader, PipeWriter) {
+    let pipe = new_pipe(1, 1);
+    (PipeReader(pipe.clone()), PipeWriter(pipe))
+}
+
+/// 割り込みを禁止してパイプをロックし、`f` を呼ぶ。
+///
+/// `f` が `None` を返した場合は、パイプの状態が変わるまで寝てから再び `f` を呼ぶ。
+fn wait_until<R>(pipe: &Mutex<Pipe>, mut f: impl FnMut(&mut Pipe) -> Option<R>) -> R {
+    loop {
+        asmfunc::cli();
+        let mut p = pipe.lock_wait();
+        if let Some(res) = f(&mut p) {
+            drop(p);
+            asmfunc::sti();
+            return res;
+        }
+
+        // 割り込みを禁止したまま寝るので、起こされる前に寝損ねることはない
+        let task = task::current_task();
+        p.waiters.push(task.id());
+        drop(p);
+        task.sleep();
+        asmfunc::sti();
+    }
+}
+
+impl Fifo {
+    /// どちら側も開かれていない FIFO を作る。
+    pub fn new() -> Self {
+        Self(new_pipe(0, 0))
+    }
+
+    /// 読み込み側を開く。
+    ///
+    /// 書き込み側が開かれるまでブロックする。
+    pub fn open_reader(&self) -> PipeReader {
+        let reader = PipeReader::attach(self.0.clone());
+        // 書き込み側が書き込んですぐに閉じた場合も、そのデータを読めるようにする
+        wait_until(&self.0, |p| {
+            if p.writers > 0 || p.len > 0 {
+                Some(())
+            } else {
+                None
+            }
+        });
+        reader
+    }
+
+    /// 書き込み側を開く。
+    ///
+    /// 読み込み側が開かれるまでブロックする。
+    pub fn open_writer(&self) -> PipeWriter {
+        let writer = PipeWriter::attach(self.0.clone());
+        wait_until(&self.0, |p| if p.readers > 0 { Some(()) } else { None });
+        writer
+    }
+}
+
+impl Default for Fifo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipeReader {
+    /// `pipe` の読み込み側を 1 つ増やす。
+    fn attach(pipe: Arc<Mutex<Pipe>>) -> Self {
+        wait_until(&pipe, |p| {
+            p.readers += 1;
+            p.wake_all();
+            Some(())
+        });
+        Self(pipe)
+    }
+
+    /// `buf` に読み込み、読み込んだバイト数を返す。
+    ///
+    /// データが無い間はブロックし、書き込み側が全て閉じられていれば `0` を返す。
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        wait_until(&self.0, |p| {
+            if p.len > 0 {
+                let n = p.pop(buf);
+                p.wake_all();
+                Some(n)
+            } else if p.writers == 0 {
+                Some(0)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl PipeWriter {
+    /// `pipe` の書き込み側を 1 つ増やす。
+    fn attach(pipe: Arc<Mutex<Pipe>>) -> Self {
+        wait_until(&pipe, |p| {
+            p.writers += 1;
+            p.wake_all();
+            Some(())
+        });
+        Self(pipe)
+    }
+
+    /// `buf` を全て書き込み、書き込んだバイト数を返す。
+    ///
+    /// バッファが一杯の間はブロックする。
+    /// 読み込み側が全て閉じられている場合はエラーを返す。
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            written += wait_until(&self.0, |p| {
+                if p.readers == 0 {
+                    Some(Err(make_error!(Code::BrokenPipe)))
+                } else if p.len < PIPE_BUF_SIZE {
+                    let n = p.push(&buf[written..]);
+                    p.wake_all();
+                    Some(Ok(n))
+                } else {
+                    None
+                }
+            })?;
+        }
+        Ok(written)
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        asmfunc::cli();
+        let mut p = self.0.lock_wait();
+        p.readers -= 1;
+        p.wake_all();
+        drop(p);
+        asmfunc::sti();
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        asmfunc::cli();
+        let mut p = self.0.lock_wait();
+        p.writers -= 1;
+        p.wake_all();
+        drop(p);
+        asmfunc::sti();
+    }
+}
@@ -0,0 +1,216 @@
+//! `/proc` にマウントされる、カーネルの状態を読み出すための読み込み専用ファイルシステム。
+//!
+//! ファイルの内容は開いた時点で生成する。
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::fmt::Write as _;
+
+use crate::{
+    asmfunc,
+    error::{Code, Result},
+    fat::Attribute,
+    file::{DirEntryInfo, FileDescriptor},
+    layer::LAYER_MANAGER,
+    logger, make_error,
+    memory_manager::{BYTES_PER_FRAME, MEMORY_MANAGER},
+    pci,
+    task::{self, Task},
+    timer::{TIMER_FREQ, TIMER_MANAGER},
+};
+
+/// ファイル名と、その内容を生成する関数の組。
+type ProcFile = (&'static str, fn() -> String);
+/// ファイル名と、タスクからその内容を生成する関数の組。
+type TaskFile = (&'static str, fn(&Task) -> String);
+
+/// `/proc` 直下に置かれるファイル。
+const ROOT_FILES: [ProcFile; 5] = [
+    ("meminfo", meminfo),
+    ("compositor", compositor),
+    ("pci", pci_devices),
+    ("uptime", uptime),
+    ("log", log),
+];
+
+/// `/proc/<task_id>` に置かれるファイル。
+const TASK_FILES: [TaskFile; 3] = [
+    ("status", task_status),
+    ("fds", task_fds),
+    ("memory", task_memory),
+];
+
+/// `/proc` 以降のパス `path` が指すファイルもしくはディレクトリを開く。
+pub fn open(path: &str) -> Result<FileDescriptor> {
+    let post_slash = path.len() > 1 && path.ends_with('/');
+    let elems: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    let fd = match elems[..] {
+        [] => FileDescriptor::new_virtual_dir(root_entries()),
+        [name] => {
+            if find_task(name).is_some() {
+                FileDescriptor::new_virtual_dir(task_entries())
+            } else if let Some((_, f)) = ROOT_FILES.iter().find(|(n, _)| *n == name) {
+                if post_slash {
+                    return Err(make_error!(Code::NotDirectory));
+                }
+                FileDescriptor::new_snapshot(f().into_bytes())
+            } else {
+                return Err(make_error!(Code::NoSuchEntry));
+            }
+        }
+        [task_name, name] => {
+            let Some(task) = find_task(task_name) else {
+                return Err(make_error!(Code::NoSuchEntry));
+            };
+            let Some((_, f)) = TASK_FILES.iter().find(|(n, _)| *n == name) else {
+                return Err(make_error!(Code::NoSuchEntry));
+            };
+            if post_slash {
+                return Err(make_error!(Code::NotDirectory));
+            }
+            FileDescriptor::new_snapshot(f(&task).into_bytes())
+        }
+        _ => return Err(make_error!(Code::NoSuchEntry)),
+    };
+    Ok(fd)
+}
+
+/// PCI デバイスの一覧を `lspci` の形式で返す。
+pub fn pci_devices() -> String {
+    let mut s = String::new();
+    for dev in pci::DEVICES.read().iter() {
+        let vendor_id = dev.read_vendor_id();
+        let _ = writeln!(
+            s,
+            "{:02x}:{:02x}.{} vend={:04x} head={:02x} class={:02x}.{:02x}:{:02x}",
+            dev.bus(),
+            dev.device(),
+            dev.function(),
+            vendor_id,
+            dev.header_type(),
+            dev.class_code().base(),
+            dev.class_code().sub(),
+            dev.class_code().interface(),
+        );
+    }
+    s
+}
+
+fn root_entries() -> Vec<DirEntryInfo> {
+    let dir = Attribute::Directory as u8;
+    let mut entries: Vec<_> = ROOT_FILES
+        .iter()
+        .map(|(name, _)| DirEntryInfo::new(name, 0, 0))
+        .collect();
+    entries.push(DirEntryInfo::new("self", dir, 0));
+    for task in all_tasks() {
+        entries.push(DirEntryInfo::new(&format!("{}", task.id()), dir, 0));
+    }
+    entries
+}
+
+fn task_entries() -> Vec<DirEntryInfo> {
+    TASK_FILES
+        .iter()
+        .map(|(name, _)| DirEntryInfo::new(name, 0, 0))
+        .collect()
+}
+
+/// `name` がタスク ID もしくは `self` の場合、そのタスクを返す。
+fn find_task(name: &str) -> Option<Arc<Task>> {
+    asmfunc::cli();
+    let task = if name == "self" {
+        Some(task::current_task())
+    } else {
+        name.parse().ok().and_then(task::get_task)
+    };
+    asmfunc::sti();
+    task
+}
+
+fn all_tasks() -> Vec<Arc<Task>> {
+    asmfunc::cli();
+    let tasks = task::tasks();
+    asmfunc::sti();
+    tasks
+}
+
+fn meminfo() -> String {
+    let stat = MEMORY_MANAGER.stat();
+    format!(
+        "MemTotal: {} kB\nMemUsed: {} kB\nMemFree: {} kB\n",
+        (stat.total_frames * BYTES_PER_FRAME) >> 10,
+        (stat.allocated_frames * BYTES_PER_FRAME) >> 10,
+        ((stat.total_frames - stat.allocated_frames) * BYTES_PER_FRAME) >> 10,
+    )
+}
+
+fn uptime() -> String {
+    let tick = TIMER_MANAGER.lock_wait().current_tick();
+    format!(
+        "{}.{:02}\n",
+        tick / TIMER_FREQ,
+        tick % TIMER_FREQ * 100 / TIMER_FREQ
+    )
+}
+
+/// 画面の合成の回数と量。
+fn compositor() -> String {
+    let (frames, pixels, presented) = LAYER_MANAGER.lock_wait().compose_stats();
+    format!(
+        "Frames: {}\nPixels: {}\nPresented: {}\n",
+        frames, pixels, presented
+    )
+}
+
+fn log() -> String {
+    String::from_utf8_lossy(&logger::log_buffer()).into_owned()
+}
+
+fn task_status(task: &Task) -> String {
+    let state = if task.is_running() {
+        "running"
+    } else {
+        "sleeping"
+    };
+    format!(
+        "id: {}\nstate: {}\nlevel: {}\ncwd: {}\n",
+        task.id(),
+        state,
+        task.run_level(),
+        task.cwd(),
+    )
+}
+
+fn task_fds(task: &Task) -> String {
+    let files = task.files().lock_wait();
+    let fds: Vec<_> = (0..files.cap() as i32)
+        .filter_map(|fd| files.get(&fd).map(|file| (fd, file.clone())))
+        .collect();
+    drop(files);
+
+    let mut s = String::new();
+    for (fd, file) in fds {
+        // パイプの読み書きでブロックしているファイルはロックが取れないので、待たずに飛ばす
+        let kind = file.lock().map_or("busy", |f| f.kind());
+        let _ = writeln!(s, "{}: {}", fd, kind);
+    }
+    s
+}
+
+fn task_memory(task: &Task) -> String {
+    let mut s = format!(
+        "demand_paging: {:#x}-{:#x}\napp_stack_size: {} kB\n",
+        task.dpaging_begin(),
+        task.dpaging_end(),
+        task.app_stack_size() >> 10,
+    );
+    for map in task.file_maps().lock_wait().iter() {
+        let _ = writeln!(
+            s,
+            "file_map: {:#x}-{:#x} fd={}",
+            map.vaddr_begin, map.vaddr_end, map.fd
+        );
+    }
+    s
+}
@@ -0,0 +1,241 @@
+//! CMOS の RTC (Real Time Clock) から日時を得る。
+//!
+//! RTC を読むのは起動時の 1 度だけで、それ以降は起動時の時刻にタイマの経過時間を足して求める。
+//! RTC は UTC を保持しているものとして扱う。
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+use crate::{
+    asmfunc,
+    timer::{TIMER_FREQ, TIMER_MANAGER},
+};
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+/// CMOS のレジスタ番号。
+mod reg {
+    pub const SECOND: u8 = 0x00;
+    pub const MINUTE: u8 = 0x02;
+    pub const HOUR: u8 = 0x04;
+    pub const DAY: u8 = 0x07;
+    pub const MONTH: u8 = 0x08;
+    pub const YEAR: u8 = 0x09;
+    pub const STATUS_A: u8 = 0x0a;
+    pub const STATUS_B: u8 = 0x0b;
+}
+
+/// UNIX 時間を表す時計の ID。
+pub const CLOCK_REALTIME: u64 = 0;
+/// 起動時からの経過時間を表す時計の ID。
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/// 起動時の UNIX 時間（秒）。
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+/// [BOOT_TIME] を読んだときのタイマカウント。
+static BOOT_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// `clock_gettime` システムコールでアプリに渡す時刻。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+/// 日時を表す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 から 12。
+    pub month: u8,
+    /// 1 から 31。
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// UNIX 時間 `t`（秒）を日時に変換する。
+    pub fn from_unix(t: u64) -> Self {
+        let days = (t / 86400) as i64;
+        let secs = t % 86400;
+
+        // cf. http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+
+        Self {
+            year: year as _,
+            month: month as _,
+            day: day as _,
+            hour: (secs / 3600) as _,
+            minute: (secs / 60 % 60) as _,
+            second: (secs % 60) as _,
+        }
+    }
+
+    /// UNIX 時間（秒）に変換する。
+    pub fn to_unix(&self) -> u64 {
+        // cf. http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let month = self.month as i64;
+        let year = self.year as i64 - (month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let secs = self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        (days * 86400 + secs).max(0) as u64
+    }
+
+    /// FAT のディレクトリエントリの日付の形式から変換する。
+    pub fn from_fat(date: u16, time: u16) -> Self {
+        Self {
+            year: 1980 + (date >> 9),
+            month: (date >> 5 & 0x0f) as _,
+            day: (date & 0x1f) as _,
+            hour: (time >> 11) as _,
+            minute: (time >> 5 & 0x3f) as _,
+            second: ((time & 0x1f) * 2) as _,
+        }
+    }
+
+    /// FAT のディレクトリエントリの日付の形式に変換する。
+    pub fn fat_date(&self) -> u16 {
+        (self.year.saturating_sub(1980) << 9) | (self.month as u16) << 5 | self.day as u16
+    }
+
+    /// FAT のディレクトリエントリの時刻の形式に変換する。
+    /// 秒は 2 秒単位に切り捨てられる。
+    pub fn fat_time(&self) -> u16 {
+        (self.hour as u16) << 11 | (self.minute as u16) << 5 | (self.second / 2) as u16
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// RTC から現在の日時を読み込み、起動時の時刻として記録する。
+///
+/// タイマの初期化後に呼び出すこと。
+pub fn init() {
+    let now = read_rtc();
+    BOOT_TICK.store(TIMER_MANAGER.lock_wait().current_tick(), Relaxed);
+    BOOT_TIME.store(now.to_unix(), Relaxed);
+}
+
+/// `clock_id` が表す時計の現在時刻を返す。
+/// 存在しない時計の場合は `None` を返す。
+pub fn clock_gettime(clock_id: u64) -> Option<Timespec> {
+    let tick = TIMER_MANAGER.lock_wait().current_tick();
+    let (base, elapsed) = match clock_id {
+        CLOCK_REALTIME => (BOOT_TIME.load(Relaxed), tick - BOOT_TICK.load(Relaxed)),
+        CLOCK_MONOTONIC => (0, tick),
+        _ => return None,
+    };
+    Some(Timespec {
+        tv_sec: (base + elapsed / TIMER_FREQ) as _,
+        tv_nsec: (elapsed % TIMER_FREQ * (1_000_000_000 / TIMER_FREQ)) as _,
+    })
+}
+
+/// 現在の UNIX 時間（秒）を返す。
+pub fn unix_time() -> u64 {
+    let elapsed = TIMER_MANAGER.lock_wait().current_tick() - BOOT_TICK.load(Relaxed);
+    BOOT_TIME.load(Relaxed) + elapsed / TIMER_FREQ
+}
+
+/// 現在の日時を返す。
+pub fn now() -> DateTime {
+    DateTime::from_unix(unix_time())
+}
+
+fn read_cmos(reg: u8) -> u8 {
+    asmfunc::io_out_8(CMOS_ADDRESS, reg);
+    asmfunc::io_in_8(CMOS_DATA)
+}
+
+fn is_updating() -> bool {
+    read_cmos(reg::STATUS_A) & 0x80 != 0
+}
+
+/// 更新中でないときに RTC の各レジスタを読む。
+fn read_raw() -> [u8; 6] {
+    while is_updating() {
+        core::hint::spin_loop();
+    }
+    [
+        reg::SECOND,
+        reg::MINUTE,
+        reg::HOUR,
+        reg::DAY,
+        reg::MONTH,
+        reg::YEAR,
+    ]
+    .map(read_cmos)
+}
+
+fn read_rtc() -> DateTime {
+    // 読んでいる途中で値が更新されることがあるので、同じ値が 2 回続けて読めるまで繰り返す
+    let mut raw = read_raw();
+    loop {
+        let next = read_raw();
+        if next == raw {
+            break;
+        }
+        raw = next;
+    }
+    let [second, minute, hour, day, month, year] = raw;
+
+    let status_b = read_cmos(reg::STATUS_B);
+    let is_binary = status_b & 0x04 != 0;
+    let is_24hour = status_b & 0x02 != 0;
+    let decode = |v: u8| {
+        if is_binary {
+            v
+        } else {
+            (v >> 4) * 10 + (v & 0x0f)
+        }
+    };
+
+    // 12 時間表記の場合、最上位ビットが PM を表す
+    let pm = hour & 0x80 != 0;
+    let mut hour = decode(hour & 0x7f);
+    if !is_24hour {
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    DateTime {
+        // 世紀のレジスタは機種依存なので、2000 年代とする
+        year: 2000 + decode(year) as u16,
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+    }
+}
@@ -0,0 +1,970 @@
+//! ターミナルに入力されたコマンドラインを解釈する。
+//!
+//! コマンドラインやスクリプトはまず [parse] で制御構文、単語、リダイレクトに分解し、
+//! コマンドを実行する直前に [expand] で変数、コマンド置換、グロブを展開する。
+
+use alloc::{string::String, vec::Vec};
+use core::{iter::Peekable, mem, str::Chars};
+
+use crate::{
+    fat::Attribute,
+    file::{DirEntryInfo, FileFlags, ATTR_FIFO},
+    vfs,
+};
+
+/// 単語を構成する要素。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    /// 引用符の外にある文字列。グロブの対象になる。
+    Bare(String),
+    /// 引用符の中にある、もしくはエスケープされた文字列。
+    Quoted(String),
+    /// `$NAME`、`${NAME}`、`$?`、`$$`、`$1`、`$#`、`$@` などによる変数の参照。
+    Var { name: String, quoted: bool },
+    /// `$(...)` もしくは `` `...` `` によるコマンド置換。
+    Command { line: String, quoted: bool },
+}
+
+/// 展開される前の単語。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word(pub Vec<Part>);
+
+/// リダイレクトの指定。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    /// `< path`: ファイルを標準入力にする。
+    Input(Word),
+    /// `> path`: ファイルを切り詰めて標準出力にする。
+    Output(Word),
+    /// `>> path`: ファイルの末尾に標準出力を追記する。
+    Append(Word),
+    /// `2> path`: ファイルを切り詰めて標準エラー出力にする。
+    Error(Word),
+    /// `2>> path`: ファイルの末尾に標準エラー出力を追記する。
+    ErrorAppend(Word),
+    /// `2>&1`: 標準エラー出力をその時点の標準出力と同じにする。
+    ErrorToOutput,
+}
+
+/// パイプで繋がれたコマンドのうちの 1 つ。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stage {
+    /// コマンド名と引数。
+    pub args: Vec<Word>,
+    /// 指定された順に並んだリダイレクト。
+    pub redirects: Vec<Redirect>,
+}
+
+/// 単語の展開に必要な情報を与える。
+pub trait Expander {
+    /// 変数 `name` の値を返す。`?`、`$`、`#`、`@`、`*` と位置パラメータも含む。
+    fn var(&self, name: &str) -> Option<String>;
+    /// コマンドライン `line` を実行し、その標準出力を返す。
+    fn command_output(&mut self, line: &str) -> String;
+    /// パス `path` をカレントディレクトリを基準に絶対パスにする。
+    fn resolve_path(&self, path: &str) -> String;
+}
+
+/// `;` もしくは改行で区切られたコマンドの列。
+pub type List = Vec<AndOr>;
+
+/// `&&` もしくは `||` で繋がれたコマンドの列。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AndOr {
+    pub first: Command,
+    /// 後続のコマンドと、その直前の演算子。
+    pub rest: Vec<(Connector, Command)>,
+}
+
+/// コマンドを繋ぐ演算子。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&`: 直前のコマンドが成功した場合のみ実行する。
+    And,
+    /// `||`: 直前のコマンドが失敗した場合のみ実行する。
+    Or,
+}
+
+/// 1 つのコマンド。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// パイプで繋がれた単純なコマンドの列。
+    Pipeline(Vec<Stage>),
+    /// `if cond; then body; elif cond; then body; else body; fi`
+    If {
+        /// 条件と、それが成功した場合に実行する本体の組。
+        branches: Vec<(List, List)>,
+        /// どの条件も成功しなかった場合に実行する本体。
+        otherwise: Option<List>,
+    },
+    /// `for name in words; do body; done`
+    For {
+        name: String,
+        words: Vec<Word>,
+        body: List,
+    },
+    /// `while cond; do body; done`。`until` の場合は条件が失敗する間繰り返す。
+    While { cond: List, body: List, until: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(Word),
+    Pipe,
+    /// `;`
+    Semi,
+    /// 改行
+    Newline,
+    /// `&&`
+    AndIf,
+    /// `||`
+    OrIf,
+    /// `<`
+    Less,
+    /// `>`
+    Great,
+    /// `>>`
+    DGreat,
+    /// `2>`
+    ErrGreat,
+    /// `2>>`
+    ErrDGreat,
+    /// `2>&1`
+    ErrToOut,
+}
+
+/// コマンドの先頭でのみ意味を持つ予約語。
+const KEYWORDS: [&str; 10] = [
+    "if", "then", "elif", "else", "fi", "for", "do", "done", "while", "until",
+];
+
+/// コマンドラインもしくはスクリプトを、コマンドの列に分解する。
+///
+/// 空の行やコメントだけの行に対しては空の列を返す。
+/// 構文が正しくない場合は、その理由を返す。
+pub fn parse(line: &str) -> Result<List, &'static str> {
+    let mut parser = Parser {
+        tokens: tokenize(line)?,
+        pos: 0,
+    };
+    parser.list(&[])
+}
+
+/// 字句の列を先頭から読む再帰下降パーサ。
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// 次の字句が引用符やエスケープを含まない単語であれば、その文字列を返す。
+    fn bare(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(Word(parts))) => match parts.as_slice() {
+                [Part::Bare(s)] => Some(s),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn keyword(&self) -> Option<&str> {
+        self.bare().filter(|s| KEYWORDS.contains(s))
+    }
+
+    /// 次の字句が予約語 `kw` であれば読み飛ばし、そうでなければ `err` を返す。
+    fn expect(&mut self, kw: &str, err: &'static str) -> Result<(), &'static str> {
+        if self.keyword() != Some(kw) {
+            return Err(if self.peek().is_none() {
+                "unexpected end of input"
+            } else {
+                err
+            });
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    /// 入力の終わりか、`ends` に含まれる予約語の手前までをコマンドの列として読む。
+    fn list(&mut self, ends: &[&str]) -> Result<List, &'static str> {
+        let mut list = Vec::new();
+        loop {
+            while matches!(self.peek(), Some(Token::Semi | Token::Newline)) {
+                self.pos += 1;
+            }
+            if self.peek().is_none() || self.keyword().is_some_and(|kw| ends.contains(&kw)) {
+                return Ok(list);
+            }
+
+            list.push(self.and_or()?);
+            match self.peek() {
+                None | Some(Token::Semi | Token::Newline) => {}
+                // `fi fi` のように、複合コマンドの直後には区切りが無くてもよい
+                Some(_) if self.keyword().is_some_and(|kw| ends.contains(&kw)) => {}
+                Some(_) => return Err("unexpected keyword"),
+            }
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, &'static str> {
+        let first = self.command()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek() {
+                Some(Token::AndIf) => Connector::And,
+                Some(Token::OrIf) => Connector::Or,
+                _ => break,
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((connector, self.command()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn command(&mut self) -> Result<Command, &'static str> {
+        let Some(kw) = self.keyword() else {
+            return self.pipeline().map(Command::Pipeline);
+        };
+        let until = kw == "until";
+        match kw {
+            "if" => {
+                self.pos += 1;
+                self.if_clause()
+            }
+            "for" => {
+                self.pos += 1;
+                self.for_clause()
+            }
+            "while" | "until" => {
+                self.pos += 1;
+                let cond = self.list(&["do"])?;
+                if cond.is_empty() {
+                    return Err("missing loop condition");
+                }
+                self.expect("do", "missing `do`")?;
+                let body = self.list(&["done"])?;
+                self.expect("done", "missing `done`")?;
+                Ok(Command::While { cond, body, until })
+            }
+            _ => Err("unexpected keyword"),
+        }
+    }
+
+    fn if_clause(&mut self) -> Result<Command, &'static str> {
+        let mut branches = Vec::new();
+        loop {
+            let cond = self.list(&["then"])?;
+            if cond.is_empty() {
+                return Err("missing condition after `if`");
+            }
+            self.expect("then", "missing `then`")?;
+            let body = self.list(&["elif", "else", "fi"])?;
+            branches.push((cond, body));
+
+            if self.keyword() != Some("elif") {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        let otherwise = if self.keyword() == Some("else") {
+            self.pos += 1;
+            Some(self.list(&["fi"])?)
+        } else {
+            None
+        };
+        self.expect("fi", "missing `fi`")?;
+        Ok(Command::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn for_clause(&mut self) -> Result<Command, &'static str> {
+        let name = match self.bare() {
+            Some(name) if is_var_name(name) => name.into(),
+            _ => return Err("bad variable name after `for`"),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+
+        if self.bare() != Some("in") {
+            return Err("missing `in`");
+        }
+        self.pos += 1;
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = self.peek() {
+            words.push(word.clone());
+            self.pos += 1;
+        }
+        if !matches!(self.peek(), Some(Token::Semi | Token::Newline)) {
+            return Err("missing `do`");
+        }
+        self.pos += 1;
+        self.skip_newlines();
+
+        self.expect("do", "missing `do`")?;
+        let body = self.list(&["done"])?;
+        self.expect("done", "missing `done`")?;
+        Ok(Command::For { name, words, body })
+    }
+
+    /// `|` で区切られたコマンドの列を読む。
+    fn pipeline(&mut self) -> Result<Vec<Stage>, &'static str> {
+        let mut stages = Vec::new();
+        let mut stage = Stage::default();
+        while let Some(token) = self.peek().cloned() {
+            let redirect: fn(Word) -> Redirect = match token {
+                Token::Semi | Token::Newline | Token::AndIf | Token::OrIf => break,
+                Token::Word(word) => {
+                    self.pos += 1;
+                    stage.args.push(word);
+                    continue;
+                }
+                Token::Pipe => {
+                    self.pos += 1;
+                    if stage.args.is_empty() {
+                        return Err("missing command before `|`");
+                    }
+                    stages.push(mem::take(&mut stage));
+                    self.skip_newlines();
+                    continue;
+                }
+                Token::ErrToOut => {
+                    self.pos += 1;
+                    stage.redirects.push(Redirect::ErrorToOutput);
+                    continue;
+                }
+                Token::Less => Redirect::Input,
+                Token::Great => Redirect::Output,
+                Token::DGreat => Redirect::Append,
+                Token::ErrGreat => Redirect::Error,
+                Token::ErrDGreat => Redirect::ErrorAppend,
+            };
+
+            self.pos += 1;
+            let Some(Token::Word(path)) = self.peek().cloned() else {
+                return Err("missing file name after redirection");
+            };
+            self.pos += 1;
+            stage.redirects.push(redirect(path));
+        }
+
+        if stage.args.is_empty() && !stages.is_empty() {
+            return Err("missing command after `|`");
+        }
+        if stage.args.is_empty() && stage.redirects.is_empty() {
+            return Err("missing command");
+        }
+        stages.push(stage);
+        Ok(stages)
+    }
+}
+
+/// `NAME=value` の形の単語であれば、変数名と値を表す単語に分ける。
+pub fn split_assignment(word: &Word) -> Option<(String, Word)> {
+    let Some((Part::Bare(first), rest)) = word.0.split_first() else {
+        return None;
+    };
+    let (name, value) = first.split_once('=')?;
+    if !is_var_name(name) {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    if !value.is_empty() {
+        parts.push(Part::Bare(value.into()));
+    }
+    parts.extend_from_slice(rest);
+    Some((name.into(), Word(parts)))
+}
+
+/// 単語の列を展開して引数の列にする。
+///
+/// 引用符の外にある変数とコマンド置換の結果は空白で区切って別々の引数にする。
+/// 引用符の外にワイルドカードを含む単語は、一致するパスを名前順に並べたものに置き換える。
+/// 一致するパスが無い場合は、そのまま残す。
+pub fn expand(words: &[Word], ex: &mut impl Expander) -> Vec<String> {
+    let mut args = Vec::new();
+    for word in words {
+        let mut fields = Vec::new();
+        let mut cur = None;
+        for part in &word.0 {
+            match part {
+                Part::Bare(s) => cur.get_or_insert_with(Field::default).push_bare(s),
+                Part::Quoted(s) => cur.get_or_insert_with(Field::default).push_quoted(s),
+                Part::Var { quoted, .. } | Part::Command { quoted, .. } => {
+                    let value = expand_part(part, ex);
+                    if *quoted {
+                        cur.get_or_insert_with(Field::default).push_quoted(&value);
+                        continue;
+                    }
+
+                    // 空白の前後で引数を区切る
+                    for (i, piece) in value.split([' ', '\t', '\n']).enumerate() {
+                        if i > 0 {
+                            fields.extend(cur.take());
+                        }
+                        if !piece.is_empty() {
+                            cur.get_or_insert_with(Field::default).push_quoted(piece);
+                        }
+                    }
+                }
+            }
+        }
+        fields.extend(cur);
+
+        for field in fields {
+            let mut matched = if field.has_glob {
+                glob(&field.pattern, ex)
+            } else {
+                Vec::new()
+            };
+            if matched.is_empty() {
+                args.push(field.text);
+            } else {
+                matched.sort();
+                args.append(&mut matched);
+            }
+        }
+    }
+    args
+}
+
+/// 単語を、引数の区切りやグロブの展開を行わずに 1 つの文字列に展開する。
+///
+/// リダイレクト先や変数への代入に使う。
+pub fn expand_single(word: &Word, ex: &mut impl Expander) -> String {
+    word.0.iter().map(|part| expand_part(part, ex)).collect()
+}
+
+/// 引数を、[parse] で元の文字列に戻るように引用符で囲む。
+pub fn quote(arg: &str) -> String {
+    let mut s = String::from("'");
+    for c in arg.chars() {
+        if c == '\'' {
+            s.push_str("'\\''");
+        } else {
+            s.push(c);
+        }
+    }
+    s.push('\'');
+    s
+}
+
+fn expand_part(part: &Part, ex: &mut impl Expander) -> String {
+    match part {
+        Part::Bare(s) | Part::Quoted(s) => s.clone(),
+        Part::Var { name, .. } => ex.var(name).unwrap_or_default(),
+        Part::Command { line, .. } => {
+            let mut output = ex.command_output(line);
+            // 末尾の改行は取り除く
+            output.truncate(output.trim_end_matches('\n').len());
+            output
+        }
+    }
+}
+
+/// `name` が変数名として使えるかを返す。
+pub fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `test` コマンドの式 `args` を評価する。
+///
+/// ファイルの検査 (`-e`、`-f`、`-d`、`-p`)、文字列の比較 (`-n`、`-z`、`=`、`!=`)、
+/// 整数の比較 (`-eq`、`-ne`、`-lt`、`-le`、`-gt`、`-ge`) と、先頭の `!` による否定を扱う。
+/// 式が正しくない場合は、その理由を返す。
+pub fn test(args: &[&str], ex: &impl Expander) -> Result<bool, &'static str> {
+    let attr = |path: &str| file_attr(&ex.resolve_path(path));
+    let int = |s: &str| s.parse::<i64>().map_err(|_| "integer expression expected");
+    let dir = Attribute::Directory as u8;
+
+    match args {
+        [] => Ok(false),
+        ["!", rest @ ..] => test(rest, ex).map(|b| !b),
+        [s] => Ok(!s.is_empty()),
+        ["-n", s] => Ok(!s.is_empty()),
+        ["-z", s] => Ok(s.is_empty()),
+        ["-e", path] => Ok(attr(path).is_some()),
+        ["-f", path] => Ok(attr(path).is_some_and(|a| a & (dir | ATTR_FIFO) == 0)),
+        ["-d", path] => Ok(attr(path).is_some_and(|a| a & dir != 0)),
+        ["-p", path] => Ok(attr(path).is_some_and(|a| a & ATTR_FIFO != 0)),
+        [_, _] => Err("unary operator expected"),
+        [a, "=" | "==", b] => Ok(a == b),
+        [a, "!=", b] => Ok(a != b),
+        [a, op @ ("-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"), b] => {
+            let (a, b) = (int(a)?, int(b)?);
+            Ok(match *op {
+                "-eq" => a == b,
+                "-ne" => a != b,
+                "-lt" => a < b,
+                "-le" => a <= b,
+                "-gt" => a > b,
+                _ => a >= b,
+            })
+        }
+        [_, _, _] => Err("binary operator expected"),
+        _ => Err("too many arguments"),
+    }
+}
+
+/// `name` が `$?` や `$1` のような特殊な変数の名前かを返す。
+fn is_special_var(name: &str) -> bool {
+    matches!(name, "?" | "$" | "#" | "@" | "*")
+        || (!name.is_empty() && name.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 展開途中の 1 つの引数。
+#[derive(Default)]
+struct Field {
+    /// 展開後の文字列。
+    text: String,
+    /// グロブのパターン。引用符の中にあったワイルドカードはエスケープされている。
+    pattern: String,
+    /// 引用符の外にワイルドカードを含むかどうか。
+    has_glob: bool,
+}
+
+impl Field {
+    fn push_bare(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.pattern.push_str(s);
+        self.has_glob |= s.contains(['*', '?', '[']);
+    }
+
+    fn push_quoted(&mut self, s: &str) {
+        self.text.push_str(s);
+        for c in s.chars() {
+            if matches!(c, '*' | '?' | '[' | '\\') {
+                self.pattern.push('\\');
+            }
+            self.pattern.push(c);
+        }
+    }
+}
+
+/// 字句解析中の単語。
+#[derive(Default)]
+struct WordBuilder {
+    parts: Vec<Part>,
+    /// 空の引用符だけでも単語になるので、要素の有無とは別に持つ。
+    started: bool,
+}
+
+impl WordBuilder {
+    fn push_bare(&mut self, c: char) {
+        self.started = true;
+        match self.parts.last_mut() {
+            Some(Part::Bare(s)) => s.push(c),
+            _ => self.parts.push(Part::Bare(c.into())),
+        }
+    }
+
+    fn push_quoted(&mut self, c: char) {
+        self.started = true;
+        match self.parts.last_mut() {
+            Some(Part::Quoted(s)) => s.push(c),
+            _ => self.parts.push(Part::Quoted(c.into())),
+        }
+    }
+
+    fn push(&mut self, part: Part) {
+        self.started = true;
+        self.parts.push(part);
+    }
+
+    fn is_bare(&self, s: &str) -> bool {
+        matches!(self.parts.as_slice(), [Part::Bare(b)] if b == s)
+    }
+
+    fn finish(&mut self, tokens: &mut Vec<Token>) {
+        if mem::take(&mut self.started) {
+            tokens.push(Token::Word(Word(mem::take(&mut self.parts))));
+        }
+    }
+}
+
+/// 空白で区切り、引用符とエスケープを解釈し、演算子を切り出す。
+///
+/// `2>`、`2>>`、`2>&1` は単語の先頭にある場合のみ演算子として扱う。
+fn tokenize(line: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut word = WordBuilder::default();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => word.finish(&mut tokens),
+            // 単語の先頭の `#` から行末まではコメント
+            '#' if !word.started => while chars.next_if(|&c| c != '\n').is_some() {},
+            '\n' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::Newline);
+            }
+            ';' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::Semi);
+            }
+            '|' => {
+                word.finish(&mut tokens);
+                if chars.next_if_eq(&'|').is_some() {
+                    tokens.push(Token::OrIf);
+                } else {
+                    tokens.push(Token::Pipe);
+                }
+            }
+            '&' if chars.next_if_eq(&'&').is_some() => {
+                word.finish(&mut tokens);
+                tokens.push(Token::AndIf);
+            }
+            '<' => {
+                word.finish(&mut tokens);
+                tokens.push(Token::Less);
+            }
+            '>' => {
+                let is_err = word.is_bare("2");
+                if is_err {
+                    word = WordBuilder::default();
+                }
+                word.finish(&mut tokens);
+
+                let append = chars.next_if_eq(&'>').is_some();
+                let token = match (is_err, append) {
+                    (false, false) => Token::Great,
+                    (false, true) => Token::DGreat,
+                    (true, true) => Token::ErrDGreat,
+                    (true, false) => {
+                        let mut lookahead = chars.clone();
+                        if lookahead.next() == Some('&') && lookahead.next() == Some('1') {
+                            chars = lookahead;
+                            Token::ErrToOut
+                        } else {
+                            Token::ErrGreat
+                        }
+                    }
+                };
+                tokens.push(token);
+            }
+            '\\' => {
+                // 行末のバックスラッシュは無視する
+                if let Some(c) = chars.next() {
+                    word.push_quoted(c);
+                }
+            }
+            '\'' => {
+                word.started = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push_quoted(c),
+                        None => return Err("unterminated quote"),
+                    }
+                }
+            }
+            '"' => {
+                word.started = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next_if(|&c| matches!(c, '$' | '`' | '"' | '\\'))
+                        {
+                            Some(c) => word.push_quoted(c),
+                            None => word.push_quoted('\\'),
+                        },
+                        Some('$') => match dollar(&mut chars, true)? {
+                            Some(part) => word.push(part),
+                            None => word.push_quoted('$'),
+                        },
+                        Some('`') => word.push(Part::Command {
+                            line: backquote(&mut chars)?,
+                            quoted: true,
+                        }),
+                        Some(c) => word.push_quoted(c),
+                        None => return Err("unterminated quote"),
+                    }
+                }
+            }
+            '$' => match dollar(&mut chars, false)? {
+                Some(part) => word.push(part),
+                None => word.push_bare('$'),
+            },
+            '`' => word.push(Part::Command {
+                line: backquote(&mut chars)?,
+                quoted: false,
+            }),
+            c => word.push_bare(c),
+        }
+    }
+    word.finish(&mut tokens);
+    Ok(tokens)
+}
+
+/// `$` の後ろを読み、変数の参照もしくはコマンド置換を返す。
+/// どちらでもない場合は `None` を返し、`$` はそのままの文字として扱う。
+fn dollar(chars: &mut Peekable<Chars>, quoted: bool) -> Result<Option<Part>, &'static str> {
+    let var = |name: String| Ok(Some(Part::Var { name, quoted }));
+    match chars.peek() {
+        Some('{') => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err("unterminated `${`"),
+                }
+            }
+            if !is_var_name(&name) && !is_special_var(&name) {
+                return Err("bad substitution");
+            }
+            var(name)
+        }
+        Some(&c @ ('?' | '$' | '#' | '@' | '*' | '0'..='9')) => {
+            chars.next();
+            var(c.into())
+        }
+        Some('(') => {
+            chars.next();
+            let line = subshell(chars)?;
+            Ok(Some(Part::Command { line, quoted }))
+        }
+        Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+                name.push(c);
+            }
+            var(name)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `$(` の後ろから対応する `)` の手前までを返す。
+fn subshell(chars: &mut Peekable<Chars>) -> Result<String, &'static str> {
+    let mut line = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                line.push(c);
+                if let Some(c) = chars.next() {
+                    line.push(c);
+                }
+                continue;
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => return Ok(line),
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+        line.push(c);
+    }
+    Err("unterminated `$(`")
+}
+
+/// `` ` `` の後ろから次の `` ` `` の手前までを返す。
+fn backquote(chars: &mut Peekable<Chars>) -> Result<String, &'static str> {
+    let mut line = String::new();
+    loop {
+        match chars.next() {
+            Some('`') => return Ok(line),
+            Some('\\') => match chars.next_if_eq(&'`') {
+                Some(c) => line.push(c),
+                None => line.push('\\'),
+            },
+            Some(c) => line.push(c),
+            None => return Err("unterminated quote"),
+        }
+    }
+}
+
+/// グロブのパターン `pattern` に一致する、存在するパスを返す。
+fn glob(pattern: &str, ex: &impl Expander) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (Vec::from([String::from("/")]), rest),
+        None => (Vec::from([String::new()]), pattern),
+    };
+
+    let components: Vec<_> = rest.split('/').filter(|s| !s.is_empty()).collect();
+    for (i, &component) in components.iter().enumerate() {
+        let is_last = i + 1 == components.len();
+        let mut next = Vec::new();
+        for base in paths {
+            if !has_wildcard(component) {
+                next.push(base + &unescape(component));
+                if !is_last {
+                    next.last_mut().unwrap().push('/');
+                }
+                continue;
+            }
+
+            let dir = if base.is_empty() {
+                ex.resolve_path(".")
+            } else {
+                ex.resolve_path(&base)
+            };
+            let ignore_case = vfs::ignores_case(&dir);
+            let pattern: Vec<_> = component.chars().collect();
+            for entry in read_entries(&dir) {
+                let name = entry.name();
+                // 隠しファイルは、パターンが `.` で始まる場合のみ一致させる
+                if name == "." || name == ".." || (name.starts_with('.') && pattern[0] != '.') {
+                    continue;
+                }
+                let is_dir = entry.attr & Attribute::Directory as u8 != 0;
+                if (is_last || is_dir)
+                    && matches(&pattern, &name.chars().collect::<Vec<_>>(), ignore_case)
+                {
+                    let mut path = base.clone() + name;
+                    if !is_last {
+                        path.push('/');
+                    }
+                    next.push(path);
+                }
+            }
+        }
+        paths = next;
+    }
+
+    // ワイルドカードを含まない要素は存在を確かめていないので、最後に確かめる
+    paths.retain(|path| file_attr(&ex.resolve_path(path)).is_some());
+    paths
+}
+
+/// 絶対パス `path` が存在すれば、その属性を返す。
+fn file_attr(path: &str) -> Option<u8> {
+    vfs::stat(path).ok().map(|entry| entry.attr)
+}
+
+/// ディレクトリ `dir` に含まれるエントリを全て返す。
+pub fn read_entries(dir: &str) -> Vec<DirEntryInfo> {
+    let Ok(mut fd) = vfs::open(dir, FileFlags::RDONLY) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+    let mut buf = [DirEntryInfo::new("", 0, 0); 8];
+    while let Ok(n @ 1..) = fd.read_dir(&mut buf) {
+        // ボリュームラベルはファイルではないので除く
+        entries.extend(
+            buf[..n]
+                .iter()
+                .filter(|e| e.attr & Attribute::VolumeID as u8 == 0),
+        );
+    }
+    entries
+}
+
+fn has_wildcard(s: &str) -> bool {
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `name` がグロブのパターン `pattern` に一致するかを返す。
+///
+/// `*`、`?`、`[...]` を解釈し、`\` の直後の文字はそのまま比較する。
+fn matches(pattern: &[char], name: &[char], ignore_case: bool) -> bool {
+    let eq = |a: char, b: char| {
+        if ignore_case {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    let Some((&p, rest)) = pattern.split_first() else {
+        return name.is_empty();
+    };
+    if p == '*' {
+        return (0..=name.len()).any(|i| matches(rest, &name[i..], ignore_case));
+    }
+    let Some((&c, name_rest)) = name.split_first() else {
+        return false;
+    };
+
+    match p {
+        '?' => matches(rest, name_rest, ignore_case),
+        '[' => match bracket(rest, c, ignore_case) {
+            Some((true, rest)) => matches(rest, name_rest, ignore_case),
+            Some((false, _)) => false,
+            // 閉じていない `[` は普通の文字として扱う
+            None => eq(p, c) && matches(rest, name_rest, ignore_case),
+        },
+        '\\' if !rest.is_empty() => eq(rest[0], c) && matches(&rest[1..], name_rest, ignore_case),
+        p => eq(p, c) && matches(rest, name_rest, ignore_case),
+    }
+}
+
+/// `[` の後ろから `]` までを文字クラスとして読み、`c` が含まれるかと `]` の後ろを返す。
+/// `]` で閉じていない場合は `None` を返す。
+fn bracket(pattern: &[char], c: char, ignore_case: bool) -> Option<(bool, &[char])> {
+    let candidates = if ignore_case {
+        [c.to_ascii_lowercase(), c.to_ascii_uppercase()]
+    } else {
+        [c, c]
+    };
+
+    let (negate, mut rest) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [']', tail @ ..] if !first => return Some((found != negate, tail)),
+            [lo, '-', hi, tail @ ..] if *hi != ']' => {
+                found |= candidates.iter().any(|c| (lo..=hi).contains(&c));
+                rest = tail;
+            }
+            [x, tail @ ..] => {
+                found |= candidates.contains(x);
+                rest = tail;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
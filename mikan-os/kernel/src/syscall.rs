@@ -6,10 +6,10 @@ use crate::{
     app_event::AppEvent,
     asmfunc,
     bitfield::BitField,
+    clipboard, devfs,
     errno::ErrNo,
     error::Code,
-    fat::{self, DirectoryEntry},
-    file::{FileDescriptor, FileFlags},
+    file::{DirEntryInfo, FileDescriptor, FileFlags},
     font,
     graphics::{PixelColor, PixelWrite as _, Vector2D, FB_CONFIG},
     keyboard::{LCONTROL_BIT, RCONTROL_BIT},
@@ -19,16 +19,19 @@ use crate::{
     memory_manager::BYTES_PER_FRAME,
     message::MessageType,
     msr::{IA32_EFER, IA32_FMASK, IA32_LSTAR, IA32_STAR},
+    rtc::{self, Timespec},
     sync::{Mutex, SharedLock},
     task::{self, FileMapping, Task},
+    terminal::{Termios, WinSize, TCGETS, TCSETS, TIOCGWINSZ},
     timer::{Timer, TIMER_FREQ, TIMER_MANAGER},
+    vfs,
     window::Window,
 };
 
 pub type SyscallFuncType = extern "sysv64" fn(u64, u64, u64, u64, u64, u64) -> Result;
 
 #[no_mangle]
-pub static SYSCALL_TABLE: [SyscallFuncType; 16] = [
+pub static SYSCALL_TABLE: [SyscallFuncType; 30] = [
     log_string,
     put_string,
     exit,
@@ -45,6 +48,20 @@ pub static SYSCALL_TABLE: [SyscallFuncType; 16] = [
     read_file,
     demand_pages,
     map_file,
+    read_dir,
+    change_dir,
+    get_cwd,
+    make_dir,
+    remove_file,
+    clock_gettime,
+    create_pipe,
+    close_file,
+    make_fifo,
+    ioctl,
+    set_clipboard,
+    get_clipboard,
+    win_resize,
+    win_set_opacity,
 ];
 
 pub fn init() {
@@ -108,21 +125,19 @@ extern "sysv64" fn put_string(arg1: u64, arg2: u64, arg3: u64, _: u64, _: u64, _
     let task = task::current_task();
     asmfunc::sti();
 
-    let mut files = task.files().lock_wait();
+    let files = task.files().lock_wait();
     if fd < 0 || files.cap() <= fd as _ {
         return ErrNo::EBADF.into();
     }
-    let Some(file) = files.get_mut(&fd).cloned() else {
+    let Some(file) = files.get(&fd).cloned() else {
         return ErrNo::EBADF.into();
     };
+    // パイプへの書き込みはブロックすることがあるので、先にロックを外す
+    drop(files);
     let res = file.lock_wait().write(s);
     match res {
         Ok(len) => Result::value(len as _),
-        Err(e) => match e.cause() {
-            // 実際はデバイスでなくメモリだが、まあ一旦こうしておく
-            Code::NoEnoughMemory => ErrNo::ENOSPC.into(),
-            e => unreachable!("{}", e),
-        },
+        Err(e) => fs_errno(e.cause()).into(),
     }
 }
 
@@ -133,7 +148,11 @@ extern "sysv64" fn exit(arg1: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Re
     Result::new(*task.os_stack_ptr(), arg1 as i32)
 }
 
-extern "sysv64" fn open_window(w: u64, h: u64, x: u64, y: u64, title: u64, _: u64) -> Result {
+/// ウィンドウを開き、そのレイヤー ID を返す。
+///
+/// `flags` の 0 ビット目が立っていれば、枠のドラッグや最大化で大きさを変えられるようにする。
+/// 大きさが変えられると [AppEvent::WindowResize] が届く。
+extern "sysv64" fn open_window(w: u64, h: u64, x: u64, y: u64, title: u64, flags: u64) -> Result {
     let w = w as u32;
     let h = h as u32;
     let x = x as i32;
@@ -150,6 +169,7 @@ extern "sysv64" fn open_window(w: u64, h: u64, x: u64, y: u64, title: u64, _: u6
     manager
         .layer(layer_id)
         .set_draggable(true)
+        .set_resizable(flags.get_bit(0))
         .r#move(Vector2D::new(x, y));
     manager.activate(layer_id);
 
@@ -179,7 +199,7 @@ extern "sysv64" fn win_write_string(
                 win.write().base_mut(),
                 Vector2D::new(x as _, y as _),
                 s,
-                &PixelColor::to_color(color as _),
+                &app_color(color, layer_id_flags),
             );
             Result::value(0)
         },
@@ -197,17 +217,36 @@ extern "sysv64" fn win_fill_rectangle(
 ) -> Result {
     do_win_func(
         |win| {
-            win.write().base_mut().fill_rectangle(
-                Vector2D::new(x as _, y as _),
-                Vector2D::new(w as _, h as _),
-                &PixelColor::to_color(color as _),
-            );
+            let pos = Vector2D::new(x as _, y as _);
+            let size = Vector2D::new(w as _, h as _);
+            let color = app_color(color, layer_id_flags);
+            let mut win = win.write();
+            // layer_flags の 2 ビット目が立っていたら、重ねずに不透明度ごと置き換える
+            if layer_id_flags.get_bits(32..).get_bit(2) {
+                win.base_mut().replace_rectangle(pos, size, &color);
+            } else {
+                win.base_mut().fill_rectangle(pos, size, &color);
+            }
             Result::value(0)
         },
         layer_id_flags,
     )
 }
 
+/// アプリから渡された色を [PixelColor] にする。
+///
+/// `layer_flags` の 1 ビット目が立っていたら、色の上位 8 ビットを不透明度として扱う。
+/// 立っていなければ不透明な色になる。不透明でない色は、ウィンドウの元のピクセルの上に重ねる。
+fn app_color(color: u64, layer_id_flags: u64) -> PixelColor {
+    let color = color as u32;
+    let c = PixelColor::to_color(color);
+    if layer_id_flags.get_bits(32..).get_bit(1) {
+        c.with_alpha((color >> 24) as u8)
+    } else {
+        c
+    }
+}
+
 fn do_win_func(f: impl Fn(Arc<SharedLock<Window>>) -> Result, layer_id_flags: u64) -> Result {
     let layer_flags = layer_id_flags.get_bits(32..) as u32;
     let layer_id = layer_id_flags.get_bits(..32) as u32;
@@ -223,14 +262,66 @@ fn do_win_func(f: impl Fn(Arc<SharedLock<Window>>) -> Result, layer_id_flags: u6
     }
 
     // layer_flags の 0 ビット目が立っていたら再描画しない
-    // つまり特に指定がなければ再描画する
+    // つまり特に指定がなければ、描き込まれた部分を次のフレームで再描画する
     if !layer_flags.get_bit(0) {
-        LAYER_MANAGER.lock_wait().draw_id(layer_id);
+        LAYER_MANAGER.lock_wait().draw_damage(layer_id);
     }
 
     res
 }
 
+/// ウィンドウ全体の大きさを `w` x `h` に変える。元の内容は重なる部分だけ残る。
+///
+/// 大きさは [Window::MIN_SIZE] から画面の大きさまでに収める。
+extern "sysv64" fn win_resize(
+    layer_id_flags: u64,
+    w: u64,
+    h: u64,
+    _: u64,
+    _: u64,
+    _: u64,
+) -> Result {
+    let layer_id = layer_id_flags.get_bits(..32) as u32;
+    asmfunc::cli();
+    let task_id = task::current_task().id();
+    asmfunc::sti();
+    // 他のタスクのウィンドウは変えられない
+    if LAYER_TASK_MAP.lock_wait().get(&layer_id) != Some(&task_id) {
+        return ErrNo::EBADF.into();
+    }
+
+    let mut manager = LAYER_MANAGER.lock_wait();
+    let size = Vector2D::new(w.min(i32::MAX as u64) as i32, h.min(i32::MAX as u64) as i32);
+    let size = Vector2D::element_max(&size, &Window::MIN_SIZE);
+    let size = Vector2D::element_min(&size, &manager.screen_size());
+    manager.layer(layer_id).clear_maximized();
+    manager.resize(layer_id, size);
+    Result::value(0)
+}
+
+/// ウィンドウ全体の不透明度を `opacity` に変える。`0` で完全に透明、`255` 以上で不透明。
+extern "sysv64" fn win_set_opacity(
+    layer_id_flags: u64,
+    opacity: u64,
+    _: u64,
+    _: u64,
+    _: u64,
+    _: u64,
+) -> Result {
+    let layer_id = layer_id_flags.get_bits(..32) as u32;
+    asmfunc::cli();
+    let task_id = task::current_task().id();
+    asmfunc::sti();
+    // 他のタスクのウィンドウは変えられない
+    if LAYER_TASK_MAP.lock_wait().get(&layer_id) != Some(&task_id) {
+        return ErrNo::EBADF.into();
+    }
+
+    let opacity = opacity.min(u8::MAX as u64) as u8;
+    LAYER_MANAGER.lock_wait().set_opacity(layer_id, opacity);
+    Result::value(0)
+}
+
 extern "sysv64" fn get_current_tick(_: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
     Result::new(TIMER_MANAGER.lock_wait().current_tick(), TIMER_FREQ as i32)
 }
@@ -257,7 +348,7 @@ extern "sysv64" fn win_draw_line(
     do_win_func(
         move |win| {
             let (mut x0, mut y0, mut x1, mut y1) = (x0 as i32, y0 as i32, x1 as i32, y1 as i32);
-            let color = PixelColor::to_color(color as u32);
+            let color = app_color(color, layer_id_flags);
 
             let dx = x1 - x0 + (x1 - x0).signum();
             let dy = y1 - y0 + (y1 - y0).signum();
@@ -389,6 +480,10 @@ extern "sysv64" fn read_event(events: u64, len: u64, _: u64, _: u64, _: u64, _:
                 };
                 i += 1;
             }
+            MessageType::MouseWheel { x, y, delta } => {
+                app_events[i] = AppEvent::MouseWheel { x, y, delta };
+                i += 1;
+            }
             MessageType::TimerTimeout { timeout, value } => {
                 // アプリ用タイマは負値
                 if value.is_negative() {
@@ -403,6 +498,37 @@ extern "sysv64" fn read_event(events: u64, len: u64, _: u64, _: u64, _: u64, _:
                 app_events[i] = AppEvent::Quit;
                 i += 1;
             }
+            MessageType::WindowResize {
+                layer_id,
+                width,
+                height,
+            } => match devfs::terminal(task.id()) {
+                // アプリを実行しているターミナルのウィンドウの大きさが変えられた
+                Some(mut term) if term.layer_id() == layer_id => term.resize(width, height),
+                // アプリのウィンドウの大きさはマウスの処理で変えてあるので、新しい大きさを知らせるだけ
+                _ => {
+                    if LAYER_MANAGER.lock_wait().find_layer(layer_id).is_none() {
+                        continue;
+                    }
+                    app_events[i] = AppEvent::WindowResize {
+                        layer_id,
+                        width,
+                        height,
+                    };
+                    i += 1;
+                }
+            },
+            MessageType::TerminalWrite { data } => {
+                // アプリを実行しているターミナルに、他のタスクから書き込まれた
+                if let Some(mut term) = devfs::terminal(task.id()) {
+                    term.write_from_device(&data);
+                }
+            }
+            MessageType::TerminalSetTermios { termios } => {
+                if let Some(mut term) = devfs::terminal(task.id()) {
+                    term.set_termios(termios);
+                }
+            }
             ty => log!(LogLevel::Info, "uncaught event type: {:?}", ty),
         }
     }
@@ -460,28 +586,16 @@ extern "sysv64" fn open_file(path: u64, flags: u64, _: u64, _: u64, _: u64, _: u
         return Result::value(0);
     }
 
-    let file = match fat::find_file(path, 0) {
-        (Some(dir), post_slash) => {
-            if dir.attr != fat::Attribute::Directory as _ && post_slash {
-                return ErrNo::ENOENT.into();
-            }
-            dir
-        }
-        (None, _) => {
-            if flags & FileFlags::CREAT == FileFlags::new(0) {
-                return ErrNo::ENOENT.into();
-            }
-            match create_file(path) {
-                Ok(f) => f,
-                Err(e) => return e.into(),
-            }
-        }
+    let path = task.resolve_path(path);
+    let file = match vfs::open(&path, flags) {
+        Ok(f) => f,
+        Err(e) => return fs_errno(e.cause()).into(),
     };
 
     let fd = allocate_fd(&task);
     task.files()
         .lock_wait()
-        .insert(fd, Arc::new(Mutex::new(FileDescriptor::new_fat(file))));
+        .insert(fd, Arc::new(Mutex::new(file)));
     Result::value(fd as _)
 }
 
@@ -496,10 +610,10 @@ extern "sysv64" fn read_file(fd: u64, buf: u64, count: u64, _: u64, _: u64, _: u
     let task = task::current_task();
     asmfunc::sti();
 
-    let mut files = task.files().lock_wait();
-    let Some(fd) = files.get_mut(&fd) else {
+    let Some(fd) = task.files().lock_wait().get(&fd).cloned() else {
         return ErrNo::EBADF.into();
     };
+    // パイプからの読み込みはブロックすることがあるので、ロックを外してから読む
     let len = fd.lock_wait().read(buf) as _;
     Result::value(len)
 }
@@ -545,6 +659,279 @@ extern "sysv64" fn map_file(fd: u64, pfile_size: u64, _: u64, _: u64, _: u64, _:
     Result::value(vaddr_begin)
 }
 
+/// ディレクトリを指す `fd` から最大 `len` 個のエントリを `entries` に読み込み、
+/// 読み込んだ数を返す。
+extern "sysv64" fn read_dir(fd: u64, entries: u64, len: u64, _: u64, _: u64, _: u64) -> Result {
+    let fd = fd as i32;
+    if fd < 0 {
+        return ErrNo::EBADF.into();
+    }
+    if entries < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+
+    let entries = unsafe { slice::from_raw_parts_mut(entries as *mut DirEntryInfo, len as _) };
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    let files = task.files().lock_wait();
+    let Some(fd) = files.get(&fd) else {
+        return ErrNo::EBADF.into();
+    };
+    let res = fd.lock_wait().read_dir(entries);
+    match res {
+        Ok(count) => Result::value(count as _),
+        Err(_) => ErrNo::ENOTDIR.into(),
+    }
+}
+
+extern "sysv64" fn change_dir(path: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    if path < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+    let path = match unsafe { CStr::from_ptr(path as _) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return ErrNo::EINVAL.into(),
+    };
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    match task.change_dir(path) {
+        Ok(()) => Result::value(0),
+        Err(e) => fs_errno(e.cause()).into(),
+    }
+}
+
+/// `buf` にカレントディレクトリの絶対パスをヌル文字終端で書き込み、その長さ（ヌル文字を除く）を返す。
+extern "sysv64" fn get_cwd(buf: u64, size: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    if buf < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    let cwd = task.cwd();
+    if cwd.len() + 1 > size as usize {
+        return ErrNo::ERANGE.into();
+    }
+
+    let buf = unsafe { slice::from_raw_parts_mut(buf as *mut u8, size as _) };
+    buf[..cwd.len()].copy_from_slice(cwd.as_bytes());
+    buf[cwd.len()] = 0;
+    Result::value(cwd.len() as _)
+}
+
+/// 空のディレクトリを作る。
+extern "sysv64" fn make_dir(path: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    path_syscall(path, vfs::make_dir)
+}
+
+/// ファイルもしくは空のディレクトリを削除する。
+extern "sysv64" fn remove_file(path: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    path_syscall(path, vfs::remove)
+}
+
+/// `clock_id` が表す時計の現在時刻を `tp` に書き込む。
+extern "sysv64" fn clock_gettime(clock_id: u64, tp: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    if tp < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+    let Some(now) = rtc::clock_gettime(clock_id) else {
+        return ErrNo::EINVAL.into();
+    };
+    unsafe { *(tp as *mut Timespec) = now };
+    Result::value(0)
+}
+
+/// パイプを作り、読み込み側と書き込み側のファイルディスクリプタを `fds` に書き込む。
+extern "sysv64" fn create_pipe(fds: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    if fds < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+    let fds = unsafe { &mut *(fds as *mut [i32; 2]) };
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    let (reader, writer) = FileDescriptor::new_pipe();
+    for (i, file) in [reader, writer].into_iter().enumerate() {
+        let fd = allocate_fd(&task);
+        task.files()
+            .lock_wait()
+            .insert(fd, Arc::new(Mutex::new(file)));
+        fds[i] = fd;
+    }
+    Result::value(0)
+}
+
+/// ファイルディスクリプタ `fd` を閉じる。
+///
+/// 同じファイルを指すファイルディスクリプタが全て閉じられた時点で、ファイルが閉じられる。
+extern "sysv64" fn close_file(fd: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    let fd = fd as i32;
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    let file = task.files().lock_wait().remove(&fd);
+    match file {
+        // パイプを閉じるときに他のタスクを起こすので、ロックを外してから手放す
+        Some(file) => {
+            drop(file);
+            Result::value(0)
+        }
+        None => ErrNo::EBADF.into(),
+    }
+}
+
+/// FIFO を作る。
+extern "sysv64" fn make_fifo(path: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> Result {
+    path_syscall(path, vfs::make_fifo)
+}
+
+/// ターミナルを指す `fd` の設定を `request` に従って読み書きする。
+///
+/// - [TCGETS]: `arg` に [Termios] を書き込む。
+/// - [TCSETS]: `arg` の [Termios] を設定する。
+/// - [TIOCGWINSZ]: `arg` に [WinSize] を書き込む。
+extern "sysv64" fn ioctl(fd: u64, request: u64, arg: u64, _: u64, _: u64, _: u64) -> Result {
+    if arg < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+    let fd = fd as i32;
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    let Some(file) = task.files().lock_wait().get(&fd).cloned() else {
+        return ErrNo::EBADF.into();
+    };
+    let (term, tty_id) = {
+        let file = file.lock_wait();
+        (file.terminal(), file.tty_id())
+    };
+    // 自身のタスクのターミナルは直接読み書きする
+    let term = term.or_else(|| {
+        tty_id
+            .filter(|&id| id == task.id())
+            .and_then(devfs::terminal)
+    });
+    if let Some(mut term) = term {
+        match request {
+            TCGETS => unsafe { *(arg as *mut Termios) = term.termios() },
+            TCSETS => term.set_termios(unsafe { *(arg as *const Termios) }),
+            TIOCGWINSZ => unsafe { *(arg as *mut WinSize) = term.window_size() },
+            _ => return ErrNo::EINVAL.into(),
+        }
+        return Result::value(0);
+    }
+
+    // 他のタスクのターミナルは、記録された設定を読み、変更はそのタスクに頼む
+    let Some(id) = tty_id else {
+        return ErrNo::ENOTTY.into();
+    };
+    let Some((termios, win_size)) = devfs::terminal_settings(id) else {
+        return ErrNo::ENOTTY.into();
+    };
+    match request {
+        TCGETS => unsafe { *(arg as *mut Termios) = termios },
+        TCSETS => {
+            let termios = unsafe { *(arg as *const Termios) };
+            asmfunc::cli();
+            let result = task::send_message(id, MessageType::TerminalSetTermios { termios }.into());
+            asmfunc::sti();
+            if result.is_err() {
+                return ErrNo::ENOTTY.into();
+            }
+        }
+        TIOCGWINSZ => unsafe { *(arg as *mut WinSize) = win_size },
+        _ => return ErrNo::EINVAL.into(),
+    }
+    Result::value(0)
+}
+
+/// クリップボードの内容を、ヌル文字終端した種類 `ty` と `data` から `len` バイトのデータで置き換える。
+extern "sysv64" fn set_clipboard(ty: u64, data: u64, len: u64, _: u64, _: u64, _: u64) -> Result {
+    // 長さが 0 の場合、データのポインタは使わない（空の Vec などでは有効なアドレスを指さない）
+    if ty < 0x8000_0000_0000_0000 || (len != 0 && data < 0x8000_0000_0000_0000) {
+        return ErrNo::EFAULT.into();
+    }
+    let ty = match unsafe { CStr::from_ptr(ty as _) }.to_str() {
+        Ok(ty) if !ty.is_empty() && ty.len() <= clipboard::MAX_TYPE_LEN => ty,
+        _ => return ErrNo::EINVAL.into(),
+    };
+    if len as usize > clipboard::MAX_SIZE {
+        return ErrNo::E2BIG.into();
+    }
+
+    let data: &[u8] = if len == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(data as *const u8, len as _) }
+    };
+    clipboard::set(ty, data.to_vec());
+    Result::value(0)
+}
+
+/// クリップボードの内容の種類を `ty` にヌル文字終端で、データを `data` に最大 `len` バイト書き込む。
+///
+/// 書き込めたかどうかに関わらず、データ全体の長さを返す。クリップボードが空の場合は
+/// [ErrNo::ENODATA] を返す。
+extern "sysv64" fn get_clipboard(
+    ty: u64,
+    ty_size: u64,
+    data: u64,
+    len: u64,
+    _: u64,
+    _: u64,
+) -> Result {
+    // 長さが 0 の場合、データのポインタは使わない（空の Vec などでは有効なアドレスを指さない）
+    if ty < 0x8000_0000_0000_0000 || (len != 0 && data < 0x8000_0000_0000_0000) {
+        return ErrNo::EFAULT.into();
+    }
+    let Some(content) = clipboard::get() else {
+        return ErrNo::ENODATA.into();
+    };
+    if content.ty.len() + 1 > ty_size as usize {
+        return ErrNo::ERANGE.into();
+    }
+
+    let ty = unsafe { slice::from_raw_parts_mut(ty as *mut u8, ty_size as _) };
+    ty[..content.ty.len()].copy_from_slice(content.ty.as_bytes());
+    ty[content.ty.len()] = 0;
+
+    let data: &mut [u8] = if len == 0 {
+        &mut []
+    } else {
+        unsafe { slice::from_raw_parts_mut(data as *mut u8, len as _) }
+    };
+    let n = data.len().min(content.data.len());
+    data[..n].copy_from_slice(&content.data[..n]);
+    Result::value(content.data.len() as _)
+}
+
+/// ユーザー空間の文字列 `path` をカレントディレクトリを基準に解決し、`f` に渡す。
+fn path_syscall(path: u64, f: fn(&str) -> crate::error::Result<()>) -> Result {
+    if path < 0x8000_0000_0000_0000 {
+        return ErrNo::EFAULT.into();
+    }
+    let path = match unsafe { CStr::from_ptr(path as _) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return ErrNo::EINVAL.into(),
+    };
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    match f(&task.resolve_path(path)) {
+        Ok(()) => Result::value(0),
+        Err(e) => fs_errno(e.cause()).into(),
+    }
+}
+
 fn allocate_fd(task: &Task) -> i32 {
     let files = task.files().lock_wait();
     let num_files = files.cap() as _;
@@ -553,11 +940,21 @@ fn allocate_fd(task: &Task) -> i32 {
         .unwrap_or(num_files as _)
 }
 
-fn create_file(path: &str) -> core::result::Result<&'static mut DirectoryEntry, ErrNo> {
-    fat::create_file(path).map_err(|e| match e.cause() {
+/// ファイルシステムの操作で起きたエラーを対応する [ErrNo] に変換する。
+fn fs_errno(code: Code) -> ErrNo {
+    match code {
         Code::IsDirectory => ErrNo::EISDIR,
+        Code::NotDirectory => ErrNo::ENOTDIR,
         Code::NoSuchEntry => ErrNo::ENOENT,
-        Code::NoEnoughMemory => ErrNo::ENOSPC,
-        _ => unreachable!(),
-    })
+        // 実際はデバイスでなくメモリだが、まあ一旦こうしておく
+        Code::NoEnoughMemory | Code::Full => ErrNo::ENOSPC,
+        Code::ReadOnly => ErrNo::EROFS,
+        Code::AlreadyExists => ErrNo::EEXIST,
+        Code::NotEmpty => ErrNo::ENOTEMPTY,
+        Code::TooManyLinks => ErrNo::ELOOP,
+        Code::BrokenPipe => ErrNo::EPIPE,
+        Code::InvalidFile => ErrNo::EBADF,
+        Code::NotImplemented => ErrNo::ENOTSUP,
+        _ => ErrNo::EIO,
+    }
 }
@@ -1,4 +1,11 @@
-use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
 use core::{
     arch::asm,
     mem, ptr,
@@ -12,10 +19,12 @@ use crate::{
     file::FileDescriptor,
     make_error,
     message::Message,
+    path,
     segment::{KERNEL_CS, KERNEL_SS},
     sync::Mutex,
     terminal::{DEFAULT_APP_STACK_SIZE, FILE_MAP_END},
     timer::{Timer, TASK_TIMER_PERIOD, TASK_TIMER_VALUE, TIMER_MANAGER},
+    vfs,
 };
 
 /// [OnceMutex] や [Mutex] で持ちたいが、ロックを取得してからコンテキストスイッチをすると
@@ -34,6 +43,11 @@ static mut TASK_MANAGER: TaskManager = TaskManager::new();
 /// * layer_id - Window がない場合は `0`。
 pub type TaskFunc = fn(u64, i64, u32);
 
+/// 親タスクを持たないタスクに設定される、環境変数 `PATH` の値。
+pub const DEFAULT_PATH: &str = "/apps";
+/// 親タスクを持たないタスクに設定される、環境変数 `HOME` の値。
+pub const DEFAULT_HOME: &str = "/home";
+
 pub fn init() {
     unsafe {
         TASK_MANAGER
@@ -102,6 +116,11 @@ pub fn get_task(task_id: u64) -> Option<Arc<Task>> {
     unsafe { TASK_MANAGER.get_task(task_id) }
 }
 
+/// 存在する全てのタスクを ID の昇順で返す。
+pub fn tasks() -> Vec<Arc<Task>> {
+    unsafe { TASK_MANAGER.tasks() }
+}
+
 #[no_mangle]
 pub fn get_current_task_os_stack_pointer() -> u64 {
     *unsafe { TASK_MANAGER.current_task().os_stack_ptr() }
@@ -204,6 +223,10 @@ pub struct Task<const STACK_SIZE: usize = { 8 * 4096 }> {
     app_stack_size: AtomicU64,
     file_map_end: AtomicU64,
     file_maps: Mutex<Vec<FileMapping>>,
+    /// カレントディレクトリの絶対パス。
+    cwd: Mutex<String>,
+    /// 環境変数。
+    env: Mutex<BTreeMap<String, String>>,
 }
 
 impl<const STACK_SIZE: usize> Task<STACK_SIZE> {
@@ -235,6 +258,11 @@ impl<const STACK_SIZE: usize> Task<STACK_SIZE> {
             app_stack_size: AtomicU64::new(DEFAULT_APP_STACK_SIZE),
             file_map_end: AtomicU64::new(FILE_MAP_END),
             file_maps: Mutex::new(vec![]),
+            cwd: Mutex::new(String::from("/")),
+            env: Mutex::new(BTreeMap::from([
+                (String::from("PATH"), String::from(DEFAULT_PATH)),
+                (String::from("HOME"), String::from(DEFAULT_HOME)),
+            ])),
         }
     }
 
@@ -291,6 +319,11 @@ impl<const STACK_SIZE: usize> Task<STACK_SIZE> {
         unsafe { TASK_MANAGER.change_level_running(self.id, level) };
     }
 
+    /// ランキューに入っている（スリープしていない）かどうかを返す。
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
     pub fn run_level(&self) -> i32 {
         self.level.load(Ordering::Relaxed)
     }
@@ -335,6 +368,58 @@ impl<const STACK_SIZE: usize> Task<STACK_SIZE> {
         &self.file_maps
     }
 
+    pub fn cwd(&self) -> String {
+        self.cwd.lock_wait().clone()
+    }
+
+    pub fn set_cwd(&self, cwd: String) {
+        *self.cwd.lock_wait() = cwd;
+    }
+
+    /// カレントディレクトリを `path` に変更する。
+    /// `path` が相対パスの場合は、現在のカレントディレクトリを基準とする。
+    pub fn change_dir(&self, path: &str) -> Result<()> {
+        let path = self.resolve_path(path);
+        vfs::check_dir(&path)?;
+
+        // 末尾の `/` は取り除いて保持する
+        let cwd = match path.trim_end_matches('/') {
+            "" => "/",
+            cwd => cwd,
+        };
+        self.set_cwd(cwd.into());
+        Ok(())
+    }
+
+    /// 全ての環境変数を返す。
+    pub fn env(&self) -> BTreeMap<String, String> {
+        self.env.lock_wait().clone()
+    }
+
+    pub fn set_env(&self, env: BTreeMap<String, String>) {
+        *self.env.lock_wait() = env;
+    }
+
+    /// 環境変数 `name` の値を返す。
+    pub fn env_var(&self, name: &str) -> Option<String> {
+        self.env.lock_wait().get(name).cloned()
+    }
+
+    /// 環境変数 `name` に `value` を設定し、それまでの値を返す。
+    pub fn set_env_var(&self, name: String, value: String) -> Option<String> {
+        self.env.lock_wait().insert(name, value)
+    }
+
+    /// 環境変数 `name` を削除し、それまでの値を返す。
+    pub fn remove_env_var(&self, name: &str) -> Option<String> {
+        self.env.lock_wait().remove(name)
+    }
+
+    /// `path` をカレントディレクトリを基準として解決した絶対パスを返す。
+    pub fn resolve_path(&self, path: &str) -> String {
+        path::resolve(&self.cwd.lock_wait(), path)
+    }
+
     fn set_level(&self, level: i32) -> &Self {
         self.level.store(level, Ordering::Relaxed);
         self
@@ -391,9 +476,15 @@ impl TaskManager {
         }
     }
 
+    /// 新しく作るタスクは、現在のタスクのカレントディレクトリと環境変数を引き継ぐ。
     fn new_task(&mut self) -> &mut Task {
         self.latest_id += 1;
-        self.tasks.push(Arc::new(Task::new(self.latest_id)));
+        let task = Task::new(self.latest_id);
+        if let Some(current) = self.current_task_checked() {
+            task.set_cwd(current.cwd());
+            task.set_env(current.env());
+        }
+        self.tasks.push(Arc::new(task));
         // 今追加したばかりで、running にはまだ追加されていないから、この unwrap() は必ず成功する
         self.tasks
             .last_mut()
@@ -598,6 +689,11 @@ impl TaskManager {
     fn get_task(&self, task_id: u64) -> Option<Arc<Task>> {
         self.tasks.iter().find(|task| task.id() == task_id).cloned()
     }
+
+    fn tasks(&self) -> Vec<Arc<Task>> {
+        // タスクは ID の昇順に追加されていくので、並べ替える必要はない
+        self.tasks.clone()
+    }
 }
 
 /// [`Arc<Task>`][Arc<Task>] の [VecDeque] から ID が `id` の [Task] を削除する。
@@ -0,0 +1,586 @@
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{
+    asmfunc,
+    fat::Attribute,
+    font,
+    graphics::{PixelColor, PixelWrite, Rectangle, Vector2D},
+    layer::{LayerManager, LAYER_MANAGER, LAYER_TASK_MAP},
+    message::MessageType,
+    mouse::MOUSE_LAYER_ID,
+    rtc, shell,
+    sync::SharedLock,
+    task, terminal,
+    timer::{Timer, TIMER_FREQ, TIMER_MANAGER},
+    window::Window,
+};
+
+/// タスクバーの高さ。
+pub const TASKBAR_HEIGHT: i32 = 28;
+/// ランチャーに並べるアプリを置くディレクトリ。
+const APPS_DIR: &str = "/apps";
+/// ウィンドウの一覧と時計を確かめる間隔（秒）。
+const UPDATE_INTERVAL: f64 = 0.25;
+
+/// ランチャーを開くボタンの幅。
+const LAUNCHER_BUTTON_WIDTH: i32 = 56;
+/// ウィンドウ 1 つ分のボタンの幅の上限。
+const WINDOW_BUTTON_WIDTH: i32 = 120;
+/// 時計を表示する部分の幅。
+const CLOCK_WIDTH: i32 = 8 * 5 + 16;
+/// ボタンの上下の余白。
+const BUTTON_MARGIN: i32 = 3;
+
+/// ランチャーの幅。
+const MENU_WIDTH: i32 = 160;
+/// ランチャーの項目 1 つ分の高さ。
+const MENU_ITEM_HEIGHT: i32 = 20;
+/// ランチャーに並べる行の数の上限。
+const MENU_MAX_ROWS: usize = 16;
+/// ランチャーの先頭に置く、ターミナルを開く項目。
+const MENU_TERMINAL: &str = "Terminal";
+
+const BG_COLOR: u32 = 0xc6c6c6;
+const LIGHT_COLOR: u32 = 0xffffff;
+const DARK_COLOR: u32 = 0x848484;
+const TEXT_COLOR: u32 = 0x000000;
+const DISABLED_TEXT_COLOR: u32 = 0x848484;
+
+/// タスクバーのタスクの ID。
+static TASKBAR_TASK_ID: AtomicU64 = AtomicU64::new(0);
+/// ランチャーが開いているかどうか。
+static MENU_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// タスクバーのボタン 1 つ分に表示するウィンドウ。
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    layer_id: u32,
+    title: String,
+    minimized: bool,
+}
+
+/// ランチャーの 1 行に置く項目。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuItem {
+    Terminal,
+    /// [Menu::apps] の何番目のアプリか。
+    App(usize),
+    /// 一覧を上にスクロールする。
+    Up,
+    /// 一覧を下にスクロールする。
+    Down,
+}
+
+/// 開いているランチャー。
+struct Menu {
+    layer_id: u32,
+    /// 並べるアプリの名前。
+    apps: Vec<String>,
+    /// 表示している先頭のアプリの位置。
+    scroll: usize,
+    /// 並べる行の数。
+    rows: usize,
+}
+
+impl Menu {
+    /// 上から順に並べる項目。アプリが収まらない場合は、スクロールするための行を前後に置く。
+    fn items(&self) -> Vec<MenuItem> {
+        let mut items = vec![MenuItem::Terminal];
+        if self.apps.len() < self.rows {
+            items.extend((0..self.apps.len()).map(MenuItem::App));
+        } else {
+            items.push(MenuItem::Up);
+            items.extend((self.scroll..self.scroll + self.visible_apps()).map(MenuItem::App));
+            items.push(MenuItem::Down);
+        }
+        items
+    }
+
+    /// スクロールする場合に、一度に並べるアプリの数。
+    fn visible_apps(&self) -> usize {
+        self.rows - 3
+    }
+
+    /// 一覧を 1 画面分スクロールする。スクロールできなければ `false` を返す。
+    fn scroll(&mut self, up: bool) -> bool {
+        let visible = self.visible_apps();
+        let scroll = if up {
+            self.scroll.saturating_sub(visible)
+        } else {
+            (self.scroll + visible).min(self.apps.len() - visible)
+        };
+        let changed = scroll != self.scroll;
+        self.scroll = scroll;
+        changed
+    }
+}
+
+/// 画面下端に置く、ウィンドウの一覧・時計・ランチャーを持つバー。
+struct Taskbar {
+    task_id: u64,
+    layer_id: u32,
+    window: Arc<SharedLock<Window>>,
+    /// タスクバーの幅。
+    width: i32,
+    /// 開いているランチャー。
+    menu: Option<Menu>,
+    /// 表示しているウィンドウの一覧。
+    entries: Vec<Entry>,
+    /// 表示しているアクティブなウィンドウ。
+    active: u32,
+    /// 表示している時刻（時, 分）。
+    clock: (u8, u8),
+}
+
+impl Taskbar {
+    /// タスクバーのレイヤーを作って画面下端に置く。
+    fn new(task_id: u64) -> Self {
+        let mut manager = LAYER_MANAGER.lock_wait();
+        let screen_size = manager.screen_size();
+        let window = Window::new_base(
+            screen_size.x() as u32,
+            TASKBAR_HEIGHT as u32,
+            manager.pixel_format(),
+        );
+        let layer_id = manager.new_layer(window);
+        manager
+            .layer(layer_id)
+            .r#move(Vector2D::new(0, screen_size.y() - TASKBAR_HEIGHT));
+        // マウスカーソルのすぐ下に置く
+        let mouse_height = manager.get_height(MOUSE_LAYER_ID.load(Ordering::Acquire));
+        manager.up_down(layer_id, mouse_height);
+        manager.set_taskbar_layer(layer_id);
+        let window = manager.layer(layer_id).window();
+        drop(manager);
+
+        LAYER_TASK_MAP.lock_wait().insert(layer_id, task_id);
+
+        Self {
+            task_id,
+            layer_id,
+            window,
+            width: screen_size.x(),
+            menu: None,
+            entries: Vec::new(),
+            active: 0,
+            clock: (u8::MAX, u8::MAX),
+        }
+    }
+
+    /// ウィンドウの一覧と時刻を確かめ、変わっていれば描き直す。
+    fn update(&mut self) {
+        let now = rtc::now();
+        let clock = (now.hour, now.minute);
+
+        let mut manager = LAYER_MANAGER.lock_wait();
+        let entries: Vec<_> = manager
+            .toplevel_layers()
+            .into_iter()
+            .filter_map(|layer_id| {
+                let layer = manager.find_layer(layer_id)?;
+                let title = layer.window().read().title()?.into();
+                Some(Entry {
+                    layer_id,
+                    title,
+                    minimized: layer.is_minimized(),
+                })
+            })
+            .collect();
+        let active = manager.get_active();
+
+        if entries == self.entries && active == self.active && clock == self.clock {
+            return;
+        }
+        self.entries = entries;
+        self.active = active;
+        self.clock = clock;
+        self.draw(&mut manager);
+    }
+
+    fn draw(&self, manager: &mut LayerManager) {
+        {
+            let mut window = self.window.write();
+            let width = self.width;
+            window.fill_rectangle(
+                Vector2D::new(0, 0),
+                Vector2D::new(width, TASKBAR_HEIGHT),
+                &PixelColor::to_color(BG_COLOR),
+            );
+            window.fill_rectangle(
+                Vector2D::new(0, 1),
+                Vector2D::new(width, 1),
+                &PixelColor::to_color(LIGHT_COLOR),
+            );
+
+            draw_button(
+                &mut window,
+                self.launcher_button(),
+                "Apps",
+                self.menu.is_some(),
+                TEXT_COLOR,
+            );
+            for (i, entry) in self.entries.iter().enumerate() {
+                let Some(area) = self.window_button(i) else {
+                    break;
+                };
+                let color = if entry.minimized {
+                    DISABLED_TEXT_COLOR
+                } else {
+                    TEXT_COLOR
+                };
+                let pressed = entry.layer_id == self.active;
+                draw_button(&mut window, area, &entry.title, pressed, color);
+            }
+
+            let (hour, minute) = self.clock;
+            let clock_area = self.clock_area();
+            draw_sunken(&mut window, clock_area);
+            font::write_string(
+                &mut *window,
+                clock_area.pos + Vector2D::new(8, (clock_area.size.y() - 16) / 2),
+                &format!("{:02}:{:02}", hour, minute),
+                &PixelColor::to_color(TEXT_COLOR),
+            );
+        }
+        manager.draw_id(self.layer_id);
+    }
+
+    fn launcher_button(&self) -> Rectangle<i32> {
+        Rectangle {
+            pos: Vector2D::new(2, BUTTON_MARGIN),
+            size: Vector2D::new(LAUNCHER_BUTTON_WIDTH, TASKBAR_HEIGHT - BUTTON_MARGIN * 2),
+        }
+    }
+
+    fn clock_area(&self) -> Rectangle<i32> {
+        Rectangle {
+            pos: Vector2D::new(self.width - CLOCK_WIDTH - 2, BUTTON_MARGIN),
+            size: Vector2D::new(CLOCK_WIDTH, TASKBAR_HEIGHT - BUTTON_MARGIN * 2),
+        }
+    }
+
+    /// `index` 番目のウィンドウのボタンの位置と大きさ。時計と重なる場合は `None`。
+    ///
+    /// ウィンドウが多い場合は、全てが収まるようにボタンの幅を狭める。
+    fn window_button(&self, index: usize) -> Option<Rectangle<i32>> {
+        let left = LAUNCHER_BUTTON_WIDTH + 8;
+        let right = self.width - CLOCK_WIDTH - 8;
+        let count = self.entries.len().max(1) as i32;
+        let button_width = ((right - left) / count).min(WINDOW_BUTTON_WIDTH);
+        if button_width < 24 {
+            return None;
+        }
+        let x = left + button_width * index as i32;
+        Some(Rectangle {
+            pos: Vector2D::new(x, BUTTON_MARGIN),
+            size: Vector2D::new(button_width - 2, TASKBAR_HEIGHT - BUTTON_MARGIN * 2),
+        })
+    }
+
+    /// タスクバー上の `pos` がクリックされた。
+    fn click(&mut self, pos: Vector2D<i32>) {
+        if contains(&self.launcher_button(), pos) {
+            if self.menu.is_some() {
+                self.close_menu();
+            } else {
+                self.open_menu();
+            }
+            self.force_update();
+            return;
+        }
+
+        let Some(layer_id) = (0..self.entries.len())
+            .find(|&i| {
+                self.window_button(i)
+                    .is_some_and(|area| contains(&area, pos))
+            })
+            .map(|i| self.entries[i].layer_id)
+        else {
+            return;
+        };
+        self.close_menu();
+
+        let mut manager = LAYER_MANAGER.lock_wait();
+        // 一覧を更新する前にウィンドウが閉じられていることがある
+        let Some(layer) = manager.find_layer(layer_id) else {
+            return;
+        };
+        if layer.is_minimized() {
+            manager.restore(layer_id);
+        } else if manager.get_active() == layer_id {
+            manager.minimize(layer_id);
+        } else {
+            manager.activate(layer_id);
+        }
+        drop(manager);
+        self.force_update();
+    }
+
+    /// ランチャー上の `pos` がクリックされた。
+    fn click_menu(&mut self, pos: Vector2D<i32>) {
+        let Some(ref mut menu) = self.menu else {
+            return;
+        };
+        let index = (pos.y() - 2) / MENU_ITEM_HEIGHT;
+        if pos.y() < 2 || index < 0 {
+            return;
+        }
+        let Some(&item) = menu.items().get(index as usize) else {
+            return;
+        };
+        match item {
+            MenuItem::Terminal => {
+                asmfunc::cli();
+                task::new_task()
+                    .init_context(terminal::task_terminal, 0, 0)
+                    .wake_up(-1);
+                asmfunc::sti();
+            }
+            MenuItem::App(i) => {
+                let command = shell::quote(&format!("{}/{}", APPS_DIR, menu.apps[i]));
+                terminal::launch(&command);
+            }
+            MenuItem::Up | MenuItem::Down => {
+                if menu.scroll(item == MenuItem::Up) {
+                    let mut manager = LAYER_MANAGER.lock_wait();
+                    draw_menu(&mut manager.layer(menu.layer_id).window().write(), menu);
+                    manager.draw_id(menu.layer_id);
+                }
+                return;
+            }
+        }
+        self.close_menu();
+        self.force_update();
+    }
+
+    /// [APPS_DIR] にあるアプリを並べたランチャーを、タスクバーの上に開く。
+    ///
+    /// 画面に収まらないほどアプリが多い場合は、行の数を抑えてスクロールできるようにする。
+    fn open_menu(&mut self) {
+        let mut apps: Vec<String> = shell::read_entries(APPS_DIR)
+            .iter()
+            .filter(|e| e.attr & Attribute::Directory as u8 == 0)
+            .map(|e| e.name().into())
+            .collect();
+        apps.sort();
+
+        let mut manager = LAYER_MANAGER.lock_wait();
+        let taskbar_pos = manager.layer(self.layer_id).pos();
+        let max_rows = ((taskbar_pos.y() - 4) / MENU_ITEM_HEIGHT).max(4) as usize;
+        let rows = (apps.len() + 1).min(max_rows.min(MENU_MAX_ROWS));
+        let height = MENU_ITEM_HEIGHT * rows as i32 + 4;
+        let mut window = Window::new_base(MENU_WIDTH as u32, height as u32, manager.pixel_format());
+        let mut menu = Menu {
+            layer_id: 0,
+            apps,
+            scroll: 0,
+            rows,
+        };
+        draw_menu(&mut window, &menu);
+
+        let layer_id = manager.new_layer(window);
+        manager
+            .layer(layer_id)
+            .r#move(taskbar_pos - Vector2D::new(0, height));
+        let mouse_height = manager.get_height(MOUSE_LAYER_ID.load(Ordering::Acquire));
+        manager.up_down(layer_id, mouse_height);
+        manager.draw_id(layer_id);
+        drop(manager);
+
+        LAYER_TASK_MAP.lock_wait().insert(layer_id, self.task_id);
+        menu.layer_id = layer_id;
+        self.menu = Some(menu);
+        MENU_OPEN.store(true, Ordering::Release);
+    }
+
+    /// ランチャーが開いていれば閉じる。
+    fn close_menu(&mut self) {
+        let Some(Menu { layer_id, .. }) = self.menu.take() else {
+            return;
+        };
+        MENU_OPEN.store(false, Ordering::Release);
+        // アクティブなウィンドウを変えないよう、layer::close_layer() は使わない
+        let mut manager = LAYER_MANAGER.lock_wait();
+        let area = Rectangle {
+            pos: manager.layer(layer_id).pos(),
+            size: manager.layer(layer_id).window().read().base().size(),
+        };
+        manager.remove_layer(layer_id);
+        manager.draw(&area);
+        drop(manager);
+        LAYER_TASK_MAP.lock_wait().remove(&layer_id);
+    }
+
+    /// 次の [Taskbar::update] で必ず描き直させる。
+    fn force_update(&mut self) {
+        self.clock = (u8::MAX, u8::MAX);
+        self.update();
+    }
+}
+
+/// タスクバーのタスク。
+pub fn task_taskbar(task_id: u64, _: i64, _: u32) {
+    asmfunc::cli();
+    let task = task::current_task();
+    asmfunc::sti();
+
+    TASKBAR_TASK_ID.store(task_id, Ordering::Release);
+    let mut taskbar = Taskbar::new(task_id);
+    taskbar.update();
+
+    let interval = (TIMER_FREQ as f64 * UPDATE_INTERVAL) as u64;
+    let current_tick = TIMER_MANAGER.lock_wait().current_tick();
+    TIMER_MANAGER
+        .lock_wait()
+        .add_timer(Timer::new(current_tick + interval, 1, task_id));
+
+    loop {
+        let msg = match task.receive_message() {
+            Some(msg) => msg,
+            None => {
+                task.sleep();
+                continue;
+            }
+        };
+
+        match msg.ty {
+            MessageType::TimerTimeout { timeout, .. } => {
+                TIMER_MANAGER
+                    .lock_wait()
+                    .add_timer(Timer::new(timeout + interval, 1, task_id));
+                taskbar.update();
+            }
+            MessageType::LayerClick { layer_id, x, y } => {
+                let pos = Vector2D::new(x, y);
+                if layer_id == taskbar.layer_id {
+                    taskbar.click(pos);
+                } else if taskbar
+                    .menu
+                    .as_ref()
+                    .is_some_and(|m| m.layer_id == layer_id)
+                {
+                    taskbar.click_menu(pos);
+                }
+            }
+            MessageType::MenuClose => {
+                taskbar.close_menu();
+                taskbar.force_update();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 左ボタンが押されたときに、マウスの処理から呼ばれる。`layer_id` は押された位置のレイヤー。
+///
+/// タスクバーとランチャーの外側が押された場合は、開いているランチャーを閉じさせる。
+pub fn notify_press(layer_id: Option<u32>) {
+    if !MENU_OPEN.load(Ordering::Acquire) {
+        return;
+    }
+    let task_id = TASKBAR_TASK_ID.load(Ordering::Acquire);
+    if layer_id.is_some_and(|id| LAYER_TASK_MAP.lock_wait().get(&id) == Some(&task_id)) {
+        return;
+    }
+    let _ = task::send_message(task_id, MessageType::MenuClose.into());
+}
+
+fn contains(area: &Rectangle<i32>, pos: Vector2D<i32>) -> bool {
+    let end = area.pos + area.size;
+    area.pos.x() <= pos.x() && pos.x() < end.x() && area.pos.y() <= pos.y() && pos.y() < end.y()
+}
+
+/// 押されていない状態では浮き出て、押された状態ではへこんで見えるボタンを描く。
+/// `label` は幅に収まるように切り詰める。
+fn draw_button(window: &mut Window, area: Rectangle<i32>, label: &str, pressed: bool, color: u32) {
+    let (top_left, bottom_right) = if pressed {
+        (DARK_COLOR, LIGHT_COLOR)
+    } else {
+        (LIGHT_COLOR, DARK_COLOR)
+    };
+    draw_frame(window, area, top_left, bottom_right);
+
+    let max_width = (area.size.x() - 8) / 8;
+    let mut width = 0;
+    let label: String = label
+        .chars()
+        .take_while(|c| {
+            width += if c.is_ascii() { 1 } else { 2 };
+            width <= max_width
+        })
+        .collect();
+    let offset = if pressed { 1 } else { 0 };
+    font::write_string(
+        window,
+        area.pos + Vector2D::new(4 + offset, (area.size.y() - 16) / 2 + offset),
+        &label,
+        &PixelColor::to_color(color),
+    );
+}
+
+/// へこんで見える枠を描く。
+fn draw_sunken(window: &mut Window, area: Rectangle<i32>) {
+    draw_frame(window, area, DARK_COLOR, LIGHT_COLOR);
+}
+
+fn draw_frame(window: &mut Window, area: Rectangle<i32>, top_left: u32, bottom_right: u32) {
+    let Rectangle { pos, size } = area;
+    window.fill_rectangle(pos, size, &PixelColor::to_color(BG_COLOR));
+    let top_left = PixelColor::to_color(top_left);
+    let bottom_right = PixelColor::to_color(bottom_right);
+    window.fill_rectangle(pos, Vector2D::new(size.x(), 1), &top_left);
+    window.fill_rectangle(pos, Vector2D::new(1, size.y()), &top_left);
+    window.fill_rectangle(
+        pos + Vector2D::new(0, size.y() - 1),
+        Vector2D::new(size.x(), 1),
+        &bottom_right,
+    );
+    window.fill_rectangle(
+        pos + Vector2D::new(size.x() - 1, 0),
+        Vector2D::new(1, size.y()),
+        &bottom_right,
+    );
+}
+
+/// ランチャーの項目を描く。先頭はターミナルを開く項目で、その後にアプリが続く。
+fn draw_menu(window: &mut Window, menu: &Menu) {
+    let size = window.size();
+    draw_frame(
+        window,
+        Rectangle {
+            pos: Vector2D::new(0, 0),
+            size,
+        },
+        LIGHT_COLOR,
+        DARK_COLOR,
+    );
+
+    let items = menu.items();
+    for (i, &item) in items.iter().enumerate() {
+        let (label, color) = match item {
+            MenuItem::Terminal => (MENU_TERMINAL, TEXT_COLOR),
+            MenuItem::App(i) => (menu.apps[i].as_str(), TEXT_COLOR),
+            MenuItem::Up if menu.scroll == 0 => ("^", DISABLED_TEXT_COLOR),
+            MenuItem::Up => ("^", TEXT_COLOR),
+            MenuItem::Down if menu.scroll + menu.visible_apps() >= menu.apps.len() => {
+                ("v", DISABLED_TEXT_COLOR)
+            }
+            MenuItem::Down => ("v", TEXT_COLOR),
+        };
+        let y = 2 + MENU_ITEM_HEIGHT * i as i32;
+        font::write_string(
+            window,
+            Vector2D::new(8, y + (MENU_ITEM_HEIGHT - 16) / 2),
+            label,
+            &PixelColor::to_color(color),
+        );
+        if item == MenuItem::Terminal && items.len() > 1 {
+            window.fill_rectangle(
+                Vector2D::new(4, y + MENU_ITEM_HEIGHT - 1),
+                Vector2D::new(size.x() - 8, 1),
+                &PixelColor::to_color(DARK_COLOR),
+            );
+        }
+    }
+}
@@ -1,38 +1,47 @@
 use alloc::{
     boxed::Box,
-    collections::VecDeque,
+    collections::{BTreeMap, VecDeque},
     format,
-    string::{String, ToString as _},
+    string::String,
     sync::Arc,
+    vec,
     vec::Vec,
 };
 use core::{
     ffi::c_char,
-    mem,
-    ops::{Deref, DerefMut},
+    iter, mem,
+    ops::{Deref, DerefMut, Range},
     ptr, slice, str,
 };
 
 use crate::{
+    ansi::{self, Action, Attr, Cell, Parser},
     asmfunc,
+    bitfield::BitField as _,
+    clipboard,
     collections::HashMap,
+    devfs,
     elf::{Elf64Ehdr, Elf64Phdr, ExecuteType, ProgramType},
     error::{Code, Result},
-    fat::{self, Attribute, DirectoryEntry, BYTES_PER_CLUSTER},
-    file::{self, FileDescriptor},
+    fat::{self, Attribute, DirectoryEntry},
+    file::{self, DirEntryInfo, FileDescriptor, FileFlags, ATTR_FIFO},
     font,
-    graphics::{PixelColor, PixelWrite, Rectangle, Vector2D, FB_CONFIG},
+    graphics::{PixelWrite, Rectangle, Vector2D, FB_CONFIG},
+    keyboard::{LCONTROL_BIT, LSHIFT_BIT, RCONTROL_BIT, RSHIFT_BIT},
     layer::{self, LAYER_MANAGER, LAYER_TASK_MAP},
     log,
     logger::LogLevel,
     make_error,
-    memory_manager::{BYTES_PER_FRAME, MEMORY_MANAGER},
+    memory_manager::BYTES_PER_FRAME,
     message::{Message, MessageType},
     paging::{self, LinearAddress4Level, PageMapEntry},
-    pci,
+    path, procfs,
+    rtc::{self, DateTime},
+    shell::{self, AndOr, Command, Connector, Expander, List, Redirect, Stage, Word},
     sync::{Mutex, SharedLock},
     task::{self, Task},
     timer::{Timer, TIMER_FREQ, TIMER_MANAGER},
+    vfs,
     window::Window,
 };
 pub const APP_STACK_ADDR: u64 = 0xffff_ffff_ffff_e000;
@@ -74,10 +83,41 @@ impl From<&mut Terminal> for TerminalRef {
 }
 
 pub struct TerminalDescriptor {
+    /// 実行するコマンドライン。
+    pub command: String,
+    /// 引き継ぐシェル変数。
+    pub vars: BTreeMap<String, String>,
+    /// 引き継ぐ位置パラメータ。先頭は `$0`。
     pub args: Vec<String>,
     pub exit_affter_command: bool,
     pub show_window: bool,
-    pub files: [Arc<Mutex<FileDescriptor>>; 3],
+    /// 標準入出力。`None` の場合はターミナル自身を使う。
+    pub files: Option<[Arc<Mutex<FileDescriptor>>; 3]>,
+}
+
+/// `desc` に従ってコマンドを実行するターミナルのタスクを作り、その ID を返す。
+fn spawn_terminal(desc: TerminalDescriptor) -> u64 {
+    let desc = Box::new(desc);
+    asmfunc::cli();
+    let id = task::new_task()
+        .init_context(task_terminal, Box::into_raw(desc) as _, 0)
+        .wake_up(-1)
+        .id();
+    asmfunc::sti();
+    id
+}
+
+/// ウィンドウを持つターミナルを開いて `command` を実行し、そのタスクの ID を返す。
+/// コマンドが終わった後もターミナルは対話用に残る。
+pub fn launch(command: &str) -> u64 {
+    spawn_terminal(TerminalDescriptor {
+        command: command.into(),
+        vars: BTreeMap::new(),
+        args: Vec::new(),
+        exit_affter_command: false,
+        show_window: true,
+        files: None,
+    })
 }
 
 /// 通常タスクに渡される `data`, `layer_id` だが、ターミナルは両者を必要としないので、
@@ -101,13 +141,14 @@ pub fn task_terminal(task_id: u64, pdesc: i64, _: u32) {
     let task = task::current_task();
     asmfunc::sti();
     let mut terminal = Terminal::new(task.clone(), desc.as_deref());
-    // desc がない場合は標準入出力の指定がないため、自身で埋める
-    if desc.is_none() {
+    // 標準入出力の指定がない場合は、自身で埋める
+    if desc.as_ref().is_none_or(|desc| desc.files.is_none()) {
         for fd in &terminal.files {
             fd.lock_wait().set_terminal((&terminal).into());
         }
     }
     if show_window {
+        devfs::register_terminal(task_id, (&terminal).into());
         let mut manager = LAYER_MANAGER.lock_wait();
         manager.r#move(terminal.layer_id, Vector2D::new(100, 200));
         manager.activate(terminal.layer_id);
@@ -116,21 +157,25 @@ pub fn task_terminal(task_id: u64, pdesc: i64, _: u32) {
             .insert(terminal.layer_id, task_id);
     }
 
+    // 対話用のターミナルでは、最初に起動スクリプトを実行する
+    if desc.is_none() {
+        terminal.execute_rc_file();
+        terminal.load_history();
+    }
+
     if let Some(desc) = desc {
-        for arg in &desc.args {
-            for &b in arg.as_bytes() {
-                terminal.input_key(0, 0, b);
-            }
-            terminal.input_key(0, 0, b' ');
-        }
-        terminal.input_key(0, 0, b'\n');
+        terminal.execute_line(&desc.command);
 
         if desc.exit_affter_command {
+            // finish は戻ってこないので、パイプなどを閉じるために先にファイルを手放す
             drop(desc);
+            let exit_code = terminal.last_exit_code;
+            drop(terminal);
             asmfunc::cli();
-            task::finish(terminal.last_exit_code);
+            task::finish(exit_code);
         }
     }
+    terminal.prompt();
 
     let add_blink_timer = |t| {
         TIMER_MANAGER.lock_wait().add_timer(Timer::new(
@@ -177,6 +222,9 @@ pub fn task_terminal(task_id: u64, pdesc: i64, _: u32) {
             } => {
                 if press {
                     let mut area = terminal.input_key(modifier, keycode, ascii);
+                    if terminal.flow == Flow::Exit {
+                        close_terminal(terminal, task_id);
+                    }
                     if show_window {
                         area.pos += Window::TOP_LEFT_MARGIN;
                         let msg = Message::from_draw_area(task_id, terminal.layer_id, area);
@@ -186,24 +234,213 @@ pub fn task_terminal(task_id: u64, pdesc: i64, _: u32) {
                     }
                 }
             }
+            MessageType::MouseWheel { delta, .. } => {
+                if show_window {
+                    let mut area = terminal.scroll_view(delta * 3);
+                    area.pos += Window::TOP_LEFT_MARGIN;
+                    let msg = Message::from_draw_area(task_id, terminal.layer_id, area);
+                    asmfunc::cli();
+                    let _ = task::send_message(1, msg);
+                    asmfunc::sti();
+                }
+            }
+            ty @ (MessageType::MouseMove { .. } | MessageType::MouseButton { .. }) => {
+                if show_window && terminal.input_mouse(ty) && terminal.search.is_none() {
+                    terminal.reset_view();
+                    terminal.draw_cursor(false);
+                    let mut area = terminal.paste_line();
+                    terminal.draw_cursor(true);
+                    area.pos += Window::TOP_LEFT_MARGIN;
+                    let msg = Message::from_draw_area(task_id, terminal.layer_id, area);
+                    asmfunc::cli();
+                    let _ = task::send_message(1, msg);
+                    asmfunc::sti();
+                }
+            }
             MessageType::WindowActive { activate } => {
                 window_isactive = activate;
                 let current_time = TIMER_MANAGER.lock_wait().current_tick();
                 add_blink_timer(current_time);
             }
-            MessageType::WindowClose { layer_id } => {
-                let _ = layer::close_layer(layer_id);
-                asmfunc::cli();
-                task::finish(terminal.last_exit_code);
-            }
+            MessageType::WindowClose { .. } => close_terminal(terminal, task_id),
+            MessageType::WindowResize { width, height, .. } => terminal.resize(width, height),
+            MessageType::TerminalWrite { data } => terminal.write_from_device(&data),
+            MessageType::TerminalSetTermios { termios } => terminal.set_termios(termios),
             _ => {}
         }
     }
 }
 
-const ROWS: usize = 15;
-const COLUMNS: usize = 60;
+/// ウィンドウを持つターミナル `terminal` を閉じ、タスクを終了する。
+fn close_terminal(terminal: Terminal, task_id: u64) -> ! {
+    devfs::unregister_terminal(task_id);
+    let _ = layer::close_layer(terminal.layer_id);
+    let exit_code = terminal.last_exit_code;
+    drop(terminal);
+    asmfunc::cli();
+    task::finish(exit_code);
+}
+
+/// V キーのキーコード。Ctrl+Shift+V で貼り付ける。
+const KEY_V: u8 = 0x19;
+
+/// 対話用のターミナルが起動時に実行するスクリプト。
+const RC_FILE: &str = "/mikanrc";
+/// スクリプトから呼び出せるスクリプトの深さの上限。
+///
+/// スクリプトはタスクのスタック（32 KiB）の上で再帰的に実行し、1 段あたり 2 KiB 強を使う。
+/// ガードページはないので、溢れる前に止める。
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+/// ターミナルの大きさの初期値。
+const DEFAULT_ROWS: usize = 15;
+const DEFAULT_COLUMNS: usize = 60;
+/// ウィンドウの枠をドラッグして変えられる大きさの最小値。
+const MIN_ROWS: usize = 2;
+const MIN_COLUMNS: usize = 10;
 const LINE_MAX: usize = 128;
+/// スクロールバックに残す行数の既定値。
+const DEFAULT_SCROLLBACK: usize = 1000;
+/// 履歴に残すコマンドの数の既定値。
+const DEFAULT_HISTSIZE: usize = 500;
+/// ホームディレクトリに置く、コマンドの履歴を保存するファイルの名前。
+const HISTORY_FILE: &str = "history";
+
+/// 補完の候補にする組み込みコマンド。
+const BUILTINS: [&str; 25] = [
+    "break", "cat", "cd", "clear", "continue", "date", "echo", "env", "exit", "export", "false",
+    "history", "ls", "lspci", "memstat", "mkdir", "mkfifo", "noterm", "pwd", "rm", "sh", "test",
+    "true", "ulimit", "unset",
+];
+
+/// 直後にコマンド名が来る予約語。
+const COMMAND_KEYWORDS: [&str; 7] = ["if", "then", "elif", "else", "do", "while", "until"];
+
+/// コマンドの実行後に、続くコマンドをどう実行するか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    /// 続けて実行する。
+    Normal,
+    /// `break`: 最も内側のループを抜ける。
+    Break,
+    /// `continue`: 最も内側のループの次の繰り返しに移る。
+    Continue,
+    /// `exit`: スクリプトもしくはターミナルを終了する。
+    Exit,
+}
+
+/// ioctl: 端末の設定 [Termios] を得る。
+pub const TCGETS: u64 = 0x5401;
+/// ioctl: 端末の設定 [Termios] を変える。
+pub const TCSETS: u64 = 0x5402;
+/// ioctl: 端末の大きさ [WinSize] を得る。
+pub const TIOCGWINSZ: u64 = 0x5413;
+
+/// [Termios::lflag]: 行単位で編集してから渡す（カノニカルモード）。
+pub const ICANON: u32 = 0x0002;
+/// [Termios::lflag]: 入力された文字を表示する。
+pub const ECHO: u32 = 0x0008;
+
+/// アプリから見た端末の設定。Linux の `struct termios` のうち、対応している部分のみを持つ。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termios {
+    pub lflag: u32,
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Self {
+            lflag: ICANON | ECHO,
+        }
+    }
+}
+
+/// 端末の大きさ。Linux の `struct winsize` と同じ並び。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WinSize {
+    /// 文字単位の行数。
+    pub rows: u16,
+    /// 文字単位の列数。
+    pub cols: u16,
+    /// ピクセル単位の幅。
+    pub xpixel: u16,
+    /// ピクセル単位の高さ。
+    pub ypixel: u16,
+}
+
+/// 画面もしくはスクロールバックの 1 行。
+#[derive(Clone)]
+struct Row {
+    cells: Vec<Cell>,
+    /// 行末で折り返し、次の行に続いているかどうか。
+    wrapped: bool,
+}
+
+impl Deref for Row {
+    type Target = Vec<Cell>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cells
+    }
+}
+
+impl DerefMut for Row {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cells
+    }
+}
+
+/// 履歴の逆方向検索 (Ctrl+R) の状態。
+struct HistorySearch {
+    /// 検索する文字列。
+    query: String,
+    /// 一致した履歴の位置。
+    found: Option<usize>,
+    /// 検索を始める前に入力していた行。
+    original: Vec<u8>,
+}
+
+/// スクロールバックの検索 (Ctrl+F) の状態。
+struct ScrollbackSearch {
+    /// 検索する文字列。
+    query: String,
+    /// 一致した行の、スクロールバックと画面を合わせた中での位置。
+    found: Option<usize>,
+    /// 直前の検索で見つからなかったかどうか。
+    failed: bool,
+}
+
+/// マウスで選択している範囲。位置はスクロールバックと画面を合わせた中での (行, 列) で表す。
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    /// ボタンを押した位置。
+    anchor: (usize, usize),
+    /// ドラッグしている位置。
+    end: (usize, usize),
+    /// ボタンを押したままかどうか。
+    dragging: bool,
+}
+
+impl Selection {
+    /// 選択範囲の始点と終点を、前から順に返す。終点の文字は含まない。
+    fn range(&self) -> ((usize, usize), (usize, usize)) {
+        if self.anchor <= self.end {
+            (self.anchor, self.end)
+        } else {
+            (self.end, self.anchor)
+        }
+    }
+
+    /// `line` 行目のうち、選択している列の範囲を返す。
+    fn columns(&self, line: usize, columns: usize) -> Range<usize> {
+        let (start, end) = self.range();
+        let first = if line == start.0 { start.1 } else { 0 };
+        let last = if line == end.0 { end.1 } else { columns };
+        first.min(columns)..last.min(columns)
+    }
+}
 
 pub struct Terminal {
     layer_id: u32,
@@ -212,13 +449,60 @@ pub struct Terminal {
     window: Option<Arc<SharedLock<Window>>>,
     cursor: Vector2D<i32>,
     cursor_visible: bool,
+    /// エスケープシーケンスでカーソルが隠されているかどうか。
+    cursor_hidden: bool,
+    /// `ESC 7` などで保存したカーソルの位置と属性。
+    saved_cursor: (Vector2D<i32>, Attr),
+    /// これから出力する文字の属性。
+    attr: Attr,
+    parser: Parser,
+    /// 大きさが変わったことをアプリに入力として知らせるかどうか。
+    resize_report: bool,
+    /// `print` の間に書き換えた行の範囲。
+    dirty_rows: Option<(i32, i32)>,
+    /// 入力中の行の長さ。
     linebuf_index: usize,
+    /// 入力中の行のうち、カーソルがある位置。
+    linebuf_cursor: usize,
     linebuf: [u8; LINE_MAX],
+    /// 入力中の行が始まる列。
+    line_start: i32,
     cmd_history: VecDeque<String>,
     cmd_history_index: i32,
+    /// 履歴ファイルに保存できなかったことを既に知らせたかどうか。
+    history_error_reported: bool,
+    /// 実行中のスクリプトの深さ。
+    script_depth: usize,
+    /// 履歴を検索している間の状態。
+    search: Option<HistorySearch>,
+    /// 文字単位の画面の大きさ。
+    rows: usize,
+    columns: usize,
+    /// 画面に表示している文字。
+    screen: Vec<Row>,
+    /// 代替画面を表示している間、元の画面を取っておく。
+    alt_screen: Option<Vec<Row>>,
+    /// 画面の上から押し出された行。古いものが先頭。
+    scrollback: VecDeque<Row>,
+    /// スクロールバックを遡って表示している行数。`0` の場合は最新の画面を表示している。
+    scroll_offset: usize,
+    /// スクロールバックを検索している間の状態。
+    scrollback_search: Option<ScrollbackSearch>,
+    selection: Option<Selection>,
+    /// アプリが標準入力を読むときの設定。
+    termios: Termios,
+    /// アプリが読み込めるようになった入力。
+    input: VecDeque<u8>,
+    /// カノニカルモードで編集中の行。
+    input_line: Vec<u8>,
     /// 標準入出力
     files: [Arc<Mutex<FileDescriptor>>; 3],
     last_exit_code: i32,
+    /// シェル変数。
+    vars: BTreeMap<String, String>,
+    /// 位置パラメータ。先頭は `$0`。
+    args: Vec<String>,
+    flow: Flow,
 }
 
 impl Terminal {
@@ -229,8 +513,8 @@ impl Terminal {
             true
         };
 
-        let files = if let Some(desc) = term_desc {
-            desc.files.clone()
+        let files = if let Some(files) = term_desc.and_then(|desc| desc.files.clone()) {
+            files
         } else {
             [
                 Arc::new(Mutex::new(FileDescriptor::new_term(
@@ -250,8 +534,8 @@ impl Terminal {
 
         let (layer_id, window) = if show_window {
             let mut window = Window::new_toplevel(
-                COLUMNS as u32 * 8 + 8 + Window::MARGIN_X,
-                ROWS as u32 * 16 + 8 + Window::MARGIN_Y,
+                DEFAULT_COLUMNS as u32 * 8 + 8 + Window::MARGIN_X,
+                DEFAULT_ROWS as u32 * 16 + 8 + Window::MARGIN_Y,
                 FB_CONFIG.as_ref().pixel_format,
                 "MikanTerm",
             );
@@ -260,7 +544,7 @@ impl Terminal {
 
             let mut manager = LAYER_MANAGER.lock_wait();
             let id = manager.new_layer(window);
-            manager.layer(id).set_draggable(true);
+            manager.layer(id).set_draggable(true).set_resizable(true);
             let window = manager.layer(id).window();
 
             (id, Some(window))
@@ -268,27 +552,48 @@ impl Terminal {
             (0, None)
         };
 
-        let mut cmd_history = VecDeque::new();
-        cmd_history.resize(8, String::new());
-
-        let mut ret = Self {
+        Self {
             layer_id,
             task_id: task.id(),
             window,
             cursor: Vector2D::new(0, 0),
             cursor_visible: false,
+            cursor_hidden: false,
+            saved_cursor: (Vector2D::new(0, 0), Attr::default()),
+            attr: Attr::default(),
+            parser: Parser::new(),
+            resize_report: false,
+            dirty_rows: None,
             linebuf_index: 0,
+            linebuf_cursor: 0,
             linebuf: [0u8; LINE_MAX],
-            cmd_history,
+            line_start: 0,
+            cmd_history: VecDeque::new(),
             cmd_history_index: -1,
+            history_error_reported: false,
+            script_depth: 0,
+            search: None,
+            rows: DEFAULT_ROWS,
+            columns: DEFAULT_COLUMNS,
+            screen: (0..DEFAULT_ROWS)
+                .map(|_| blank_line(Attr::default(), DEFAULT_COLUMNS))
+                .collect(),
+            alt_screen: None,
+            scrollback: VecDeque::new(),
+            scroll_offset: 0,
+            scrollback_search: None,
+            selection: None,
+            termios: Termios::default(),
+            input: VecDeque::new(),
+            input_line: Vec::new(),
             // TerminalRef はここでは設定できない（move が起こる）ので、
             // 戻ってから設定する
             files,
             last_exit_code: 0,
-        };
-
-        ret.print(">");
-        ret
+            vars: term_desc.map(|desc| desc.vars.clone()).unwrap_or_default(),
+            args: term_desc.map(|desc| desc.args.clone()).unwrap_or_default(),
+            flow: Flow::Normal,
+        }
     }
 
     pub fn blink_cursor(&mut self) -> Rectangle<i32> {
@@ -301,62 +606,33 @@ impl Terminal {
     }
 
     /// `linebuf` や `linebuf_index` を変更せずに文字列を表示する。
+    ///
+    /// 文字列に含まれるエスケープシーケンスは [ansi] に従って解釈する。
     pub fn print(&mut self, s: &str) {
-        let Some(window) = self.window.clone() else {
+        if self.window.is_none() {
             return;
-        };
+        }
 
-        let cursor_before = self.calc_curosr_pos();
+        self.clear_selection();
+        self.reset_view();
         self.draw_cursor(false);
-
-        let newline = |term: &mut Self| {
-            term.cursor = if term.cursor.y() < ROWS as i32 - 1 {
-                Vector2D::new(0, term.cursor.y() + 1)
-            } else {
-                term.scroll1();
-                Vector2D::new(0, term.cursor.y())
-            };
-        };
+        self.dirty_rows = Some((self.cursor.y(), self.cursor.y()));
 
         for c in s.chars() {
-            if c == '\n' {
-                newline(self);
-            } else if c.is_ascii() {
-                if self.cursor.x() == COLUMNS as i32 {
-                    newline(self)
-                }
-                font::write_unicode(
-                    &mut *window.write(),
-                    self.calc_curosr_pos(),
-                    c,
-                    &PixelColor::new(255, 255, 255),
-                );
-                self.cursor += Vector2D::new(1, 0);
-            } else {
-                if self.cursor.x() >= COLUMNS as i32 - 1 {
-                    newline(self);
-                }
-                font::write_unicode(
-                    &mut *window.write(),
-                    self.calc_curosr_pos(),
-                    c,
-                    &PixelColor::new(255, 255, 255),
-                );
-                self.cursor += Vector2D::new(2, 0);
+            if let Some(action) = self.parser.advance(c) {
+                self.perform(action);
             }
         }
 
         self.draw_cursor(true);
-        let cursor_after = self.calc_curosr_pos();
-
-        let draw_pos = Window::TOP_LEFT_MARGIN + Vector2D::new(0, cursor_before.y());
-        let draw_size = Vector2D::new(
-            window.read().width() as _,
-            cursor_after.y() - cursor_before.y() + 16,
-        );
+        let Some((top, bottom)) = self.dirty_rows.take() else {
+            return;
+        };
+        let top = top.min(self.cursor.y());
+        let bottom = bottom.max(self.cursor.y());
         let draw_area = Rectangle {
-            pos: draw_pos,
-            size: draw_size,
+            pos: Window::TOP_LEFT_MARGIN + Vector2D::new(0, 4 + 16 * top),
+            size: Vector2D::new(8 * self.columns as i32 + 8, 16 * (bottom - top + 1)),
         };
 
         let msg = Message::from_draw_area(self.task_id, self.layer_id, draw_area);
@@ -365,542 +641,2305 @@ impl Terminal {
         asmfunc::sti();
     }
 
-    pub fn input_key(&mut self, _modifier: u8, keycode: u8, ascii: u8) -> Rectangle<i32> {
-        self.draw_cursor(false);
-
-        let mut draw_area = Rectangle {
-            pos: self.calc_curosr_pos(),
-            size: Vector2D::new(8 * 2, 16),
-        };
-
-        match ascii {
-            0 => {
-                draw_area = match keycode {
-                    // down arrow
-                    0x51 => self.history_up_down(-1),
-                    // up arrow
-                    0x52 => self.history_up_down(1),
-                    _ => draw_area,
+    /// エスケープシーケンスを解釈した結果の処理を行う。
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => {
+                let width = if c.is_ascii() { 1 } else { 2 };
+                if self.cursor.x() + width > self.columns as i32 {
+                    self.screen[self.cursor.y() as usize].wrapped = true;
+                    self.newline();
+                }
+                let (x, y) = (self.cursor.x() as usize, self.cursor.y() as usize);
+                self.put_cell(x, y, Cell { c, attr: self.attr });
+                if width == 2 {
+                    self.put_cell(
+                        x + 1,
+                        y,
+                        Cell {
+                            c: '\0',
+                            attr: self.attr,
+                        },
+                    );
                 }
+                self.cursor += Vector2D::new(width, 0);
             }
-            b'\n' => {
-                let command =
-                    String::from(str::from_utf8(&self.linebuf[..self.linebuf_index]).unwrap());
-                if self.linebuf_index > 0 {
-                    self.cmd_history.pop_back();
-                    self.cmd_history.push_front(command.clone());
+            Action::Control('\n') => {
+                self.screen[self.cursor.y() as usize].wrapped = false;
+                self.newline();
+            }
+            Action::Control('\r') => self.cursor = Vector2D::new(0, self.cursor.y()),
+            Action::Control('\x08') => {
+                let x = (self.cursor.x() - 1).max(0);
+                self.cursor = Vector2D::new(x, self.cursor.y());
+            }
+            Action::Control('\t') => {
+                let x = ((self.cursor.x() / 8 + 1) * 8).min(self.columns as i32 - 1);
+                self.cursor = Vector2D::new(x, self.cursor.y());
+            }
+            Action::Control(_) => {}
+            Action::Esc(c) => match c {
+                '7' => self.saved_cursor = (self.cursor, self.attr),
+                '8' => self.restore_cursor(),
+                // Index
+                'D' => self.line_feed(),
+                // Next Line
+                'E' => self.newline(),
+                // Reverse Index
+                'M' => {
+                    if self.cursor.y() > 0 {
+                        self.cursor -= Vector2D::new(0, 1);
+                    } else {
+                        self.screen.pop();
+                        self.screen
+                            .insert(0, blank_line(self.attr.erased(), self.columns));
+                        self.draw_screen();
+                    }
                 }
-                self.linebuf_index = 0;
-                self.cmd_history_index = -1;
+                // Reset to Initial State
+                'c' => {
+                    self.attr = Attr::default();
+                    self.cursor_hidden = false;
+                    self.leave_alt_screen(false);
+                    self.erase_display(2);
+                    self.cursor = Vector2D::new(0, 0);
+                }
+                _ => {}
+            },
+            Action::Csi {
+                params,
+                private,
+                action,
+            } => self.perform_csi(&params, private, action),
+        }
+    }
 
-                self.cursor = if self.cursor.y() < ROWS as i32 - 1 {
-                    Vector2D::new(0, self.cursor.y() + 1)
-                } else {
-                    self.scroll1();
-                    Vector2D::new(0, self.cursor.y())
-                };
+    /// CSI シーケンスを処理する。
+    fn perform_csi(&mut self, params: &[u16], private: Option<char>, action: char) {
+        let param = |i: usize| params.get(i).copied().unwrap_or(0) as i32;
+        // 移動量などは、省略されたり 0 の場合は 1 として扱う
+        let count = param(0).max(1);
+        let (x, y) = (
+            self.cursor.x().min(self.columns as i32 - 1),
+            self.cursor.y(),
+        );
 
-                self.execute_line(command);
-                self.print(">");
-                if let Some(ref window) = self.window {
-                    draw_area.pos = Vector2D::new(0, 0);
-                    draw_area.size = window.read().size();
+        match (private, action) {
+            (None, 'A') => self.move_cursor(x, y - count),
+            (None, 'B') => self.move_cursor(x, y + count),
+            (None, 'C') => self.move_cursor(x + count, y),
+            (None, 'D') => self.move_cursor(x - count, y),
+            (None, 'E') => self.move_cursor(0, y + count),
+            (None, 'F') => self.move_cursor(0, y - count),
+            (None, 'G') => self.move_cursor(count - 1, y),
+            (None, 'd') => self.move_cursor(x, count - 1),
+            (None, 'H' | 'f') => self.move_cursor(param(1).max(1) - 1, count - 1),
+            (None, 'J') => {
+                self.erase_display(param(0));
+                if param(0) == 3 {
+                    self.scrollback.clear();
                 }
             }
-            0x08 => {
-                // backspace
-                if self.cursor.x() > 0 && self.linebuf_index > 0 {
-                    self.cursor -= Vector2D::new(1, 0);
-                    if let Some(ref window) = self.window {
-                        window.write().draw_rectangle(
-                            self.calc_curosr_pos(),
-                            Vector2D::new(8, 16),
-                            &PixelColor::new(0, 0, 0),
-                        );
-                    }
-                    self.linebuf_index -= 1;
-                }
-            }
-            ascii => {
-                if self.cursor.x() < COLUMNS as i32 - 1 && self.linebuf_index < LINE_MAX - 1 {
-                    self.linebuf[self.linebuf_index] = ascii;
-                    self.linebuf_index += 1;
-                    if let Some(ref window) = self.window {
-                        font::write_ascii(
-                            &mut *window.write(),
-                            self.calc_curosr_pos(),
-                            ascii,
-                            &PixelColor::new(255, 255, 255),
-                        );
+            (None, 'K') => {
+                let range = match param(0) {
+                    0 => x as usize..self.columns,
+                    1 => 0..x as usize + 1,
+                    _ => 0..self.columns,
+                };
+                self.erase_cells(y as usize, range);
+            }
+            (None, 'm') => self.attr.apply_sgr(params),
+            (None, 's') => self.saved_cursor = (self.cursor, self.attr),
+            (None, 'u') => self.restore_cursor(),
+            (Some('?'), 'h' | 'l') => {
+                let set = action == 'h';
+                for &mode in params {
+                    match mode {
+                        25 => self.cursor_hidden = !set,
+                        2048 => {
+                            self.resize_report = set;
+                            if set {
+                                self.report_size();
+                            }
+                        }
+                        47 | 1047 | 1049 if set => self.enter_alt_screen(mode == 1049),
+                        47 | 1047 | 1049 => self.leave_alt_screen(mode == 1049),
+                        _ => {}
                     }
-                    self.cursor += Vector2D::new(1, 0);
                 }
             }
+            _ => {}
         }
+    }
 
-        self.draw_cursor(true);
+    /// カーソルを画面内に収まるように `(x, y)` へ移動する。
+    fn move_cursor(&mut self, x: i32, y: i32) {
+        self.cursor = Vector2D::new(
+            x.clamp(0, self.columns as i32 - 1),
+            y.clamp(0, self.rows as i32 - 1),
+        );
+    }
 
-        draw_area
+    /// 保存しておいたカーソル位置と属性に戻す。保存後に画面が縮んでいても画面内に収める。
+    fn restore_cursor(&mut self) {
+        let (cursor, attr) = self.saved_cursor;
+        self.attr = attr;
+        self.move_cursor(cursor.x(), cursor.y());
     }
 
-    pub fn redraw(&mut self) {
-        let Some(ref wnd) = self.window else {
-            return;
-        };
-        let draw_area = Rectangle {
-            pos: Window::TOP_LEFT_MARGIN,
-            size: wnd.read().size(),
-        };
+    /// カーソルを次の行の先頭に移す。最下行では画面をスクロールする。
+    fn newline(&mut self) {
+        self.line_feed();
+        self.cursor = Vector2D::new(0, self.cursor.y());
+    }
 
-        let msg = Message::from_draw_area(self.task_id, self.layer_id, draw_area);
-        asmfunc::cli();
-        let _ = task::send_message(1, msg);
-        asmfunc::sti();
+    /// カーソルを 1 行下に移す。最下行では画面をスクロールする。
+    fn line_feed(&mut self) {
+        if self.cursor.y() < self.rows as i32 - 1 {
+            self.cursor += Vector2D::new(0, 1);
+        } else {
+            self.scroll1();
+        }
     }
 
-    fn draw_cursor(&mut self, visible: bool) {
-        let Some(ref window) = self.window else {
-            return;
+    /// ED (Erase in Display) の `mode` に従って画面を消去する。
+    fn erase_display(&mut self, mode: i32) {
+        let (x, y) = (self.cursor.x() as usize, self.cursor.y() as usize);
+        let rows = match mode {
+            0 => {
+                self.erase_cells(y, x.min(self.columns)..self.columns);
+                y + 1..self.rows
+            }
+            1 => {
+                self.erase_cells(y, 0..(x + 1).min(self.columns));
+                0..y
+            }
+            _ => 0..self.rows,
         };
+        for y in rows {
+            self.erase_cells(y, 0..self.columns);
+        }
+    }
 
-        self.cursor_visible = visible;
-        let color = if self.cursor_visible { 0xffffff } else { 0 };
-        let color = PixelColor::to_color(color);
-        let pos = Vector2D::new(4 + 8 * self.cursor.x(), 5 + 16 * self.cursor.y());
-
-        window
-            .write()
-            .fill_rectangle(pos, Vector2D::new(7, 15), &color);
+    /// `y` 行目の `range` の文字を消去する。
+    fn erase_cells(&mut self, y: usize, range: Range<usize>) {
+        if range.end == self.columns {
+            self.screen[y].wrapped = false;
+        }
+        let blank = Cell::blank(self.attr.erased());
+        for x in range {
+            self.put_cell(x, y, blank);
+        }
     }
 
-    fn calc_curosr_pos(&self) -> Vector2D<i32> {
-        Vector2D::new(4 + 8 * self.cursor.x(), 4 + 16 * self.cursor.y())
+    /// 代替画面に切り替える。元の画面は、戻るときのために取っておく。
+    fn enter_alt_screen(&mut self, save_cursor: bool) {
+        if self.alt_screen.is_some() {
+            return;
+        }
+        if save_cursor {
+            self.saved_cursor = (self.cursor, self.attr);
+        }
+        let screen = (0..self.rows)
+            .map(|_| blank_line(self.attr.erased(), self.columns))
+            .collect();
+        self.alt_screen = Some(mem::replace(&mut self.screen, screen));
+        self.draw_screen();
     }
 
-    fn scroll1(&mut self) {
-        let Some(ref window) = self.window else {
+    /// 代替画面から元の画面に戻る。
+    fn leave_alt_screen(&mut self, restore_cursor: bool) {
+        let Some(screen) = self.alt_screen.take() else {
             return;
         };
-        let move_src = Rectangle {
-            pos: Vector2D::new(4, 4 + 16),
-            size: Vector2D::new(8 * COLUMNS as i32, 16 * (ROWS as i32 - 1)),
-        };
-        let mut window = window.write();
-        window.r#move(Vector2D::new(4, 4), &move_src);
-        window.fill_rectangle(
-            Vector2D::new(4, 4 + 16 * self.cursor.y()),
-            Vector2D::new(8 * COLUMNS as i32, 16),
-            &PixelColor::new(0, 0, 0),
-        );
+        self.screen = screen;
+        if restore_cursor {
+            self.restore_cursor();
+        }
+        self.draw_screen();
     }
 
-    fn execute_line(&mut self, command: String) {
-        // ターミナルとしての標準出力を保持する
-        let mut fd_term_out = None;
-
-        // > リダイレクトの指定
-        let (command, redir_dest) = if let Some(sp) = command.split_once('>') {
-            (String::from(sp.0), Some(sp.1.trim()))
-        } else {
-            (command, None)
+    /// `(x, y)` の文字を `cell` に置き換えて描く。
+    fn put_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        let Some(&old) = self.screen.get(y).and_then(|line| line.get(x)) else {
+            return;
         };
+        self.screen[y][x] = cell;
+        self.mark_dirty(y as i32);
+        if cell.c != '\0' {
+            if let Some(ref window) = self.window {
+                draw_cell(&mut window.write(), x, y, cell, false);
+            }
+        }
 
-        if let Some(redir_dest) = redir_dest {
-            let file = match fat::find_file(redir_dest, 0) {
-                (Some(f), false) => {
-                    if f.attr == Attribute::Directory as u8 {
-                        file::print_to_fd(
-                            &mut self.files[2].lock_wait(),
-                            "cannot redirect to a directory",
-                        );
-                        self.last_exit_code = 1;
-                        return;
-                    } else {
-                        f
-                    }
-                }
-                (None, false) => match fat::create_file(redir_dest) {
-                    Ok(f) => f,
-                    Err(e) => {
-                        file::print_to_fd(
-                            &mut self.files[2].lock_wait(),
-                            &format!("failed to create a redirect file: {}\n", e),
-                        );
-                        self.last_exit_code = 1;
-                        return;
-                    }
-                },
-                (_, true) => {
-                    file::print_to_fd(
-                        &mut self.files[2].lock_wait(),
-                        "cannot redirect to a directory",
-                    );
-                    self.last_exit_code = 1;
-                    return;
-                }
-            };
+        // 全角文字の片側だけが上書きされたら、残った側を消す
+        let blank = Cell::blank(old.attr);
+        if old.c == '\0' && cell.c != '\0' && x > 0 && self.screen[y][x - 1].c != '\0' {
+            self.put_cell(x - 1, y, blank);
+        } else if !old.c.is_ascii()
+            && cell.c.is_ascii()
+            && self.screen[y].get(x + 1).is_some_and(|next| next.c == '\0')
+        {
+            self.put_cell(x + 1, y, blank);
+        }
+    }
 
-            let mut new = Arc::new(Mutex::new(FileDescriptor::new_fat(file)));
-            mem::swap(&mut self.files[1], &mut new);
-            fd_term_out = Some(new);
+    /// 画面の文字をすべて描き直す。
+    fn draw_screen(&mut self) {
+        if let Some(ref window) = self.window {
+            let mut window = window.write();
+            for (y, line) in self.screen.iter().enumerate() {
+                draw_cells(&mut window, y, line);
+            }
         }
+        self.dirty_rows = Some((0, self.rows as i32 - 1));
+    }
 
-        // パイプ
-        let (command, subcommand) = match command.split_once('|') {
-            Some(sp) => (String::from(sp.0), Some(sp.1)),
-            None => (command, None),
-        };
-        let subtask_id = if let Some(subcommand) = subcommand {
-            let subtask = task::new_task();
+    fn mark_dirty(&mut self, y: i32) {
+        if let Some((top, bottom)) = self.dirty_rows.as_mut() {
+            *top = (*top).min(y);
+            *bottom = (*bottom).max(y);
+        }
+    }
 
-            asmfunc::cli();
-            // 今発行したばかりの Task なので、必ずある
-            let subtask_arc = task::get_task(subtask.id()).unwrap();
-            asmfunc::sti();
-            let pipe_fd = Arc::new(Mutex::new(FileDescriptor::new_pipe(subtask_arc)));
+    pub fn termios(&self) -> Termios {
+        self.termios
+    }
 
-            let args = subcommand
-                .split(' ')
-                .filter_map(|s| {
-                    if s.is_empty() {
-                        None
-                    } else {
-                        Some(String::from(s))
-                    }
-                })
-                .collect();
+    pub fn set_termios(&mut self, termios: Termios) {
+        // カノニカルモードをやめたら、編集中の行はそのまま読めるようにする
+        if termios.lflag & ICANON == 0 {
+            self.input.extend(self.input_line.drain(..));
+        }
+        self.termios = termios;
+        self.publish_settings();
+    }
 
-            // 今の task と subtask をパイプで繋げる
-            let term_desc = Box::new(TerminalDescriptor {
-                args,
-                exit_affter_command: true,
-                show_window: false,
-                files: [
-                    pipe_fd.clone(),
-                    self.files[1].clone(),
-                    self.files[2].clone(),
-                ],
-            });
+    /// 他のタスクが `/dev/tty<id>` から読めるように、現在の設定を記録する。
+    fn publish_settings(&self) {
+        devfs::update_terminal(self.task_id, self.termios, self.window_size());
+    }
 
-            let mut new_stdout = pipe_fd;
-            mem::swap(&mut self.files[1], &mut new_stdout);
-            if fd_term_out.is_none() {
-                fd_term_out = Some(new_stdout);
-            };
+    /// アプリが変えたままにした入力の設定や表示の状態を、アプリの終了後に既定に戻す。
+    fn reset_app_state(&mut self) {
+        self.termios = Termios::default();
+        self.input.clear();
+        self.input_line.clear();
+        self.leave_alt_screen(true);
+        self.attr = Attr::default();
+        self.cursor_hidden = false;
+        self.resize_report = false;
+        self.parser = Parser::new();
+        self.publish_settings();
+    }
 
-            let id = subtask
-                .init_context(task_terminal, Box::into_raw(term_desc) as _, 0)
-                .wake_up(-1)
-                .id();
-            LAYER_TASK_MAP.lock_wait().insert(self.layer_id, id);
-            id
-        } else {
-            0
-        };
+    pub fn window_size(&self) -> WinSize {
+        WinSize {
+            rows: self.rows as u16,
+            cols: self.columns as u16,
+            xpixel: 8 * self.columns as u16,
+            ypixel: 16 * self.rows as u16,
+        }
+    }
 
-        let args: Vec<_> = command.split(' ').filter(|s| !s.is_empty()).collect();
+    /// `/dev/tty<id>` に書き込まれた `data` を表示する。
+    pub fn write_from_device(&mut self, data: &[u8]) {
+        self.print(&String::from_utf8_lossy(data));
+        self.redraw();
+    }
+
+    pub fn layer_id(&self) -> u32 {
+        self.layer_id
+    }
 
-        let Some(&command) = args.first() else {
+    /// ウィンドウ全体の大きさが `width` x `height` 程度になるように、ターミナルの大きさを変える。
+    ///
+    /// 実際の大きさは文字単位に切り捨てる。画面とスクロールバックは、新しい幅で折り返し直す。
+    pub fn resize(&mut self, width: i32, height: i32) {
+        let Some(window) = self.window.clone() else {
             return;
         };
-        'exe: {
-            match command {
-                "echo" => {
-                    match args.get(1) {
-                        Some(&"$?") => {
-                            file::print_to_fd(
-                                &mut self.files[1].lock_wait(),
-                                &format!("{}", self.last_exit_code),
-                            );
-                        }
-                        Some(arg) => {
-                            file::print_to_fd(&mut self.files[1].lock_wait(), arg);
-                        }
-                        None => {}
-                    }
-                    self.print("\n");
-                    self.last_exit_code = 0;
-                }
-                "clear" => {
-                    if let Some(ref window) = self.window {
-                        window.write().fill_rectangle(
-                            Vector2D::new(4, 4),
-                            Vector2D::new(8 * COLUMNS as i32, 16 * ROWS as i32),
-                            &PixelColor::new(0, 0, 0),
-                        );
-                        self.cursor = Vector2D::new(self.cursor.x(), 0);
-                    }
-                    self.last_exit_code = 0;
-                }
-                "lspci" => {
-                    for dev in pci::DEVICES.read().iter() {
-                        let vendor_id = dev.read_vendor_id();
-                        let s = format!(
-                            "{:02x}:{:02x}.{} vend={:04x} head={:02x} class={:02x}.{:02x}:{:02x}\n",
-                            dev.bus(),
-                            dev.device(),
-                            dev.function(),
-                            vendor_id,
-                            dev.header_type(),
-                            dev.class_code().base(),
-                            dev.class_code().sub(),
-                            dev.class_code().interface(),
-                        );
-                        file::print_to_fd(&mut self.files[1].lock_wait(), &s);
-                    }
-                    self.last_exit_code = 0;
-                }
-                "ls" => {
-                    let Some(&first_arg) = args.get(1) else {
-                        self.list_all_entries(fat::BOOT_VOLUME_IMAGE.get().root_clus());
-                        break 'exe;
-                    };
+        let columns = (width - Window::MARGIN_X as i32 - 8) / 8;
+        let rows = (height - Window::MARGIN_Y as i32 - 8) / 16;
+        let columns = (columns.max(0) as usize).max(MIN_COLUMNS);
+        let rows = (rows.max(0) as usize).max(MIN_ROWS);
+        if (rows, columns) == (self.rows, self.columns) {
+            return;
+        }
 
-                    let (Some(dir), post_slash) = fat::find_file(first_arg, 0) else {
-                        let mut stderr = self.files[2].lock_wait();
-                        file::print_to_fd(&mut stderr, "No such file or directory: ");
-                        file::print_to_fd(&mut stderr, first_arg);
-                        file::print_to_fd(&mut stderr, "\n");
+        self.draw_cursor(false);
+        self.scroll_offset = 0;
+        self.scrollback_search = None;
+        self.selection = None;
+        self.reflow(rows, columns);
+
+        let size = Vector2D::new(
+            columns as i32 * 8 + 8 + Window::MARGIN_X as i32,
+            rows as i32 * 16 + 8 + Window::MARGIN_Y as i32,
+        );
+        LAYER_MANAGER.lock_wait().resize(self.layer_id, size);
+        {
+            let mut window = window.write();
+            let size = window.size();
+            window.draw_terminal(Vector2D::new(0, 0), size);
+        }
+        self.draw_screen();
+        // 入力中の行は、カーソルと同じ行にあるものとして扱い直す
+        self.line_start = (self.cursor.x() - self.linebuf_cursor as i32).max(0);
+        self.draw_cursor(true);
+        self.redraw();
+
+        self.publish_settings();
+        if self.resize_report {
+            self.report_size();
+        }
+    }
+
+    /// 画面とスクロールバックを `rows` 行 `columns` 列に合わせて折り返し直す。
+    fn reflow(&mut self, rows: usize, columns: usize) {
+        self.rows = rows;
+        self.columns = columns;
+        let saved = &mut self.saved_cursor.0;
+        *saved = Vector2D::new(
+            saved.x().clamp(0, columns as i32 - 1),
+            saved.y().clamp(0, rows as i32 - 1),
+        );
+
+        // 代替画面を使うアプリは大きさの変化を受けて描き直すので、折り返さずに切り詰める
+        if self.alt_screen.is_some() {
+            for screen in iter::once(&mut self.screen).chain(self.alt_screen.as_mut()) {
+                screen.resize(rows, blank_line(Attr::default(), columns));
+                for line in screen {
+                    line.resize(columns, Cell::default());
+                }
+            }
+            self.move_cursor(self.cursor.x(), self.cursor.y());
+            return;
+        }
+
+        let cursor_y = self.scrollback.len() + self.cursor.y() as usize;
+        let mut lines: Vec<_> = self
+            .scrollback
+            .drain(..)
+            .chain(self.screen.drain(..))
+            .collect();
+        // カーソルより下の空行は、画面を埋めるためにあるだけなので捨てる
+        while lines.len() > cursor_y + 1
+            && lines
+                .last()
+                .is_some_and(|line| line.iter().all(|&cell| cell == Cell::default()))
+        {
+            lines.pop();
+        }
+
+        let (mut lines, (x, y)) = reflow(lines, (self.cursor.x() as usize, cursor_y), columns);
+        // カーソルのある行が画面に入るように、画面の先頭の行を決める
+        let top = lines.len().saturating_sub(rows).min(y);
+        lines.truncate(top + rows);
+        self.screen = lines.split_off(top);
+        self.screen
+            .resize(rows, blank_line(Attr::default(), columns));
+        self.scrollback = lines.into();
+        let limit = self.scrollback_limit();
+        while self.scrollback.len() > limit {
+            self.scrollback.pop_front();
+        }
+        self.cursor = Vector2D::new(x as i32, (y - top) as i32);
+    }
+
+    /// アプリが大きさの変化の通知 (`CSI ? 2048 h`) を求めている場合に、現在の大きさを入力として渡す。
+    fn report_size(&mut self) {
+        let size = self.window_size();
+        let report = format!(
+            "\x1b[48;{};{};{};{}t",
+            size.rows, size.cols, size.ypixel, size.xpixel
+        );
+        self.input.extend(report.bytes());
+    }
+
+    /// アプリが標準入力から読み込むキー入力を `buf` に書き込み、書き込んだバイト数を返す。
+    ///
+    /// カノニカルモードでは 1 行の入力が終わるまで待ち、行頭での Ctrl+D を EOF として `0` を返す。
+    /// 非カノニカルモードでは 1 バイトでも入力があれば返し、矢印キーなどはエスケープシーケンスで表す。
+    pub fn read_input(&mut self, task: &Task, buf: &mut [u8]) -> usize {
+        loop {
+            if !self.input.is_empty() {
+                let len = buf.len().min(self.input.len());
+                for (dst, src) in buf.iter_mut().zip(self.input.drain(..len)) {
+                    *dst = src;
+                }
+                return len;
+            }
+
+            // Task::recieve_message は Mutex でガードされているので、
+            // 割り込みは禁止しなくて良い
+            let msg = match task.receive_message() {
+                Some(m) => m,
+                None => {
+                    task.sleep();
+                    continue;
+                }
+            };
+            let (modifier, keycode, ascii) = match msg.ty {
+                MessageType::KeyPush {
+                    modifier,
+                    keycode,
+                    ascii,
+                    press: true,
+                } => (modifier, keycode, ascii),
+                // アプリの実行中でもウィンドウの大きさは変えられる
+                MessageType::WindowResize { width, height, .. } => {
+                    self.resize(width, height);
+                    continue;
+                }
+                MessageType::TerminalWrite { data } => {
+                    self.write_from_device(&data);
+                    continue;
+                }
+                MessageType::TerminalSetTermios { termios } => {
+                    self.set_termios(termios);
+                    continue;
+                }
+                ty @ (MessageType::MouseMove { .. } | MessageType::MouseButton { .. }) => {
+                    if self.input_mouse(ty) {
+                        self.paste_input();
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            self.clear_selection();
+            let ctrl = modifier.get_bit(LCONTROL_BIT) || modifier.get_bit(RCONTROL_BIT);
+            let shift = modifier.get_bit(LSHIFT_BIT) || modifier.get_bit(RSHIFT_BIT);
+            if ctrl && shift && keycode == KEY_V {
+                self.paste_input();
+            } else if self.termios.lflag & ICANON == 0 {
+                self.input_raw(ctrl, keycode, ascii);
+            } else if self.input_canonical(ctrl, keycode, ascii) {
+                // EOF
+                return 0;
+            }
+        }
+    }
+
+    /// カノニカルモードでキー入力を処理する。EOF が入力された場合は `true` を返す。
+    fn input_canonical(&mut self, ctrl: bool, keycode: u8, ascii: u8) -> bool {
+        let echo = self.termios.lflag & ECHO != 0;
+        match (ctrl, ascii) {
+            (true, _) => match keycode {
+                // D
+                7 => {
+                    if self.input_line.is_empty() {
+                        return true;
+                    }
+                    self.input.extend(self.input_line.drain(..));
+                }
+                // U: 行を消す
+                24 => {
+                    if echo {
+                        self.print(&"\x08 \x08".repeat(self.input_line.len()));
+                    }
+                    self.input_line.clear();
+                }
+                _ => {
+                    if echo {
+                        self.print(&format!("^{}", ascii.to_ascii_uppercase() as char));
+                    }
+                }
+            },
+            (false, 0) => {}
+            (false, 0x08) => {
+                if self.input_line.pop().is_some() && echo {
+                    self.print("\x08 \x08");
+                }
+            }
+            (false, ascii) => {
+                self.input_line.push(ascii);
+                if ascii == b'\n' {
+                    self.input.extend(self.input_line.drain(..));
+                }
+                if echo {
+                    self.print(str::from_utf8(&[ascii]).unwrap_or_default());
+                }
+            }
+        }
+        false
+    }
+
+    /// 非カノニカルモードでキー入力を処理する。
+    fn input_raw(&mut self, ctrl: bool, keycode: u8, ascii: u8) {
+        let byte;
+        let bytes: &[u8] = match (ctrl, ascii) {
+            // Esc キーは 0x08 に割り当てられているので、キーコードで見分ける
+            (_, _) if keycode == 0x29 => b"\x1b",
+            (false, 0) => match special_key_sequence(keycode) {
+                Some(seq) => seq,
+                None => return,
+            },
+            (true, 0x40..=0x7f) => {
+                byte = [ascii & 0x1f];
+                &byte
+            }
+            _ => {
+                byte = [ascii];
+                &byte
+            }
+        };
+        self.input.extend(bytes);
+
+        if self.termios.lflag & ECHO != 0 && bytes.iter().all(|b| !b.is_ascii_control()) {
+            self.print(str::from_utf8(bytes).unwrap_or_default());
+        }
+    }
+
+    /// クリップボードのテキストを、アプリへの入力として渡す。
+    ///
+    /// カノニカルモードでは、改行までを 1 行の入力として扱う。
+    fn paste_input(&mut self) {
+        let Some(content) = clipboard::get() else {
+            return;
+        };
+        let Some(text) = content.text() else {
+            return;
+        };
+
+        if self.termios.lflag & ICANON == 0 {
+            self.input.extend(text.bytes());
+        } else {
+            for b in text.bytes() {
+                self.input_line.push(b);
+                if b == b'\n' {
+                    self.input.extend(self.input_line.drain(..));
+                }
+            }
+        }
+        if self.termios.lflag & ECHO != 0 {
+            self.print(text);
+        }
+    }
+
+    pub fn input_key(&mut self, modifier: u8, keycode: u8, ascii: u8) -> Rectangle<i32> {
+        self.draw_cursor(false);
+
+        let mut draw_area = Rectangle {
+            pos: self.calc_curosr_pos(),
+            size: Vector2D::new(8 * 2, 16),
+        };
+        let ctrl = modifier.get_bit(LCONTROL_BIT) || modifier.get_bit(RCONTROL_BIT);
+        let shift = modifier.get_bit(LSHIFT_BIT) || modifier.get_bit(RSHIFT_BIT);
+        self.clear_selection();
+
+        // Shift+PageUp/PageDown: スクロールバックを 1 画面分ずつ遡る・戻る
+        if shift && matches!(keycode, 0x4b | 0x4e) {
+            let lines = self.rows as i32 - 1;
+            return self.scroll_view(if keycode == 0x4b { lines } else { -lines });
+        }
+        if self.scrollback_search.is_some() {
+            return self.input_scrollback_search(ctrl, keycode, ascii);
+        }
+        // Ctrl+F: スクロールバックを検索する
+        if ctrl && keycode == 9 {
+            return self.start_scrollback_search();
+        }
+        self.reset_view();
+
+        // Ctrl+Shift+V: クリップボードの内容を貼り付ける
+        if ctrl && shift && keycode == KEY_V && self.search.is_none() {
+            let area = self.paste_line();
+            self.draw_cursor(true);
+            return area;
+        }
+
+        // 履歴の検索中に検索に使わないキーが押されたら、検索を終えてそのキーを続けて処理する
+        if self.search.is_some() {
+            if let Some(area) = self.input_search(ctrl, keycode, ascii) {
+                self.draw_cursor(true);
+                return area;
+            }
+            draw_area = self.redraw_line();
+        }
+
+        match (ctrl, ascii) {
+            (true, _) => {
+                // R
+                if keycode == 21 {
+                    draw_area = self.start_search();
+                } else if self.edit_with_ctrl(keycode) {
+                    draw_area = self.redraw_line();
+                }
+            }
+            (false, 0) => {
+                draw_area = match keycode {
+                    // down arrow
+                    0x51 => self.history_up_down(-1),
+                    // up arrow
+                    0x52 => self.history_up_down(1),
+                    // left arrow
+                    0x50 => {
+                        self.linebuf_cursor = self.linebuf_cursor.saturating_sub(1);
+                        self.redraw_line()
+                    }
+                    // right arrow
+                    0x4f => {
+                        self.linebuf_cursor = (self.linebuf_cursor + 1).min(self.linebuf_index);
+                        self.redraw_line()
+                    }
+                    // Home
+                    0x4a => {
+                        self.linebuf_cursor = 0;
+                        self.redraw_line()
+                    }
+                    // End
+                    0x4d => {
+                        self.linebuf_cursor = self.linebuf_index;
+                        self.redraw_line()
+                    }
+                    // Delete
+                    0x4c => {
+                        if self.linebuf_cursor < self.linebuf_index {
+                            self.remove_chars(self.linebuf_cursor..self.linebuf_cursor + 1);
+                        }
+                        self.redraw_line()
+                    }
+                    _ => draw_area,
+                }
+            }
+            (false, b'\n') => {
+                let line =
+                    String::from(str::from_utf8(&self.linebuf[..self.linebuf_index]).unwrap());
+                self.linebuf_index = 0;
+                self.linebuf_cursor = 0;
+                self.cmd_history_index = -1;
+
+                self.cursor = if self.cursor.y() < self.rows as i32 - 1 {
+                    Vector2D::new(0, self.cursor.y() + 1)
+                } else {
+                    self.scroll1();
+                    Vector2D::new(0, self.cursor.y())
+                };
+
+                // 履歴を参照していれば、置き換えた後のコマンドを表示してから実行する
+                match self.expand_history(&line) {
+                    Ok(command) => {
+                        if command != line {
+                            self.print(&format!("{}\n", command));
+                        }
+                        if !command.trim().is_empty() {
+                            self.add_history(command.clone());
+                        }
+                        self.execute_line(&command);
+                    }
+                    Err(msg) => {
+                        self.print(&msg);
+                        self.last_exit_code = 1;
+                    }
+                }
+                self.prompt();
+                if let Some(ref window) = self.window {
+                    draw_area.pos = Vector2D::new(0, 0);
+                    draw_area.size = window.read().size();
+                }
+            }
+            (false, 0x08) => {
+                // backspace
+                if self.linebuf_cursor > 0 {
+                    self.remove_chars(self.linebuf_cursor - 1..self.linebuf_cursor);
+                }
+                draw_area = self.redraw_line();
+            }
+            (false, b'\t') => draw_area = self.complete(),
+            (false, ascii) => {
+                self.insert(&[ascii]);
+                draw_area = self.redraw_line();
+            }
+        }
+
+        self.draw_cursor(true);
+
+        draw_area
+    }
+
+    pub fn redraw(&mut self) {
+        let Some(ref wnd) = self.window else {
+            return;
+        };
+        let draw_area = Rectangle {
+            pos: Window::TOP_LEFT_MARGIN,
+            size: wnd.read().size(),
+        };
+
+        let msg = Message::from_draw_area(self.task_id, self.layer_id, draw_area);
+        asmfunc::cli();
+        let _ = task::send_message(1, msg);
+        asmfunc::sti();
+    }
+
+    fn draw_cursor(&mut self, visible: bool) {
+        let Some(ref window) = self.window else {
+            return;
+        };
+        // スクロールバックを表示している間は、カーソルを表示しない
+        if self.is_viewing_scrollback() {
+            return;
+        }
+
+        self.cursor_visible = visible && !self.cursor_hidden;
+        let (x, y) = (self.cursor.x() as usize, self.cursor.y() as usize);
+        let mut window = window.write();
+        // 文字の上にあるカーソルは、その文字を反転して表示する
+        match self.screen[y].get(x) {
+            Some(&cell) if cell.c != '\0' => {
+                draw_cell(&mut window, x, y, cell, self.cursor_visible)
+            }
+            _ => {
+                let color = if self.cursor_visible {
+                    ansi::DEFAULT_FG
+                } else {
+                    ansi::DEFAULT_BG
+                };
+                window.fill_rectangle(cell_pos(x, y), Vector2D::new(8, 16), &color);
+            }
+        }
+    }
+
+    fn calc_curosr_pos(&self) -> Vector2D<i32> {
+        Vector2D::new(4 + 8 * self.cursor.x(), 4 + 16 * self.cursor.y())
+    }
+
+    fn scroll1(&mut self) {
+        let Some(ref window) = self.window else {
+            return;
+        };
+
+        let line = self.screen.remove(0);
+        self.screen
+            .push(blank_line(self.attr.erased(), self.columns));
+        // 押し出された行はスクロールバックに残す。代替画面の行は残さない
+        if self.alt_screen.is_none() {
+            self.scrollback.push_back(line);
+            let limit = self.scrollback_limit();
+            while self.scrollback.len() > limit {
+                self.scrollback.pop_front();
+            }
+        }
+
+        let move_src = Rectangle {
+            pos: Vector2D::new(4, 4 + 16),
+            size: Vector2D::new(8 * self.columns as i32, 16 * (self.rows as i32 - 1)),
+        };
+        let mut window = window.write();
+        window.r#move(Vector2D::new(4, 4), &move_src);
+        draw_cells(&mut window, self.rows - 1, &self.screen[self.rows - 1]);
+        if self.dirty_rows.is_some() {
+            self.dirty_rows = Some((0, self.rows as i32 - 1));
+        }
+    }
+
+    fn execute_line(&mut self, line: &str) {
+        let list = match shell::parse(line) {
+            Ok(list) => list,
+            Err(msg) => {
+                let msg = format!("syntax error: {}\n", msg);
+                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                self.last_exit_code = 2;
+                return;
+            }
+        };
+        self.execute_list(&list);
+        // ループの外の break と continue は何もしない
+        if matches!(self.flow, Flow::Break | Flow::Continue) {
+            self.flow = Flow::Normal;
+        }
+    }
+
+    /// スクリプト `script` を、位置パラメータを `args` にして実行する。
+    ///
+    /// スクリプトはこのターミナルで実行するので、変数やカレントディレクトリの変更は終了後も残る。
+    /// `exit` はスクリプトの実行のみを終える。
+    fn execute_script(&mut self, script: &str, args: Vec<String>) {
+        if self.script_depth >= MAX_SCRIPT_DEPTH {
+            let name = args.first().map_or("sh", String::as_str);
+            let msg = format!("{}: scripts nested too deeply\n", name);
+            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+            self.last_exit_code = 1;
+            return;
+        }
+
+        let list = match shell::parse(script) {
+            Ok(list) => list,
+            Err(msg) => {
+                let name = args.first().map_or("sh", String::as_str);
+                let msg = format!("{}: syntax error: {}\n", name, msg);
+                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                self.last_exit_code = 2;
+                return;
+            }
+        };
+
+        let saved_args = mem::replace(&mut self.args, args);
+        self.script_depth += 1;
+        self.execute_list(&list);
+        self.script_depth -= 1;
+        self.args = saved_args;
+        self.flow = Flow::Normal;
+    }
+
+    /// 起動スクリプトがあれば実行する。
+    fn execute_rc_file(&mut self) {
+        let Ok(mut fd) = vfs::open(RC_FILE, FileFlags::RDONLY) else {
+            return;
+        };
+        let script = read_to_end(&mut fd);
+        self.execute_script(
+            &String::from_utf8_lossy(&script),
+            Vec::from([RC_FILE.into()]),
+        );
+    }
+
+    fn execute_list(&mut self, list: &List) {
+        for and_or in list {
+            self.execute_and_or(and_or);
+            if self.flow != Flow::Normal {
+                return;
+            }
+        }
+    }
+
+    fn execute_and_or(&mut self, and_or: &AndOr) {
+        self.execute_command(&and_or.first);
+        for (connector, command) in &and_or.rest {
+            if self.flow != Flow::Normal {
+                return;
+            }
+            let succeeded = self.last_exit_code == 0;
+            if succeeded == (*connector == Connector::And) {
+                self.execute_command(command);
+            }
+        }
+    }
+
+    fn execute_command(&mut self, command: &Command) {
+        match command {
+            Command::Pipeline(stages) => self.execute_pipeline(stages),
+            Command::If {
+                branches,
+                otherwise,
+            } => {
+                for (cond, body) in branches {
+                    self.execute_list(cond);
+                    if self.flow != Flow::Normal {
+                        return;
+                    }
+                    if self.last_exit_code == 0 {
+                        self.execute_list(body);
+                        return;
+                    }
+                }
+                match otherwise {
+                    Some(body) => self.execute_list(body),
+                    None => self.last_exit_code = 0,
+                }
+            }
+            Command::For { name, words, body } => {
+                let values = shell::expand(words, self);
+                self.last_exit_code = 0;
+                for value in values {
+                    self.set_var(name.clone(), value);
+                    self.execute_list(body);
+                    if !self.continue_loop() {
+                        break;
+                    }
+                }
+            }
+            Command::While { cond, body, until } => {
+                // 終了コードは最後に実行した本体のものとする
+                let mut exit_code = 0;
+                loop {
+                    self.execute_list(cond);
+                    if self.flow != Flow::Normal || (self.last_exit_code == 0) == *until {
+                        break;
+                    }
+                    self.execute_list(body);
+                    exit_code = self.last_exit_code;
+                    if !self.continue_loop() {
+                        break;
+                    }
+                }
+                if self.flow != Flow::Exit {
+                    self.last_exit_code = exit_code;
+                }
+            }
+        }
+    }
+
+    /// ループの本体を 1 回実行した後に呼び、次の繰り返しに進むかを返す。
+    fn continue_loop(&mut self) -> bool {
+        match self.flow {
+            Flow::Normal => true,
+            Flow::Continue => {
+                self.flow = Flow::Normal;
+                true
+            }
+            Flow::Break => {
+                self.flow = Flow::Normal;
+                false
+            }
+            Flow::Exit => false,
+        }
+    }
+
+    fn execute_pipeline(&mut self, stages: &[Stage]) {
+        let Some((first_stage, rest)) = stages.split_first() else {
+            return;
+        };
+
+        // ターミナルとしての標準入出力を保持し、コマンドの実行後に戻す
+        let term_files = self.files.clone();
+
+        // パイプ
+        // 先頭のコマンドはこのターミナルで、残りはそれぞれ新しいタスクで実行し、隣同士をパイプで繋ぐ
+        // 後ろのコマンドから順にタスクを作る
+        // 最後のコマンドの標準出力は、このターミナルの標準出力
+        // リダイレクトに失敗したコマンドは実行せず、その位置には None を入れる
+        let mut subtask_ids = Vec::new();
+        let mut stdout = self.files[1].clone();
+        for stage in rest.iter().rev() {
+            let (reader, writer) = FileDescriptor::new_pipe();
+            let mut files = [Arc::new(Mutex::new(reader)), stdout, self.files[2].clone()];
+            stdout = Arc::new(Mutex::new(writer));
+
+            if let Err(msg) = self.apply_redirects(&mut files, &stage.redirects) {
+                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                subtask_ids.push(None);
+                continue;
+            }
+            // 展開済みの引数は、再び展開されないように引用符で囲んで渡す
+            let (assignments, words) = self.take_assignments(&stage.args);
+            let args = shell::expand(words, self);
+            let command: Vec<_> = assignments
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, shell::quote(value)))
+                .chain(args.iter().map(|arg| shell::quote(arg)))
+                .collect();
+            let id = spawn_terminal(TerminalDescriptor {
+                command: command.join(" "),
+                vars: self.vars.clone(),
+                args: self.args.clone(),
+                exit_affter_command: true,
+                show_window: false,
+                files: Some(files),
+            });
+            subtask_ids.push(Some(id));
+        }
+        self.files[1] = stdout;
+
+        asmfunc::cli();
+        let task = task::current_task();
+        asmfunc::sti();
+        // コマンドの実行中のみ設定する環境変数の、元の値
+        let mut saved_env = Vec::new();
+        'exe: {
+            let mut files = self.files.clone();
+            if let Err(msg) = self.apply_redirects(&mut files, &first_stage.redirects) {
+                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                self.last_exit_code = 1;
+                break 'exe;
+            }
+            self.files = files;
+
+            // 代入のみの場合は、シェル変数を設定する
+            // コマンドの前に代入がある場合は、そのコマンドの実行中のみ環境変数に設定する
+            let (assignments, words) = self.take_assignments(&first_stage.args);
+            if words.is_empty() && !assignments.is_empty() {
+                for (name, value) in assignments {
+                    self.set_var(name, value);
+                }
+                self.last_exit_code = 0;
+                break 'exe;
+            }
+            for (name, value) in assignments {
+                let old = task.set_env_var(name.clone(), value);
+                saved_env.push((name, old));
+            }
+
+            let expanded = shell::expand(words, self);
+            let args: Vec<_> = expanded.iter().map(String::as_str).collect();
+            // リダイレクトのみの場合は、ファイルを作るだけで何も実行しない
+            let Some(&command) = args.first() else {
+                self.last_exit_code = 0;
+                break 'exe;
+            };
+
+            match command {
+                "echo" => {
+                    let s = format!("{}\n", args[1..].join(" "));
+                    file::print_to_fd(&mut self.files[1].lock_wait(), &s);
+                    self.last_exit_code = 0;
+                }
+                "clear" => {
+                    self.print("\x1b[2J\x1b[H");
+                    self.last_exit_code = 0;
+                }
+                "lspci" => {
+                    let s = procfs::pci_devices();
+                    file::print_to_fd(&mut self.files[1].lock_wait(), &s);
+                    self.last_exit_code = 0;
+                }
+                "cd" => {
+                    let dest = args.get(1).copied().unwrap_or("/");
+                    asmfunc::cli();
+                    let task = task::current_task();
+                    asmfunc::sti();
+                    match task.change_dir(dest) {
+                        Ok(()) => self.last_exit_code = 0,
+                        Err(e) => {
+                            let msg = match e.cause() {
+                                Code::NotDirectory => "not a directory",
+                                _ => "no such directory",
+                            };
+                            let mut stderr = self.files[2].lock_wait();
+                            file::print_to_fd(&mut stderr, &format!("cd: {}: {}\n", msg, dest));
+                            self.last_exit_code = 1;
+                        }
+                    }
+                }
+                "mkdir" | "mkfifo" | "rm" => {
+                    let Some(&target) = args.get(1) else {
+                        let msg = format!("Usage: {} <path>\n", command);
+                        file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
                         self.last_exit_code = 1;
                         break 'exe;
                     };
-                    if dir.attr == fat::Attribute::Directory as _ {
-                        self.list_all_entries(dir.first_cluster());
-                    } else {
-                        let (base, ext) = fat::read_name(dir);
-                        // Safety: ASCII 文字列だけが格納されている
-                        let name = if ext.is_empty() {
-                            base.to_string()
-                        } else {
-                            format!("{}.{}", base, ext)
-                        };
-                        if post_slash {
+                    let path = self.resolve_path(target);
+                    let res = match command {
+                        "mkdir" => vfs::make_dir(&path),
+                        "mkfifo" => vfs::make_fifo(&path),
+                        _ => vfs::remove(&path),
+                    };
+                    match res {
+                        Ok(()) => self.last_exit_code = 0,
+                        Err(e) => {
+                            let msg = format!("{}: {}: {}\n", command, target, e);
+                            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                            self.last_exit_code = 1;
+                        }
+                    }
+                }
+                "date" => {
+                    let s = format!("{}\n", rtc::now());
+                    file::print_to_fd(&mut self.files[1].lock_wait(), &s);
+                    self.last_exit_code = 0;
+                }
+                "history" => match args.get(1).copied() {
+                    Some("-c") => {
+                        self.cmd_history.clear();
+                        self.save_history();
+                        self.last_exit_code = 0;
+                    }
+                    arg => {
+                        let len = self.cmd_history.len();
+                        let Ok(count) = arg.map_or(Ok(len), str::parse::<usize>) else {
                             let mut stderr = self.files[2].lock_wait();
-                            file::print_to_fd(&mut stderr, &name);
-                            file::print_to_fd(&mut stderr, " is not a directory\n");
+                            file::print_to_fd(&mut stderr, "Usage: history [-c | <count>]\n");
                             self.last_exit_code = 1;
+                            break 'exe;
+                        };
+                        let s: String = self
+                            .cmd_history
+                            .iter()
+                            .rev()
+                            .enumerate()
+                            .skip(len.saturating_sub(count))
+                            .map(|(i, command)| format!("{:5}  {}\n", i + 1, command))
+                            .collect();
+                        file::print_to_fd(&mut self.files[1].lock_wait(), &s);
+                        self.last_exit_code = 0;
+                    }
+                },
+                "pwd" => {
+                    asmfunc::cli();
+                    let task = task::current_task();
+                    asmfunc::sti();
+                    let s = format!("{}\n", task.cwd());
+                    file::print_to_fd(&mut self.files[1].lock_wait(), &s);
+                    self.last_exit_code = 0;
+                }
+                "ls" => {
+                    let long = args.get(1) == Some(&"-l");
+                    let first_arg = args.get(if long { 2 } else { 1 }).copied().unwrap_or(".");
+                    let path = self.resolve_path(first_arg);
+                    // FIFO を開いて待ってしまわないように、開く前に種類を調べる
+                    let opened = vfs::stat(&path).and_then(|entry| {
+                        if entry.attr & Attribute::Directory as u8 != 0 {
+                            vfs::open(&path, FileFlags::RDONLY).map(Ok)
                         } else {
-                            let mut stdout = self.files[1].lock_wait();
-                            file::print_to_fd(&mut stdout, &name);
-                            file::print_to_fd(&mut stdout, "\n");
-                            self.last_exit_code = 0;
+                            Ok(Err(entry))
+                        }
+                    });
+                    let opened = match opened {
+                        Ok(opened) => opened,
+                        Err(e) => {
+                            let msg = match e.cause() {
+                                Code::NotDirectory => format!("{} is not a directory\n", first_arg),
+                                _ => format!("No such file or directory: {}\n", first_arg),
+                            };
+                            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                            self.last_exit_code = 1;
+                            break 'exe;
+                        }
+                    };
+
+                    match opened {
+                        Ok(mut fd) => self.list_all_entries(&mut fd, long),
+                        Err(entry) => {
+                            let line = format_entry(&entry, long);
+                            file::print_to_fd(&mut self.files[1].lock_wait(), &line);
                         }
                     }
+                    self.last_exit_code = 0;
                 }
                 "cat" => {
                     let fd = if let Some(file_path) = args.get(1) {
-                        let (Some(file_entry), post_slash) = fat::find_file(file_path, 0) else {
-                            let mut stderr = self.files[2].lock_wait();
-                            file::print_to_fd(
-                                &mut stderr,
-                                &format!("no such file: {}\n", file_path),
-                            );
+                        let path = self.resolve_path(file_path);
+                        match vfs::open(&path, FileFlags::RDONLY) {
+                            Ok(fd) => Arc::new(Mutex::new(fd)),
+                            Err(e) => {
+                                let msg = match e.cause() {
+                                    Code::NotDirectory => {
+                                        format!("{} is not a directory\n", file_path)
+                                    }
+                                    _ => format!("no such file: {}\n", file_path),
+                                };
+                                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                                self.last_exit_code = 1;
+                                break 'exe;
+                            }
+                        }
+                    } else {
+                        self.files[0].clone()
+                    };
+
+                    let mut buf = [0; 1024];
+                    let mut stored = 0;
+                    self.draw_cursor(false);
+                    loop {
+                        let read_bytes = match fd.lock_wait().read(&mut buf[stored..]) {
+                            0 => break,
+                            len => len,
+                        };
+                        let s = match core::str::from_utf8(&buf[..read_bytes]) {
+                            Ok(s) => s,
+                            Err(e) => {
+                                if e.error_len().is_some() {
+                                    file::print_to_fd(
+                                        &mut self.files[2].lock_wait(),
+                                        "\ninvalid UTF-8 sequence\n",
+                                    );
+                                    break 'exe;
+                                }
+                                unsafe { core::str::from_utf8_unchecked(&buf[..e.valid_up_to()]) }
+                            }
+                        };
+                        file::print_to_fd(&mut self.files[1].lock_wait(), s);
+
+                        stored = read_bytes - s.len();
+                        if stored > 0 {
+                            unsafe { ptr::copy(buf[s.len()..].as_ptr(), buf.as_mut_ptr(), stored) };
+                        }
+                    }
+                    self.last_exit_code = 0;
+                }
+                "noterm" => {
+                    if args.len() >= 2 {
+                        let command: Vec<_> =
+                            args[1..].iter().map(|arg| shell::quote(arg)).collect();
+                        spawn_terminal(TerminalDescriptor {
+                            command: command.join(" "),
+                            vars: self.vars.clone(),
+                            args: self.args.clone(),
+                            exit_affter_command: true,
+                            show_window: false,
+                            files: Some(self.files.clone()),
+                        });
+                    }
+                }
+                "ulimit" => {
+                    if args.len() >= 3 {
+                        if "-s" == args[1] {
+                            if let Ok(size) = args[2].parse::<u64>() {
+                                asmfunc::cli();
+                                let task = task::current_task();
+                                asmfunc::sti();
+                                task.set_app_stack_size(size << 10);
+                            } else {
+                                let mut stderr = self.files[2].lock_wait();
+                                file::print_to_fd(&mut stderr, "Usage: ulimit -s <size (KiB)>\n");
+                                self.last_exit_code = 1;
+                            }
+                        }
+                    } else {
+                        asmfunc::cli();
+                        let task = task::current_task();
+                        asmfunc::sti();
+                        let s = format!("stack_size: {} KiB\n", task.app_stack_size() >> 10);
+                        let mut stdout = self.files[1].lock_wait();
+                        file::print_to_fd(&mut stdout, &s);
+                        self.last_exit_code = 0;
+                    }
+                }
+                "memstat" => match vfs::open("/proc/meminfo", FileFlags::RDONLY) {
+                    Ok(mut fd) => {
+                        let data = read_to_end(&mut fd);
+                        file::print_to_fd(
+                            &mut self.files[1].lock_wait(),
+                            &String::from_utf8_lossy(&data),
+                        );
+                        self.last_exit_code = 0;
+                    }
+                    Err(e) => {
+                        let msg = format!("memstat: {}\n", e);
+                        file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                        self.last_exit_code = 1;
+                    }
+                },
+                "export" => {
+                    if args.len() == 1 {
+                        self.print_env(&task);
+                    }
+                    self.last_exit_code = 0;
+                    for &arg in &args[1..] {
+                        let (name, value) = match arg.split_once('=') {
+                            Some((name, value)) => (name, Some(String::from(value))),
+                            None => (arg, None),
+                        };
+                        if !shell::is_var_name(name) {
+                            let msg = format!("export: `{}`: not a valid identifier\n", arg);
+                            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                            self.last_exit_code = 1;
+                            continue;
+                        }
+
+                        // 値が指定されていない場合は、シェル変数の値を引き継ぐ
+                        let shell_value = self.vars.remove(name);
+                        let value = value
+                            .or(shell_value)
+                            .or_else(|| task.env_var(name))
+                            .unwrap_or_default();
+                        task.set_env_var(name.into(), value);
+                    }
+                }
+                "env" => {
+                    self.print_env(&task);
+                    self.last_exit_code = 0;
+                }
+                "unset" => {
+                    for &name in &args[1..] {
+                        self.vars.remove(name);
+                        task.remove_env_var(name);
+                    }
+                    self.last_exit_code = 0;
+                }
+                "true" => self.last_exit_code = 0,
+                "false" => self.last_exit_code = 1,
+                "test" | "[" => {
+                    let mut expr = &args[1..];
+                    if command == "[" {
+                        match expr.split_last() {
+                            Some((&"]", rest)) => expr = rest,
+                            _ => {
+                                file::print_to_fd(
+                                    &mut self.files[2].lock_wait(),
+                                    "[: missing `]`\n",
+                                );
+                                self.last_exit_code = 2;
+                                break 'exe;
+                            }
+                        }
+                    }
+                    match shell::test(expr, self) {
+                        Ok(true) => self.last_exit_code = 0,
+                        Ok(false) => self.last_exit_code = 1,
+                        Err(msg) => {
+                            let msg = format!("{}: {}\n", command, msg);
+                            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                            self.last_exit_code = 2;
+                        }
+                    }
+                }
+                "sh" => {
+                    let Some(&script) = args.get(1) else {
+                        let msg = "Usage: sh <file> [args...]\n";
+                        file::print_to_fd(&mut self.files[2].lock_wait(), msg);
+                        self.last_exit_code = 1;
+                        break 'exe;
+                    };
+                    let mut fd = match vfs::open(&self.resolve_path(script), FileFlags::RDONLY) {
+                        Ok(fd) if !fd.is_dir() => fd,
+                        Ok(_) => {
+                            let msg = format!("sh: {}: is a directory\n", script);
+                            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
                             self.last_exit_code = 1;
                             break 'exe;
-                        };
-                        if file_entry.attr != fat::Attribute::Directory as _ && post_slash {
-                            let mut stderr = self.files[2].lock_wait();
-                            file::print_to_fd(&mut stderr, file_path);
-                            file::print_to_fd(&mut stderr, " is not a directory\n");
+                        }
+                        Err(e) => {
+                            let msg = format!("sh: {}: {}\n", script, e);
+                            file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
                             self.last_exit_code = 1;
                             break 'exe;
                         }
-                        Arc::new(Mutex::new(FileDescriptor::new_fat(file_entry)))
+                    };
+                    let content = read_to_end(&mut fd);
+                    let args = args[1..].iter().map(|&arg| arg.into()).collect();
+                    self.execute_script(&String::from_utf8_lossy(&content), args);
+                }
+                "exit" => {
+                    if let Some(code) = args.get(1) {
+                        match code.parse() {
+                            Ok(code) => self.last_exit_code = code,
+                            Err(_) => {
+                                let msg = format!("exit: {}: numeric argument required\n", code);
+                                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                                self.last_exit_code = 2;
+                            }
+                        }
+                    }
+                    self.flow = Flow::Exit;
+                }
+                "break" | "continue" => {
+                    self.flow = if command == "break" {
+                        Flow::Break
                     } else {
-                        self.files[0].clone()
+                        Flow::Continue
                     };
-
-                    let mut buf = [0; 1024];
-                    let mut stored = 0;
-                    self.draw_cursor(false);
-                    loop {
-                        let read_bytes = match fd.lock_wait().read(&mut buf[stored..]) {
-                            0 => break,
-                            len => len,
-                        };
-                        let s = match core::str::from_utf8(&buf[..read_bytes]) {
-                            Ok(s) => s,
+                    self.last_exit_code = 0;
+                }
+                command => {
+                    let cwd = self.resolve_path(".");
+                    let path_var = task.env_var("PATH").unwrap_or_default();
+                    if let Some(path) = find_command(command, &cwd, &path_var) {
+                        let program = match Program::open(&path) {
+                            Ok(program) => program,
                             Err(e) => {
-                                if e.error_len().is_some() {
-                                    file::print_to_fd(
-                                        &mut self.files[2].lock_wait(),
-                                        "\ninvalid UTF-8 sequence\n",
-                                    );
-                                    break 'exe;
-                                }
-                                unsafe { core::str::from_utf8_unchecked(&buf[..e.valid_up_to()]) }
+                                let msg = format!("failed to exec file: {}\n", e);
+                                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+                                self.last_exit_code = -(e.cause() as i32);
+                                break 'exe;
                             }
                         };
-                        file::print_to_fd(&mut self.files[1].lock_wait(), s);
+                        // `#!` で始まるファイルはスクリプトとして実行する
+                        if program.is_script() {
+                            let script = program.contents();
+                            let args = args.iter().map(|&arg| arg.into()).collect();
+                            self.execute_script(&String::from_utf8_lossy(&script), args);
+                            break 'exe;
+                        }
+                        match self.execute_file(&program, args) {
+                            Ok(code) => self.last_exit_code = code,
+                            Err(e) => {
+                                let mut stderr = self.files[2].lock_wait();
+                                file::print_to_fd(
+                                    &mut stderr,
+                                    &format!("failed to exec file: {}\n", e),
+                                );
+                                self.last_exit_code = -(e.cause() as i32);
+                            }
+                        }
+                    } else {
+                        let mut stderr = self.files[2].lock_wait();
+                        let stderr = &mut stderr;
+                        file::print_to_fd(stderr, "no such command: ");
+                        file::print_to_fd(stderr, command);
+                        file::print_to_fd(stderr, "\n");
+                        self.last_exit_code = 1;
+                    }
+                }
+            }
+        }
+
+        for (name, old) in saved_env.into_iter().rev() {
+            match old {
+                Some(value) => task.set_env_var(name, value),
+                None => task.remove_env_var(&name),
+            };
+        }
+
+        // 標準入出力を戻す
+        // パイプに送っていた場合は、ここで書き込み側が閉じられて EOF が伝わるので、
+        // その後で全てのコマンドの終了を待つ
+        // 終了コードは最後のコマンドのものとする
+        self.files = term_files;
+        for id in subtask_ids.into_iter().rev() {
+            let Some(id) = id else {
+                self.last_exit_code = 1;
+                continue;
+            };
+            asmfunc::cli();
+            let ret = task::wait_finish(id);
+            asmfunc::sti();
+            match ret {
+                Ok(code) => self.last_exit_code = code,
+                Err(e) => {
+                    log!(LogLevel::Warn, "failed to wait finish: {}", e);
+                }
+            }
+        }
+    }
+
+    /// 先頭に並んだ `NAME=value` の形の単語を展開して取り出し、残りの単語を返す。
+    fn take_assignments<'a>(&mut self, words: &'a [Word]) -> (Vec<(String, String)>, &'a [Word]) {
+        let mut assignments = Vec::new();
+        let mut rest = words;
+        while let Some((name, value)) = rest.first().and_then(shell::split_assignment) {
+            assignments.push((name, shell::expand_single(&value, self)));
+            rest = &rest[1..];
+        }
+        (assignments, rest)
+    }
+
+    /// シェル変数 `name` に `value` を設定する。
+    /// 環境変数として公開されている場合は、環境変数を更新する。
+    fn set_var(&mut self, name: String, value: String) {
+        asmfunc::cli();
+        let task = task::current_task();
+        asmfunc::sti();
+        if task.env_var(&name).is_some() {
+            task.set_env_var(name, value);
+        } else {
+            self.vars.insert(name, value);
+        }
+    }
+
+    /// 環境変数を `NAME=value` の形で 1 行ずつ標準出力に書き出す。
+    fn print_env(&mut self, task: &Task) {
+        let mut stdout = self.files[1].lock_wait();
+        for (name, value) in task.env() {
+            file::print_to_fd(&mut stdout, &format!("{}={}\n", name, value));
+        }
+    }
+
+    /// `redirects` を順に適用し、`files` の標準入出力を置き換える。
+    ///
+    /// ファイルを開けなかった場合は、表示するエラーメッセージを返す。
+    fn apply_redirects(
+        &mut self,
+        files: &mut [Arc<Mutex<FileDescriptor>>; 3],
+        redirects: &[Redirect],
+    ) -> core::result::Result<(), String> {
+        let write = FileFlags::WRONLY | FileFlags::CREAT;
+        for redirect in redirects {
+            let (fd, path, flags) = match redirect {
+                Redirect::Input(path) => (0, path, FileFlags::RDONLY),
+                Redirect::Output(path) => (1, path, write | FileFlags::TRUNC),
+                Redirect::Append(path) => (1, path, write | FileFlags::APPEND),
+                Redirect::Error(path) => (2, path, write | FileFlags::TRUNC),
+                Redirect::ErrorAppend(path) => (2, path, write | FileFlags::APPEND),
+                Redirect::ErrorToOutput => {
+                    files[2] = files[1].clone();
+                    continue;
+                }
+            };
+
+            let path = shell::expand_single(path, self);
+            let file = match vfs::open(&self.resolve_path(&path), flags) {
+                Ok(file) if file.is_dir() => return Err(format!("{}: is a directory\n", path)),
+                Ok(file) => file,
+                Err(e) => {
+                    let msg = match e.cause() {
+                        Code::IsDirectory => format!("{}: is a directory\n", path),
+                        Code::NoSuchEntry | Code::NotDirectory => {
+                            format!("{}: no such file or directory\n", path)
+                        }
+                        _ => format!("{}: failed to open a redirect file: {}\n", path, e),
+                    };
+                    return Err(msg);
+                }
+            };
+            files[fd] = Arc::new(Mutex::new(file));
+        }
+        Ok(())
+    }
+
+    /// 行の中の `!!` を直前のコマンドに、`!n` を `n` 番目のコマンドに、`!-n` を `n` 個前のコマンドに置き換える。
+    ///
+    /// 単一引用符の中と、`!` の後ろに数字や `!` が続かない場合は置き換えない。
+    /// 履歴にないコマンドを参照した場合は、エラーメッセージを返す。
+    fn expand_history(&self, line: &str) -> core::result::Result<String, String> {
+        let mut expanded = String::new();
+        let mut in_quote = false;
+        let mut chars = line.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c == '\'' {
+                in_quote = !in_quote;
+            }
+            if c != '!' || in_quote {
+                expanded.push(c);
+                continue;
+            }
+
+            let rest = &line[i + 1..];
+            let (event, entry) = if rest.starts_with('!') {
+                ("!", self.history_entry(-1))
+            } else {
+                let sign = usize::from(rest.starts_with('-'));
+                let digits = rest[sign..].bytes().take_while(u8::is_ascii_digit).count();
+                if digits == 0 {
+                    expanded.push(c);
+                    continue;
+                }
+                let event = &rest[..sign + digits];
+                (
+                    event,
+                    event.parse().ok().and_then(|n| self.history_entry(n)),
+                )
+            };
+            let Some(entry) = entry else {
+                return Err(format!("!{}: event not found\n", event));
+            };
+            expanded.push_str(entry);
+            // 参照は ASCII のみなので、バイト数と文字数は等しい
+            chars.nth(event.len() - 1);
+        }
+        Ok(expanded)
+    }
+
+    /// 古い方から数えて `n` 番目 (1 始まり) のコマンドを返す。負の場合は新しい方から数える。
+    fn history_entry(&self, n: i64) -> Option<&str> {
+        let len = self.cmd_history.len() as i64;
+        let i = match n {
+            0 => return None,
+            1.. => len - n,
+            _ => -n - 1,
+        };
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| self.cmd_history.get(i))
+            .map(String::as_str)
+    }
+
+    /// 実行したコマンドを履歴に加え、履歴ファイルに保存する。同じコマンドの古い履歴は取り除く。
+    fn add_history(&mut self, command: String) {
+        self.cmd_history.retain(|c| *c != command);
+        self.cmd_history.push_front(command);
+        self.cmd_history.truncate(self.history_limit());
+        self.save_history();
+    }
+
+    /// 履歴に残すコマンドの最大の数。シェル変数 `HISTSIZE` で変えられる。
+    fn history_limit(&self) -> usize {
+        self.var("HISTSIZE")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_HISTSIZE)
+    }
+
+    /// 履歴を保存するファイルの絶対パス。シェル変数 `HISTFILE` で変えられ、既定では `$HOME/history`。
+    ///
+    /// `HISTFILE` が空の場合は保存しない。
+    fn history_file(&self) -> Option<String> {
+        let path = self.var("HISTFILE").or_else(|| {
+            let home = self.var("HOME")?;
+            Some(format!("{}/{}", home.trim_end_matches('/'), HISTORY_FILE))
+        })?;
+        if path.is_empty() {
+            return None;
+        }
+        Some(self.resolve_path(&path))
+    }
+
+    /// 履歴ファイルからコマンドの履歴を読み込む。
+    fn load_history(&mut self) {
+        let Some(path) = self.history_file() else {
+            return;
+        };
+        let Ok(mut fd) = vfs::open(&path, FileFlags::RDONLY) else {
+            return;
+        };
+        let data = read_to_end(&mut fd);
+        // ファイルには古い順に 1 行ずつ並んでいる
+        for line in String::from_utf8_lossy(&data).lines() {
+            if line.is_empty() || line.len() >= LINE_MAX {
+                continue;
+            }
+            self.cmd_history.retain(|c| c != line);
+            self.cmd_history.push_front(line.into());
+        }
+        self.cmd_history.truncate(self.history_limit());
+    }
+
+    /// コマンドの履歴を履歴ファイルに書き込む。
+    ///
+    /// 書き込めなかった場合は、最初の 1 回だけ標準エラー出力に知らせる。
+    fn save_history(&mut self) {
+        let Some(path) = self.history_file() else {
+            return;
+        };
+        if let Err(e) = self.write_history(&path) {
+            if !self.history_error_reported {
+                self.history_error_reported = true;
+                let msg = format!("history: cannot save to {}: {}\n", path, e);
+                file::print_to_fd(&mut self.files[2].lock_wait(), &msg);
+            }
+        }
+    }
+
+    /// コマンドの履歴を `path` に書き込む。ファイルを置くディレクトリがなければ作る。
+    fn write_history(&self, path: &str) -> Result<()> {
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            if !dir.is_empty() && vfs::check_dir(dir).is_err() {
+                vfs::make_dir(dir)?;
+            }
+        }
+        let flags = FileFlags::WRONLY | FileFlags::CREAT | FileFlags::TRUNC;
+        let mut fd = vfs::open(path, flags)?;
+        let mut data = String::new();
+        for command in self.cmd_history.iter().rev() {
+            data.push_str(command);
+            data.push('\n');
+        }
+        fd.write(data.as_bytes())?;
+        Ok(())
+    }
+
+    fn history_up_down(&mut self, direction: i32) -> Rectangle<i32> {
+        if direction == -1 && self.cmd_history_index >= 0 {
+            self.cmd_history_index -= 1;
+        } else if direction == 1 && self.cmd_history_index + 1 < self.cmd_history.len() as i32 {
+            self.cmd_history_index += 1;
+        }
+
+        let history = if self.cmd_history_index >= 0 {
+            self.cmd_history[self.cmd_history_index as usize].clone()
+        } else {
+            String::new()
+        };
+        self.set_line(history.as_bytes());
+        self.redraw_line()
+    }
+
+    /// プロンプトを表示し、その後ろから行の入力を始める。
+    fn prompt(&mut self) {
+        self.print(">");
+        self.line_start = self.cursor.x();
+    }
+
+    /// 入力中の行に入る最大の文字数。
+    fn line_capacity(&self) -> usize {
+        let width = (self.columns as i32 - 1 - self.line_start).max(0) as usize;
+        width.min(LINE_MAX - 1)
+    }
+
+    /// 入力中の行のカーソルの位置に `s` を挿入する。入り切らない場合は何もせず `false` を返す。
+    fn insert(&mut self, s: &[u8]) -> bool {
+        if self.linebuf_index + s.len() > self.line_capacity() {
+            return false;
+        }
+        self.linebuf.copy_within(
+            self.linebuf_cursor..self.linebuf_index,
+            self.linebuf_cursor + s.len(),
+        );
+        self.linebuf[self.linebuf_cursor..][..s.len()].copy_from_slice(s);
+        self.linebuf_index += s.len();
+        self.linebuf_cursor += s.len();
+        true
+    }
+
+    /// クリップボードのテキストを入力中の行に挿入する。
+    ///
+    /// 行には ASCII の文字しか入力できないので、改行やタブは空白に置き換え、それ以外の文字は捨てる。
+    /// 入り切らない部分も捨てる。
+    fn paste_line(&mut self) -> Rectangle<i32> {
+        let Some(content) = clipboard::get() else {
+            return self.redraw_line();
+        };
+        let Some(text) = content.text() else {
+            return self.redraw_line();
+        };
+
+        let s: Vec<_> = text
+            .trim_end_matches('\n')
+            .bytes()
+            .filter_map(|b| match b {
+                b'\n' | b'\r' | b'\t' => Some(b' '),
+                b' ' => Some(b),
+                b if b.is_ascii_graphic() => Some(b),
+                _ => None,
+            })
+            .collect();
+        let len = s
+            .len()
+            .min(self.line_capacity().saturating_sub(self.linebuf_index));
+        self.insert(&s[..len]);
+        self.redraw_line()
+    }
+
+    /// 入力中の行から `range` の文字を取り除き、カーソルをその位置に置く。
+    fn remove_chars(&mut self, range: Range<usize>) {
+        self.linebuf
+            .copy_within(range.end..self.linebuf_index, range.start);
+        self.linebuf_index -= range.len();
+        self.linebuf_cursor = range.start;
+    }
+
+    /// 入力中の行を `s` で置き換え、カーソルを行末に置く。
+    fn set_line(&mut self, s: &[u8]) {
+        let len = s.len().min(self.line_capacity());
+        self.linebuf[..len].copy_from_slice(&s[..len]);
+        self.linebuf_index = len;
+        self.linebuf_cursor = len;
+    }
+
+    /// Ctrl と同時に押されたキーで行を編集する。編集した場合は `true` を返す。
+    fn edit_with_ctrl(&mut self, keycode: u8) -> bool {
+        match keycode {
+            // A: 行頭へ移動
+            4 => self.linebuf_cursor = 0,
+            // E: 行末へ移動
+            8 => self.linebuf_cursor = self.linebuf_index,
+            // K: カーソルから行末までを削除
+            14 => self.remove_chars(self.linebuf_cursor..self.linebuf_index),
+            // U: 行頭からカーソルまでを削除
+            24 => self.remove_chars(0..self.linebuf_cursor),
+            // W: カーソルの手前の単語を削除
+            26 => {
+                let line = &self.linebuf[..self.linebuf_cursor];
+                let end = line.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+                let start = line[..end]
+                    .iter()
+                    .rposition(|&c| c == b' ')
+                    .map_or(0, |i| i + 1);
+                self.remove_chars(start..self.linebuf_cursor);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// 入力中の行を `text` で描き直し、カーソルを `cursor` 文字目に置く。描き直した範囲を返す。
+    fn draw_line(&mut self, text: &str, cursor: usize) -> Rectangle<i32> {
+        let width = (self.columns as i32 - self.line_start).max(0) as usize;
+        let pos = cell_pos(self.line_start as usize, self.cursor.y() as usize);
+        let size = Vector2D::new(8 * width as i32, 16);
+
+        let y = self.cursor.y() as usize;
+        let mut chars = text.chars();
+        for x in self.line_start as usize..self.columns {
+            let c = chars.next().unwrap_or(' ');
+            self.put_cell(
+                x,
+                y,
+                Cell {
+                    c,
+                    attr: Attr::default(),
+                },
+            );
+        }
+
+        let cursor = cursor.min(width.saturating_sub(1));
+        self.cursor = Vector2D::new(self.line_start + cursor as i32, self.cursor.y());
+        Rectangle { pos, size }
+    }
+
+    /// 入力中の行を `linebuf` の内容で描き直す。
+    fn redraw_line(&mut self) -> Rectangle<i32> {
+        // 入力できる文字は ASCII に限られている
+        let line = String::from_utf8_lossy(&self.linebuf[..self.linebuf_index]).into_owned();
+        self.draw_line(&line, self.linebuf_cursor)
+    }
+
+    /// 履歴の逆方向検索を始める。
+    fn start_search(&mut self) -> Rectangle<i32> {
+        self.search = Some(HistorySearch {
+            query: String::new(),
+            found: None,
+            original: self.linebuf[..self.linebuf_index].to_vec(),
+        });
+        self.linebuf_cursor = self.linebuf_index;
+        self.draw_search_line()
+    }
+
+    /// 履歴の検索中に押されたキーを処理する。
+    ///
+    /// キーを検索に使った場合は描き直した範囲を返し、検索を終えた場合は `None` を返す。
+    fn input_search(&mut self, ctrl: bool, keycode: u8, ascii: u8) -> Option<Rectangle<i32>> {
+        let search = self.search.as_mut()?;
+        let from = match (ctrl, keycode, ascii) {
+            // Ctrl+R: さらに古い履歴を探す
+            (true, 21, _) => search.found.map_or(0, |i| i + 1),
+            // Ctrl+G, Esc: 検索を取り消し、元の行に戻す
+            (true, 10, _) | (false, 0x29, _) => {
+                let original = mem::take(&mut search.original);
+                self.search = None;
+                self.set_line(&original);
+                return Some(self.redraw_line());
+            }
+            (false, _, 0x08) => {
+                search.query.pop();
+                0
+            }
+            (false, _, c) if c == b' ' || c.is_ascii_graphic() => {
+                search.query.push(c as char);
+                search.found.unwrap_or(0)
+            }
+            _ => {
+                self.search = None;
+                return None;
+            }
+        };
+        self.search_history(from);
+        Some(self.draw_search_line())
+    }
+
+    /// `from` 番目以降の履歴から検索する文字列を含むものを探し、見つかれば入力中の行にする。
+    fn search_history(&mut self, from: usize) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        if search.query.is_empty() {
+            search.found = None;
+            return;
+        }
+        let Some(i) = (from..self.cmd_history.len())
+            .find(|&i| self.cmd_history[i].contains(search.query.as_str()))
+        else {
+            return;
+        };
+        search.found = Some(i);
+        let line = self.cmd_history[i].clone();
+        self.set_line(line.as_bytes());
+    }
+
+    fn draw_search_line(&mut self) -> Rectangle<i32> {
+        let Some(ref search) = self.search else {
+            return self.redraw_line();
+        };
+        let line = String::from_utf8_lossy(&self.linebuf[..self.linebuf_index]);
+        let text = format!("(search)'{}': {}", search.query, line);
+        // カーソルは検索する文字列の後ろに置く
+        let cursor = "(search)'".len() + search.query.len();
+        self.draw_line(&text, cursor)
+    }
+
+    /// カーソルの手前の単語を、コマンド名もしくはパスとして補完する。
+    ///
+    /// 候補が 1 つの場合はそれに置き換え、複数の場合は共通する部分まで補完する。
+    /// それ以上補完できない場合は、候補を一覧表示する。
+    fn complete(&mut self) -> Rectangle<i32> {
+        let line = String::from_utf8_lossy(&self.linebuf[..self.linebuf_cursor]).into_owned();
+        let word_start = line
+            .rfind([' ', '\t', '|', ';', '&', '<', '>', '(', '`'])
+            .map_or(0, |i| i + 1);
+        let (before, word) = line.split_at(word_start);
+        // 引用符などを含む単語は補完しない
+        if word.contains(['\'', '"', '\\', '$']) {
+            return self.redraw_line();
+        }
+
+        let before = before.trim_end();
+        let is_command = before.is_empty()
+            || before.ends_with(['|', ';', '&', '(', '`'])
+            || before
+                .rsplit(' ')
+                .next()
+                .is_some_and(|w| COMMAND_KEYWORDS.contains(&w));
+        let mut candidates = if is_command && !word.contains('/') {
+            self.command_candidates(word)
+        } else {
+            self.path_candidates(word)
+        };
+        candidates.sort();
+        candidates.dedup();
+
+        let completed = match candidates.as_slice() {
+            [] => return self.redraw_line(),
+            [(name, is_dir)] => format!("{}{}", name, if *is_dir { '/' } else { ' ' }),
+            [(first, _), rest @ ..] => {
+                let len = rest.iter().fold(first.len(), |len, (name, _)| {
+                    first
+                        .bytes()
+                        .zip(name.bytes())
+                        .take(len)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                if len <= word.len() {
+                    return self.show_candidates(&candidates);
+                }
+                first[..len].into()
+            }
+        };
+
+        if self.linebuf_index - word.len() + completed.len() > self.line_capacity() {
+            return self.redraw_line();
+        }
+        self.remove_chars(word_start..self.linebuf_cursor);
+        self.insert(completed.as_bytes());
+        self.redraw_line()
+    }
+
+    /// 名前が `prefix` で始まる組み込みコマンドと、`PATH` に含まれるアプリを返す。
+    fn command_candidates(&self, prefix: &str) -> Vec<(String, bool)> {
+        let mut candidates: Vec<_> = BUILTINS
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|&name| (String::from(name), false))
+            .collect();
+
+        asmfunc::cli();
+        let task = task::current_task();
+        asmfunc::sti();
+        let path_var = task.env_var("PATH").unwrap_or_default();
+        for dir in path_var.split(':').filter(|dir| !dir.is_empty()) {
+            let dir = self.resolve_path(dir);
+            let apps = dir_candidates(&dir, "", prefix);
+            candidates.extend(apps.into_iter().filter(|&(_, is_dir)| !is_dir));
+        }
+        candidates
+    }
+
+    /// パス `word` の最後の要素を補完する候補を返す。
+    fn path_candidates(&self, word: &str) -> Vec<(String, bool)> {
+        let (base, prefix) = match word.rfind('/') {
+            Some(i) => word.split_at(i + 1),
+            None => ("", word),
+        };
+        let dir = self.resolve_path(if base.is_empty() { "." } else { base });
+        dir_candidates(&dir, base, prefix)
+    }
+
+    /// 補完の候補を入力中の行の下に一覧表示し、新しいプロンプトに行を表示し直す。
+    fn show_candidates(&mut self, candidates: &[(String, bool)]) -> Rectangle<i32> {
+        let names: Vec<_> = candidates
+            .iter()
+            .map(|(name, is_dir)| {
+                let name = name.rsplit('/').next().unwrap_or(name);
+                format!("{}{}", name, if *is_dir { "/" } else { "" })
+            })
+            .collect();
+
+        // 一覧を表示する間は、カーソルを行末に置いておく
+        let cursor = self.linebuf_cursor;
+        self.linebuf_cursor = self.linebuf_index;
+        self.cursor = Vector2D::new(self.line_start + self.linebuf_index as i32, self.cursor.y());
+        self.print(&format!("\n{}\n", names.join("  ")));
+        self.prompt();
+        self.linebuf_cursor = cursor;
+        self.redraw_line();
+
+        self.whole_area()
+    }
+
+    /// ウィンドウ全体を表す範囲を返す。
+    fn whole_area(&self) -> Rectangle<i32> {
+        Rectangle {
+            pos: Vector2D::new(0, 0),
+            size: self
+                .window
+                .as_ref()
+                .map_or(Vector2D::new(0, 0), |window| window.read().size()),
+        }
+    }
+
+    /// スクロールバックに残す最大の行数。シェル変数 `SCROLLBACK` で変えられる。
+    fn scrollback_limit(&self) -> usize {
+        self.var("SCROLLBACK")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_SCROLLBACK)
+    }
+
+    fn is_viewing_scrollback(&self) -> bool {
+        self.scroll_offset > 0 || self.scrollback_search.is_some()
+    }
+
+    /// スクロールバックと画面を合わせた中で `i` 行目の文字を返す。
+    fn line(&self, i: usize) -> &Row {
+        match i.checked_sub(self.scrollback.len()) {
+            Some(row) => &self.screen[row],
+            None => &self.scrollback[i],
+        }
+    }
+
+    /// スクロールバックを表示していれば、最新の画面に戻す。
+    fn reset_view(&mut self) {
+        if self.is_viewing_scrollback() {
+            self.scroll_offset = 0;
+            self.scrollback_search = None;
+            self.draw_view();
+            self.redraw();
+        }
+    }
+
+    /// 表示を `lines` 行だけ遡る。負の場合は新しい方へ戻る。
+    fn scroll_view(&mut self, lines: i32) -> Rectangle<i32> {
+        // 代替画面にはスクロールバックがない
+        if self.alt_screen.is_some() {
+            return self.whole_area();
+        }
+        let offset = self.scroll_offset as i32 + lines;
+        self.scroll_offset = offset.clamp(0, self.scrollback.len() as i32) as usize;
+        self.draw_view();
+        self.draw_cursor(true);
+        self.whole_area()
+    }
+
+    /// `scroll_offset` だけ遡った位置から、スクロールバックと画面の文字を描き直す。
+    ///
+    /// スクロールバックの検索中は、最下行に検索する文字列を表示し、一致した部分を反転させる。
+    fn draw_view(&mut self) {
+        let Some(window) = self.window.clone() else {
+            return;
+        };
+        let mut window = window.write();
 
-                        stored = read_bytes - s.len();
-                        if stored > 0 {
-                            unsafe { ptr::copy(buf[s.len()..].as_ptr(), buf.as_mut_ptr(), stored) };
-                        }
+        let top = self.scrollback.len() - self.scroll_offset;
+        for y in 0..self.rows {
+            draw_cells(&mut window, y, self.line(top + y));
+        }
+
+        if let Some(selection) = self.selection {
+            for y in 0..self.rows {
+                let line = self.line(top + y);
+                for x in selection.columns(top + y, self.columns) {
+                    if line[x].c != '\0' {
+                        draw_cell(&mut window, x, y, line[x], true);
                     }
-                    self.last_exit_code = 0;
                 }
-                "noterm" => {
-                    if args.len() >= 2 {
-                        let args = args[1..].iter().map(|&s| String::from(s)).collect();
-                        let desc = Box::new(TerminalDescriptor {
-                            args,
-                            exit_affter_command: true,
-                            show_window: false,
-                            files: self.files.clone(),
-                        });
-                        asmfunc::cli();
-                        task::new_task()
-                            .init_context(task_terminal, Box::into_raw(desc) as _, 0)
-                            .wake_up(-1);
-                        asmfunc::sti();
-                    }
+            }
+        }
+
+        let Some(ref search) = self.scrollback_search else {
+            return;
+        };
+        let query: Vec<_> = search.query.chars().collect();
+        if let Some(i) = search
+            .found
+            .filter(|i| (top..top + self.rows - 1).contains(i))
+        {
+            let line = self.line(i);
+            if let Some(start) = find_chars(line, &query) {
+                for (x, &cell) in line.iter().enumerate().skip(start).take(query.len()) {
+                    draw_cell(&mut window, x, i - top, cell, true);
                 }
-                "ulimit" => {
-                    if args.len() >= 3 {
-                        if "-s" == args[1] {
-                            if let Ok(size) = args[2].parse::<u64>() {
-                                asmfunc::cli();
-                                let task = task::current_task();
-                                asmfunc::sti();
-                                task.set_app_stack_size(size << 10);
-                            } else {
-                                let mut stderr = self.files[2].lock_wait();
-                                file::print_to_fd(&mut stderr, "Usage: ulimit -s <size (KiB)>\n");
-                                self.last_exit_code = 1;
-                            }
-                        }
-                    } else {
-                        asmfunc::cli();
-                        let task = task::current_task();
-                        asmfunc::sti();
-                        let s = format!("stack_size: {} KiB\n", task.app_stack_size() >> 10);
-                        let mut stdout = self.files[1].lock_wait();
-                        file::print_to_fd(&mut stdout, &s);
-                        self.last_exit_code = 0;
-                    }
+            }
+        }
+
+        let status = format!(
+            "(scrollback{})'{}'",
+            if search.failed { ": not found" } else { "" },
+            search.query
+        );
+        let mut chars = status.chars();
+        let line: Vec<_> = (0..self.columns)
+            .map(|_| Cell {
+                c: chars.next().unwrap_or(' '),
+                attr: Attr::default(),
+            })
+            .collect();
+        draw_cells(&mut window, self.rows - 1, &line);
+    }
+
+    /// マウスの操作を処理する。左ボタンのドラッグで文字を選択し、ボタンを離したときに
+    /// 選択した文字列をクリップボードにコピーする。
+    ///
+    /// 中ボタンが押された場合は、貼り付けのために `true` を返す。
+    fn input_mouse(&mut self, ty: MessageType) -> bool {
+        match ty {
+            MessageType::MouseButton {
+                x,
+                y,
+                press: true,
+                button: 0,
+            } => {
+                self.clear_selection();
+                if let Some(pos) = self.cell_at(x, y) {
+                    self.selection = Some(Selection {
+                        anchor: pos,
+                        end: pos,
+                        dragging: true,
+                    });
                 }
-                "memstat" => {
-                    let stat = MEMORY_MANAGER.stat();
-                    let s = format!(
-                        "Phys used : {} frames ({} MiB)\n\
-                    Phys total: {} frames ({} MiB)\n",
-                        stat.allocated_frames,
-                        (stat.allocated_frames * BYTES_PER_FRAME) >> 20,
-                        stat.total_frames,
-                        (stat.total_frames * BYTES_PER_FRAME) >> 20,
-                    );
-                    let mut stdout = self.files[1].lock_wait();
-                    file::print_to_fd(&mut stdout, &s);
-                    self.last_exit_code = 0;
+            }
+            MessageType::MouseMove { x, y, buttons, .. } if buttons.get_bit(0) => {
+                let Some(selection) = self.selection.filter(|s| s.dragging) else {
+                    return false;
+                };
+                let pos = self.cell_at_clamped(x, y);
+                if pos != selection.end {
+                    self.selection = Some(Selection {
+                        end: pos,
+                        ..selection
+                    });
+                    self.draw_selection();
                 }
-                command => {
-                    if let Some(file_entry) = find_command(command, 0) {
-                        match self.execute_file(file_entry, args) {
-                            Ok(code) => self.last_exit_code = code,
-                            Err(e) => {
-                                let mut stderr = self.files[2].lock_wait();
-                                file::print_to_fd(
-                                    &mut stderr,
-                                    &format!("failed to exec file: {}\n", e),
-                                );
-                                self.last_exit_code = -(e.cause() as i32);
-                            }
-                        }
-                    } else {
-                        let mut stderr = self.files[2].lock_wait();
-                        let stderr = &mut stderr;
-                        file::print_to_fd(stderr, "no such command: ");
-                        file::print_to_fd(stderr, command);
-                        file::print_to_fd(stderr, "\n");
-                        self.last_exit_code = 1;
-                    }
+            }
+            MessageType::MouseButton {
+                press: false,
+                button: 0,
+                ..
+            } => {
+                let Some(selection) = self.selection.as_mut() else {
+                    return false;
+                };
+                selection.dragging = false;
+                if selection.anchor == selection.end {
+                    self.selection = None;
+                } else {
+                    clipboard::set_text(&self.selected_text());
                 }
             }
+            MessageType::MouseButton {
+                press: true,
+                button: 2,
+                ..
+            } => return true,
+            _ => {}
         }
+        false
+    }
 
-        // パイプに送っていた場合
-        if subtask_id != 0 {
-            self.files[1].lock_wait().finish_write();
-            asmfunc::cli();
-            let ret = task::wait_finish(subtask_id);
-            asmfunc::sti();
-            match ret {
-                Ok(code) => self.last_exit_code = code,
-                Err(e) => {
-                    log!(LogLevel::Warn, "failed to wait finish: {}", e);
-                }
-            }
-            LAYER_TASK_MAP
-                .lock_wait()
-                .insert(self.layer_id, self.task_id);
+    /// ウィンドウ内の座標 `(x, y)` にある文字の位置を返す。文字を表示する範囲の外なら [None] を返す。
+    fn cell_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let pos = Vector2D::new(x, y) - Window::TOP_LEFT_MARGIN - Vector2D::new(4, 4);
+        let area = Vector2D::new(8 * self.columns as i32, 16 * self.rows as i32);
+        if pos.x() < 0 || pos.y() < 0 || pos.x() >= area.x() || pos.y() >= area.y() {
+            return None;
         }
+        Some(self.cell_at_clamped(x, y))
+    }
+
+    /// ウィンドウ内の座標 `(x, y)` に最も近い文字の位置を返す。
+    ///
+    /// 文字の右半分を指している場合は、その文字の後ろの位置を返す。
+    fn cell_at_clamped(&self, x: i32, y: i32) -> (usize, usize) {
+        let pos = Vector2D::new(x, y) - Window::TOP_LEFT_MARGIN - Vector2D::new(4, 4);
+        let column = ((pos.x() + 4) / 8).clamp(0, self.columns as i32) as usize;
+        let row = (pos.y() / 16).clamp(0, self.rows as i32 - 1) as usize;
+        (self.scrollback.len() - self.scroll_offset + row, column)
+    }
+
+    /// 選択している範囲を反転して描き直す。
+    fn draw_selection(&mut self) {
+        self.draw_view();
+        self.draw_cursor(true);
+        self.redraw();
+    }
 
-        // 標準出力先を戻す
-        if let Some(stdout) = fd_term_out {
-            self.files[1] = stdout;
+    /// 選択を解除し、反転していた文字を元に戻す。
+    fn clear_selection(&mut self) {
+        if self.selection.take().is_some() {
+            self.draw_selection();
         }
     }
 
-    fn history_up_down(&mut self, direction: i32) -> Rectangle<i32> {
-        let Some(ref window) = self.window else {
-            return Rectangle {
-                pos: Vector2D::default(),
-                size: Vector2D::default(),
-            };
+    /// 選択している文字列を返す。
+    ///
+    /// 各行の末尾の空白は取り除き、折り返しでつながった行の間には改行を入れない。
+    fn selected_text(&self) -> String {
+        let Some(selection) = self.selection else {
+            return String::new();
         };
-
-        if direction == -1 && self.cmd_history_index >= 0 {
-            self.cmd_history_index -= 1;
-        } else if direction == 1 && self.cmd_history_index + 1 < self.cmd_history.len() as i32 {
-            self.cmd_history_index += 1;
+        let (start, end) = selection.range();
+        let mut text = String::new();
+        for i in start.0..=end.0 {
+            let line = self.line(i);
+            let range = selection.columns(i, self.columns);
+            let reaches_end = range.end == self.columns;
+            let chars: String = line[range]
+                .iter()
+                .map(|cell| cell.c)
+                .filter(|&c| c != '\0')
+                .collect();
+            if reaches_end && line.wrapped {
+                text.push_str(&chars);
+            } else {
+                text.push_str(chars.trim_end_matches(' '));
+                if i != end.0 {
+                    text.push('\n');
+                }
+            }
         }
+        text
+    }
 
-        // プロンプト分の1
-        self.cursor = Vector2D::new(1, self.cursor.y());
-        let first_pos = self.calc_curosr_pos();
+    /// スクロールバックの検索を始める。
+    fn start_scrollback_search(&mut self) -> Rectangle<i32> {
+        if self.alt_screen.is_some() {
+            return self.whole_area();
+        }
+        self.scrollback_search = Some(ScrollbackSearch {
+            query: String::new(),
+            found: None,
+            failed: false,
+        });
+        self.draw_view();
+        self.whole_area()
+    }
 
-        let draw_area = Rectangle {
-            pos: first_pos,
-            size: Vector2D::new(8 * (COLUMNS as i32 - 1), 16),
+    /// スクロールバックの検索中に押されたキーを処理する。
+    ///
+    /// 文字は検索する文字列に加え、Ctrl+F はさらに古い行を探す。それ以外のキーで検索を終える。
+    fn input_scrollback_search(&mut self, ctrl: bool, keycode: u8, ascii: u8) -> Rectangle<i32> {
+        let Some(search) = self.scrollback_search.as_mut() else {
+            return self.whole_area();
+        };
+        let total = self.scrollback.len() + self.rows;
+        let before = match (ctrl, keycode, ascii) {
+            (true, 9, _) => search.found.unwrap_or(total),
+            (false, _, 0x08) => {
+                search.query.pop();
+                total
+            }
+            (false, _, c) if c == b' ' || c.is_ascii_graphic() => {
+                search.query.push(c as char);
+                search.found.map_or(total, |i| i + 1)
+            }
+            _ => {
+                self.scrollback_search = None;
+                self.scroll_offset = 0;
+                self.draw_view();
+                self.draw_cursor(true);
+                return self.whole_area();
+            }
         };
-        window
-            .write()
-            .fill_rectangle(draw_area.pos, draw_area.size, &PixelColor::new(0, 0, 0));
+        self.search_scrollback(before);
+        self.draw_view();
+        self.whole_area()
+    }
 
-        let history = if self.cmd_history_index >= 0 {
-            self.cmd_history[self.cmd_history_index as usize].as_bytes()
+    /// `before` 行目より古い行から検索する文字列を含むものを探し、見つかればその行まで遡る。
+    fn search_scrollback(&mut self, before: usize) {
+        let Some(search) = self.scrollback_search.as_ref() else {
+            return;
+        };
+        let query: Vec<_> = search.query.chars().collect();
+        let found = if query.is_empty() {
+            None
         } else {
-            b""
+            (0..before)
+                .rev()
+                .find(|&i| find_chars(self.line(i), &query).is_some())
         };
 
-        self.linebuf[..history.len()].copy_from_slice(history);
-        self.linebuf_index = history.len();
-
-        // Safety: 入力できる文字は ASCII に限られている
-        let history = unsafe { core::str::from_utf8_unchecked(history) };
-        font::write_string(
-            &mut *window.write(),
-            first_pos,
-            history,
-            &PixelColor::new(255, 255, 255),
-        );
-        self.cursor += Vector2D::new(history.len() as i32, 0);
-        draw_area
+        let len = self.scrollback.len();
+        let Some(search) = self.scrollback_search.as_mut() else {
+            return;
+        };
+        search.failed = found.is_none() && !query.is_empty();
+        if let Some(i) = found {
+            search.found = Some(i);
+            // 一致した行が画面の中ほどに来るようにする
+            let top = i.saturating_sub(self.rows / 2).min(len);
+            self.scroll_offset = len - top;
+        }
     }
 
-    fn execute_file(
-        &mut self,
-        file_entry: &'static DirectoryEntry,
-        args: Vec<&str>,
-    ) -> Result<i32> {
+    fn execute_file(&mut self, program: &Program, args: Vec<&str>) -> Result<i32> {
         asmfunc::cli();
         let task = task::current_task();
         asmfunc::sti();
 
         paging::setup_pml4(&task)?;
 
-        let app_load = load_app(file_entry, &task)?;
+        let app_load = load_app(program, &task)?;
 
         // デマンドページを ELF バイナリの最後から割り当てる
         let elf_next_page = (app_load.vaddr_end + 4095) & !0xfff;
@@ -918,7 +2957,7 @@ impl Terminal {
         paging::setup_page_maps(args_frame_addr, 1, true)?;
         let arg_buf =
             unsafe { slice::from_raw_parts_mut(args_frame_addr.addr as *mut u8, BYTES_PER_FRAME) };
-        let argc = make_arg_vector(args, arg_buf)?;
+        let argc = make_arg_vector(args, &task.env(), arg_buf)?;
 
         asmfunc::cli();
         let task = task::current_task();
@@ -944,6 +2983,8 @@ impl Terminal {
             task.os_stack_ptr(),
         );
 
+        self.reset_app_state();
+
         // アプリの実行が終了したら、現在のファイルディスクリプタを全削除
         {
             let mut files = task.files().lock_wait();
@@ -961,39 +3002,337 @@ impl Terminal {
         Ok(ret)
     }
 
-    fn list_all_entries(&mut self, mut dir_cluster: u32) {
-        let entries_per_cluster =
-            BYTES_PER_CLUSTER.get() as usize / mem::size_of::<fat::DirectoryEntry>();
+    /// `path` を現在のタスクのカレントディレクトリを基準として解決した絶対パスを返す。
+    fn resolve_path(&self, path: &str) -> String {
+        asmfunc::cli();
+        let task = task::current_task();
+        asmfunc::sti();
+        task.resolve_path(path)
+    }
 
-        while dir_cluster != fat::END_OF_CLUSTER_CHAIN as _ {
-            let dir = fat::get_sector_by_cluster::<fat::DirectoryEntry>(
-                dir_cluster as _,
-                entries_per_cluster,
-            );
+    /// ディレクトリとして開いた `dir` に含まれるエントリを標準出力に書き出す。
+    /// `long` が `true` の場合は種類、サイズ、更新日時も書き出す。
+    fn list_all_entries(&mut self, dir: &mut FileDescriptor, long: bool) {
+        let mut entries = [DirEntryInfo::new("", 0, 0); 8];
+        while let Ok(n @ 1..) = dir.read_dir(&mut entries) {
+            let mut stdout = self.files[1].lock_wait();
+            for entry in &entries[..n] {
+                file::print_to_fd(&mut stdout, &format_entry(entry, long));
+            }
+        }
+    }
+}
 
-            for entry in dir {
-                let (base, ext) = fat::read_name(entry);
-                // ファイル終了
-                if base.as_bytes()[0] == 0x00 {
-                    return;
-                } else if base.as_bytes()[0] == 0x5e || entry.attr == fat::Attribute::LongName as u8
-                {
-                    continue;
-                }
+impl Expander for Terminal {
+    fn var(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(format!("{}", self.last_exit_code)),
+            "$" => Some(format!("{}", self.task_id)),
+            "#" => Some(format!("{}", self.args.len().saturating_sub(1))),
+            "@" | "*" => Some(self.args.get(1..).unwrap_or_default().join(" ")),
+            name if name.starts_with(|c: char| c.is_ascii_digit()) => name
+                .parse()
+                .ok()
+                .and_then(|i: usize| self.args.get(i).cloned()),
+            name => self.vars.get(name).cloned().or_else(|| {
+                asmfunc::cli();
+                let task = task::current_task();
+                asmfunc::sti();
+                task.env_var(name)
+            }),
+        }
+    }
 
-                let s = if !ext.is_empty() {
-                    format!("{}.{}\n", base, ext)
-                } else {
-                    format!("{}\n", base)
-                };
-                self.print(&s);
+    /// `line` を別のタスクのターミナルで実行し、その標準出力をパイプで受け取る。
+    fn command_output(&mut self, line: &str) -> String {
+        let (mut reader, writer) = FileDescriptor::new_pipe();
+        let id = spawn_terminal(TerminalDescriptor {
+            command: line.into(),
+            vars: self.vars.clone(),
+            args: self.args.clone(),
+            exit_affter_command: true,
+            show_window: false,
+            files: Some([
+                self.files[0].clone(),
+                Arc::new(Mutex::new(writer)),
+                self.files[2].clone(),
+            ]),
+        });
+
+        let output = read_to_end(&mut reader);
+
+        asmfunc::cli();
+        let ret = task::wait_finish(id);
+        asmfunc::sti();
+        if let Err(e) = ret {
+            log!(LogLevel::Warn, "failed to wait finish: {}", e);
+        }
+        String::from_utf8_lossy(&output).into()
+    }
+
+    fn resolve_path(&self, path: &str) -> String {
+        Terminal::resolve_path(self, path)
+    }
+}
+
+/// ディレクトリ `dir` の、名前が `prefix` で始まるエントリを `base` に続けたものと、
+/// それがディレクトリかどうかの組を返す。
+///
+/// 大文字と小文字を区別しないファイルシステムでは、名前を小文字にして返す。
+fn dir_candidates(dir: &str, base: &str, prefix: &str) -> Vec<(String, bool)> {
+    let ignore_case = vfs::ignores_case(dir);
+    let prefix = if ignore_case {
+        prefix.to_ascii_lowercase()
+    } else {
+        prefix.into()
+    };
+    shell::read_entries(dir)
+        .iter()
+        .filter_map(|entry| {
+            let name = entry.name();
+            // 隠しファイルは、`.` で始まる場合のみ候補にする
+            if name == "." || name == ".." || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let name = if ignore_case {
+                name.to_ascii_lowercase()
+            } else {
+                name.into()
+            };
+            let is_dir = entry.attr & Attribute::Directory as u8 != 0;
+            name.starts_with(&prefix)
+                .then(|| (format!("{}{}", base, name), is_dir))
+        })
+        .collect()
+}
+
+/// 非カノニカルモードで、文字を持たないキー `keycode` を表すエスケープシーケンス。
+fn special_key_sequence(keycode: u8) -> Option<&'static [u8]> {
+    let seq: &[u8] = match keycode {
+        0x3a => b"\x1bOP",
+        0x3b => b"\x1bOQ",
+        0x3c => b"\x1bOR",
+        0x3d => b"\x1bOS",
+        0x49 => b"\x1b[2~",
+        0x4a => b"\x1b[H",
+        0x4b => b"\x1b[5~",
+        0x4c => b"\x1b[3~",
+        0x4d => b"\x1b[F",
+        0x4e => b"\x1b[6~",
+        0x4f => b"\x1b[C",
+        0x50 => b"\x1b[D",
+        0x51 => b"\x1b[B",
+        0x52 => b"\x1b[A",
+        _ => return None,
+    };
+    Some(seq)
+}
+
+/// `line` のうち `query` と一致する部分の先頭の位置を返す。
+fn find_chars(line: &[Cell], query: &[char]) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    line.windows(query.len())
+        .position(|w| w.iter().map(|cell| cell.c).eq(query.iter().copied()))
+}
+
+/// `lines` を幅 `columns` で折り返し直す。
+///
+/// `cursor` は `lines` の中でのカーソルの位置 (列, 行) で、折り返し直した後の位置を合わせて返す。
+fn reflow(lines: Vec<Row>, cursor: (usize, usize), columns: usize) -> (Vec<Row>, (usize, usize)) {
+    // 折り返しでつながっている行を、1 つの論理行にまとめる
+    let mut logical_lines = Vec::new();
+    // カーソルがある論理行と、その先頭からの位置
+    let mut logical_cursor = (0, 0);
+    let mut current = Vec::new();
+    for (y, line) in lines.into_iter().enumerate() {
+        if y == cursor.1 {
+            logical_cursor = (logical_lines.len(), current.len() + cursor.0);
+        }
+        let wrapped = line.wrapped;
+        current.extend(line.cells);
+        if !wrapped {
+            // 行末の空白は、新しい幅で折り返さない
+            let len = current
+                .iter()
+                .rposition(|&cell| cell != Cell::default())
+                .map_or(0, |i| i + 1);
+            current.truncate(len);
+            logical_lines.push(mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        logical_lines.push(current);
+    }
+
+    let mut rows = Vec::new();
+    let mut new_cursor = (0, 0);
+    let finish = |mut cells: Vec<Cell>, wrapped| {
+        cells.resize(columns, Cell::default());
+        Row { cells, wrapped }
+    };
+    for (i, cells) in logical_lines.into_iter().enumerate() {
+        let len = cells.len();
+        let mut row = Vec::with_capacity(columns);
+        for (j, cell) in cells.into_iter().enumerate() {
+            let width = match cell.c {
+                '\0' => 0,
+                c if c.is_ascii() => 1,
+                _ => 2,
+            };
+            // 全角文字は 2 行に分けない
+            if row.len() + width > columns {
+                rows.push(finish(mem::take(&mut row), true));
+            }
+            if (i, j) == logical_cursor {
+                new_cursor = (row.len(), rows.len());
+            }
+            row.push(cell);
+        }
+        // 行末より後ろにあるカーソル
+        if i == logical_cursor.0 && logical_cursor.1 >= len {
+            let x = row.len() + logical_cursor.1 - len;
+            new_cursor = (x.min(columns), rows.len());
+        }
+        rows.push(finish(row, false));
+    }
+    (rows, new_cursor)
+}
+
+fn blank_line(attr: Attr, columns: usize) -> Row {
+    Row {
+        cells: Vec::from_iter(iter::repeat_n(Cell::blank(attr), columns)),
+        wrapped: false,
+    }
+}
+
+/// 画面の `(x, y)` の文字を描く位置。
+fn cell_pos(x: usize, y: usize) -> Vector2D<i32> {
+    Vector2D::new(4 + 8 * x as i32, 4 + 16 * y as i32)
+}
+
+/// `(x, y)` に `cell` を描く。`invert` の場合は文字色と背景色を入れ替える。
+fn draw_cell(window: &mut Window, x: usize, y: usize, cell: Cell, invert: bool) {
+    let (mut fg, mut bg) = cell.attr.colors();
+    if invert {
+        mem::swap(&mut fg, &mut bg);
+    }
+    let width = if cell.c.is_ascii() { 8 } else { 16 };
+    let pos = cell_pos(x, y);
+    window.fill_rectangle(pos, Vector2D::new(width, 16), &bg);
+    if cell.c != ' ' {
+        font::write_unicode(window, pos, cell.c, &fg);
+        // 太字は 1 ピクセルずらして重ね書きする
+        if cell.attr.bold {
+            font::write_unicode(window, pos + Vector2D::new(1, 0), cell.c, &fg);
+        }
+    }
+}
+
+/// `y` 行目に `line` の文字を描く。
+fn draw_cells(window: &mut Window, y: usize, line: &[Cell]) {
+    for (x, &cell) in line.iter().enumerate() {
+        if cell.c != '\0' {
+            draw_cell(window, x, y, cell, false);
+        }
+    }
+}
+
+/// `fd` から EOF まで読み込む。
+fn read_to_end(fd: &mut FileDescriptor) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0; 256];
+    loop {
+        match fd.read(&mut buf) {
+            0 => return data,
+            len => data.extend_from_slice(&buf[..len]),
+        }
+    }
+}
+
+/// ファイルの先頭が `#!` であれば、シェルスクリプトとみなす。
+fn is_script(entry: &DirectoryEntry) -> bool {
+    if entry.file_size < 2 || entry.first_cluster() == 0 {
+        return false;
+    }
+    let head = fat::get_sector_by_cluster::<u8>(entry.first_cluster() as _, 2);
+    head == b"#!"
+}
+
+/// コマンドとして実行するファイル。
+enum Program {
+    /// ブートボリュームのファイル。ロードした結果を使い回す。
+    Fat(&'static DirectoryEntry),
+    /// `/tmp` や `/mnt` など、それ以外のファイルシステムのファイルの中身。
+    Other(Vec<u8>),
+}
+
+impl Program {
+    /// 絶対パス `path` のファイルを開く。FAT 以外のファイルは、ここで中身を全て読み込む。
+    fn open(path: &str) -> Result<Self> {
+        if let Some(entry) = vfs::fat_entry(path) {
+            return Ok(Self::Fat(entry));
+        }
+
+        let mut fd = vfs::open(path, FileFlags::RDONLY)?;
+        // デバイスファイルのように終わりの無いものもあるので、ファイルの大きさだけ読む
+        let mut data = vec![0; fd.size()];
+        let mut len = 0;
+        while len < data.len() {
+            match fd.read(&mut data[len..]) {
+                0 => break,
+                n => len += n,
             }
+        }
+        data.truncate(len);
+        Ok(Self::Other(data))
+    }
+
+    /// `#!` で始まるスクリプトかどうかを返す。
+    fn is_script(&self) -> bool {
+        match self {
+            Self::Fat(entry) => is_script(entry),
+            Self::Other(data) => data.starts_with(b"#!"),
+        }
+    }
 
-            dir_cluster = fat::next_cluster(dir_cluster as _) as _;
+    /// ファイルの中身を返す。
+    fn contents(&self) -> Vec<u8> {
+        match self {
+            Self::Fat(entry) => fat::load_file(entry),
+            Self::Other(data) => data.clone(),
         }
     }
 }
 
+/// `ls` の 1 行分の表示を返す。
+fn format_entry(entry: &DirEntryInfo, long: bool) -> String {
+    if !long {
+        return format!("{}\n", entry.name());
+    }
+
+    let kind = if entry.attr & Attribute::Directory as u8 != 0 {
+        'd'
+    } else if entry.attr & ATTR_FIFO != 0 {
+        'p'
+    } else {
+        '-'
+    };
+    let mtime = if entry.mtime == 0 {
+        String::from("-")
+    } else {
+        format!("{}", DateTime::from_unix(entry.mtime))
+    };
+    format!(
+        "{} {:>10} {:<19} {}\n",
+        kind,
+        entry.size,
+        mtime,
+        entry.name()
+    )
+}
+
 /// アプリの情報と、コピーオンライトの雛形になっているページディレクトリの情報を保持する。
 #[derive(Debug, Clone)]
 struct AppLoadInfoTemplate {
@@ -1021,7 +3360,8 @@ impl AppLoadInfo {
 }
 
 /// ロードした ELF バイナリの最終アドレスを返す。
-fn load_elf(ehdr: &Elf64Ehdr) -> Result<u64> {
+/// `writable` が `false` の場合は、読み取り専用のページにロードする。
+fn load_elf(ehdr: &Elf64Ehdr, writable: bool) -> Result<u64> {
     if ehdr.r#type != ExecuteType::Exec {
         return Err(make_error!(Code::InvalidFormat));
     }
@@ -1031,14 +3371,26 @@ fn load_elf(ehdr: &Elf64Ehdr) -> Result<u64> {
         return Err(make_error!(Code::InvalidFormat));
     }
 
-    copy_load_segments(ehdr)
+    copy_load_segments(ehdr, writable)
 }
 
 /// アプリがロードされていなければ読み取り専用でロードし、
 /// 既にどこかにロードされている場合は PT（ページテーブル）ごとその浅いコピーを返す。
-fn load_app(file_entry: &'static DirectoryEntry, task: &Arc<Task>) -> Result<AppLoadInfo> {
+fn load_app(program: &Program, task: &Arc<Task>) -> Result<AppLoadInfo> {
     let temp_pml4 = paging::setup_pml4(task)?;
 
+    let Program::Fat(file_entry) = *program else {
+        // FAT 以外のファイルは中身が変わりうるので使い回さず、書き込み可能でタスクに直接ロードする
+        let file_buf = program.contents();
+        let elf_header = elf_header(&file_buf)?;
+        let last_addr = load_elf(elf_header, true)?;
+        return Ok(AppLoadInfo {
+            entry: elf_header.entry as _,
+            vaddr_end: last_addr,
+            pml4: temp_pml4,
+        });
+    };
+
     let mut app_loads = APP_LOADS.lock_wait();
     if let Some(app_load) = app_loads.get(&file_entry).cloned() {
         paging::copy_page_maps(temp_pml4, app_load.pml4, 4, 256)?;
@@ -1046,13 +3398,8 @@ fn load_app(file_entry: &'static DirectoryEntry, task: &Arc<Task>) -> Result<App
     }
 
     let file_buf = fat::load_file(file_entry);
-
-    let elf_header: &Elf64Ehdr = unsafe { &*(file_buf.as_ptr() as *const _) };
-    if &elf_header.ident[..4] != b"\x7fELF" {
-        return Err(make_error!(Code::InvalidFile));
-    }
-
-    let last_addr = load_elf(elf_header)?;
+    let elf_header = elf_header(&file_buf)?;
+    let last_addr = load_elf(elf_header, false)?;
 
     let app_load_temp = AppLoadInfoTemplate {
         entry: elf_header.entry as _,
@@ -1067,6 +3414,14 @@ fn load_app(file_entry: &'static DirectoryEntry, task: &Arc<Task>) -> Result<App
     Ok(app_load)
 }
 
+/// `file_buf` の先頭を ELF ヘッダとして返す。ELF ファイルでなければエラーを返す。
+fn elf_header(file_buf: &[u8]) -> Result<&Elf64Ehdr> {
+    if file_buf.len() < mem::size_of::<Elf64Ehdr>() || &file_buf[..4] != b"\x7fELF" {
+        return Err(make_error!(Code::InvalidFile));
+    }
+    Ok(unsafe { &*(file_buf.as_ptr() as *const _) })
+}
+
 fn get_first_load_address(ehdr: &Elf64Ehdr) -> usize {
     for phdr in get_program_headers(ehdr) {
         if phdr.r#type != ProgramType::Load as _ {
@@ -1087,7 +3442,7 @@ fn get_program_headers(ehdr: &Elf64Ehdr) -> &[Elf64Phdr] {
 }
 
 /// ロードした ELF バイナリの最終アドレスを返す。
-fn copy_load_segments(ehdr: &Elf64Ehdr) -> Result<u64> {
+fn copy_load_segments(ehdr: &Elf64Ehdr, writable: bool) -> Result<u64> {
     let mut elf_last_addr = 0;
 
     for phdr in get_program_headers(ehdr) {
@@ -1105,7 +3460,7 @@ fn copy_load_segments(ehdr: &Elf64Ehdr) -> Result<u64> {
         // 4 KB アラインの先頭から数える必要がある
         let num_4kpages = ((phdr.vaddr & 0xfff) + phdr.memsz as usize + 4095) / 4096;
 
-        paging::setup_page_maps(dest_addr, num_4kpages, false)?;
+        paging::setup_page_maps(dest_addr, num_4kpages, writable)?;
 
         unsafe {
             let src = (ehdr as *const _ as *const u8).add(phdr.offset as usize);
@@ -1122,57 +3477,66 @@ fn copy_load_segments(ehdr: &Elf64Ehdr) -> Result<u64> {
     Ok(elf_last_addr)
 }
 
-/// 引数を配置し、引数の数を返す。
-/// ただし `args` は32個まで。
-fn make_arg_vector(args: Vec<&str>, buf: &mut [u8]) -> Result<usize> {
-    let len = args.len();
-    if len >= 32 {
-        return Err(make_error!(Code::InvalidFormat, "too many args"));
-    }
-
-    if buf.len() < 32 * mem::size_of::<*const c_char>() {
+/// 引数と環境変数を配置し、引数の数を返す。
+///
+/// 先頭にポインタの配列を argv、ヌルポインタ、envp、ヌルポインタの順に並べ、
+/// その後ろにそれぞれが指すヌル終端文字列を置く。
+/// 環境変数は `NAME=value` の形の文字列にする。
+fn make_arg_vector(
+    args: Vec<&str>,
+    env: &BTreeMap<String, String>,
+    buf: &mut [u8],
+) -> Result<usize> {
+    let argc = args.len();
+    let env: Vec<_> = env
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let strings = args.into_iter().chain(env.iter().map(String::as_str));
+
+    let num_ptrs = argc + 1 + env.len() + 1;
+    let mut cur = num_ptrs * mem::size_of::<*const c_char>();
+    if cur > buf.len() {
         return Err(make_error!(Code::BufferTooSmall));
     }
 
-    let mut cur = 32 * mem::size_of::<*const c_char>();
-    for (i, arg) in args.into_iter().enumerate() {
+    let ptrs = buf.as_mut_ptr() as *mut *const c_char;
+    for (i, s) in strings.enumerate() {
         // null 文字分多く必要
-        if cur + arg.len() + 1 >= buf.len() {
+        if cur + s.len() + 1 >= buf.len() {
             return Err(make_error!(Code::BufferTooSmall));
         }
 
-        unsafe {
-            *(buf.as_ptr() as *mut *const c_char).add(i) =
-                buf.as_ptr().byte_add(cur) as *const c_char;
-        }
-        buf[cur..cur + arg.len()].clone_from_slice(arg.as_bytes());
-        cur += arg.len() + 1;
+        // argv の後ろのヌルポインタを飛ばす
+        let index = if i < argc { i } else { i + 1 };
+        unsafe { *ptrs.add(index) = buf.as_ptr().byte_add(cur) as *const c_char };
+        buf[cur..cur + s.len()].clone_from_slice(s.as_bytes());
+        cur += s.len() + 1;
         buf[cur - 1] = 0;
     }
-    Ok(len)
+    unsafe {
+        *ptrs.add(argc) = ptr::null();
+        *ptrs.add(num_ptrs - 1) = ptr::null();
+    }
+    Ok(argc)
 }
 
-/// `command` を絶対パス、相対パス、もしくは `/apps` に含まれているファイル名として探索する。
-fn find_command(command: &str, dir_cluster: u64) -> Option<&'static DirectoryEntry> {
-    match fat::find_file(command, dir_cluster) {
-        (_, true) => return None,
-        (Some(entry), false) => {
-            if entry.attr == Attribute::Directory as _ {
-                return None;
-            } else {
-                return Some(entry);
-            }
-        }
-        _ => {
-            if dir_cluster != 0 || command.contains('/') {
-                return None;
-            }
-        }
-    }
+/// `command` を絶対パス、`cwd` からの相対パス、もしくは `path_var` に `:` 区切りで
+/// 並んだディレクトリに含まれているファイル名として探索する。
+fn find_command(command: &str, cwd: &str, path_var: &str) -> Option<String> {
+    let find = |dir: &str| {
+        let path = path::resolve(dir, command);
+        let attr = vfs::stat(&path).ok()?.attr;
+        (attr & (Attribute::Directory as u8 | ATTR_FIFO) == 0).then_some(path)
+    };
 
-    if let (Some(apps_entry), _) = fat::find_file("apps", 0) {
-        find_command(command, apps_entry.first_cluster() as _)
-    } else {
-        None
-    }
+    find(cwd).or_else(|| {
+        if command.contains('/') {
+            return None;
+        }
+        path_var
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .find_map(|dir| find(&path::resolve(cwd, dir)))
+    })
 }
@@ -0,0 +1,195 @@
+//! `/tmp` にマウントされる、メモリ上にのみ存在するファイルシステム。
+//!
+//! 削除されたファイルやディレクトリのメモリは、それを開いているファイルディスクリプタが
+//! 全て閉じられた時点で解放される。
+//!
+//! 名前付きパイプ (FIFO) もここにのみ作ることができる。
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    error::{Code, Result},
+    fat::Attribute,
+    file::{DirEntryInfo, FileDescriptor, FileFlags, ATTR_FIFO},
+    make_error,
+    pipe::Fifo,
+    sync::Mutex,
+};
+
+/// ディレクトリの中身。
+/// key: ファイル名。
+type DirMap = BTreeMap<String, Node>;
+
+/// ファイルの中身。
+pub type FileData = Arc<Mutex<Vec<u8>>>;
+
+/// `/tmp` 自身の中身。
+static ROOT: Mutex<DirMap> = Mutex::new(BTreeMap::new());
+
+/// ファイル、ディレクトリもしくは FIFO。
+#[derive(Clone)]
+enum Node {
+    File(FileData),
+    Dir(Arc<Mutex<DirMap>>),
+    Fifo(Fifo),
+}
+
+impl Node {
+    fn entry_info(&self, name: &str) -> DirEntryInfo {
+        match self {
+            Self::File(data) => DirEntryInfo::new(name, 0, data.lock_wait().len() as _),
+            Self::Dir(_) => DirEntryInfo::new(name, Attribute::Directory as _, 0),
+            Self::Fifo(_) => DirEntryInfo::new(name, ATTR_FIFO, 0),
+        }
+    }
+}
+
+/// `/tmp` 以降のパス `path` が指すファイルもしくはディレクトリを開く。
+///
+/// ファイルが存在せず、`flags` に [FileFlags::CREAT] が含まれる場合は新しく作る。
+pub fn open(path: &str, flags: FileFlags) -> Result<FileDescriptor> {
+    let (parent, name, post_slash) = split_path(path)?;
+    let Some(name) = name else {
+        return Ok(FileDescriptor::new_virtual_dir(entries(&ROOT.lock_wait())));
+    };
+
+    let node = with_dir(&parent, |dir| match dir.get(name) {
+        Some(node) => Ok(node.clone()),
+        None => {
+            if flags & FileFlags::CREAT == FileFlags::new(0) {
+                return Err(make_error!(Code::NoSuchEntry));
+            }
+            if post_slash {
+                return Err(make_error!(Code::IsDirectory));
+            }
+            let node = Node::File(Arc::new(Mutex::new(Vec::new())));
+            dir.insert(name.into(), node.clone());
+            Ok(node)
+        }
+    })?;
+
+    match node {
+        Node::Dir(d) => Ok(FileDescriptor::new_virtual_dir(entries(&d.lock_wait()))),
+        Node::File(_) | Node::Fifo(_) if post_slash => Err(make_error!(Code::NotDirectory)),
+        Node::File(data) => {
+            if flags & FileFlags::TRUNC != FileFlags::new(0) {
+                data.lock_wait().clear();
+            }
+            Ok(FileDescriptor::new_tmp(data))
+        }
+        // 相手側が開かれるまでブロックするので、ディレクトリのロックを外してから開く
+        Node::Fifo(fifo) => FileDescriptor::new_fifo(&fifo, flags),
+    }
+}
+
+/// `/tmp` 以降のパス `path` が指すファイルもしくはディレクトリの情報を返す。
+///
+/// [open] と違い、FIFO であっても相手側を待たない。
+pub fn stat(path: &str) -> Result<DirEntryInfo> {
+    let (parent, name, post_slash) = split_path(path)?;
+    let Some(name) = name else {
+        return Ok(DirEntryInfo::new("tmp", Attribute::Directory as _, 0));
+    };
+
+    let node = with_dir(&parent, |dir| {
+        dir.get(name)
+            .cloned()
+            .ok_or_else(|| make_error!(Code::NoSuchEntry))
+    })?;
+    if post_slash && !matches!(node, Node::Dir(_)) {
+        return Err(make_error!(Code::NotDirectory));
+    }
+    Ok(node.entry_info(name))
+}
+
+/// `/tmp` 以降のパス `path` に空のディレクトリを作る。
+pub fn make_dir(path: &str) -> Result<()> {
+    make_node(path, Node::Dir(Arc::new(Mutex::new(BTreeMap::new()))))
+}
+
+/// `/tmp` 以降のパス `path` に FIFO を作る。
+pub fn make_fifo(path: &str) -> Result<()> {
+    make_node(path, Node::Fifo(Fifo::new()))
+}
+
+fn make_node(path: &str, node: Node) -> Result<()> {
+    let (parent, name, _) = split_path(path)?;
+    let Some(name) = name else {
+        return Err(make_error!(Code::AlreadyExists));
+    };
+
+    with_dir(&parent, |dir| {
+        if dir.contains_key(name) {
+            return Err(make_error!(Code::AlreadyExists));
+        }
+        dir.insert(name.into(), node);
+        Ok(())
+    })
+}
+
+/// `/tmp` 以降のパス `path` が指すファイルもしくは空のディレクトリを削除する。
+pub fn remove(path: &str) -> Result<()> {
+    let (parent, name, post_slash) = split_path(path)?;
+    let Some(name) = name else {
+        // `/tmp` 自体は削除できない
+        return Err(make_error!(Code::ReadOnly));
+    };
+
+    with_dir(&parent, |dir| {
+        match dir.get(name) {
+            None => return Err(make_error!(Code::NoSuchEntry)),
+            Some(Node::File(_) | Node::Fifo(_)) if post_slash => {
+                return Err(make_error!(Code::NotDirectory))
+            }
+            Some(Node::Dir(d)) if !d.lock_wait().is_empty() => {
+                return Err(make_error!(Code::NotEmpty))
+            }
+            Some(_) => {}
+        }
+        dir.remove(name);
+        Ok(())
+    })
+}
+
+/// `path` を親ディレクトリまでの要素と最後の要素に分ける。
+/// また、末尾に `/` があるかどうかも返す。
+///
+/// `.` や `..` は解決済みであることを前提とし、これらを含む場合はエラーを返す。
+fn split_path(path: &str) -> Result<(Vec<&str>, Option<&str>, bool)> {
+    let mut elems: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if elems.iter().any(|&e| e == "." || e == "..") {
+        return Err(make_error!(Code::NoSuchEntry));
+    }
+    let post_slash = !elems.is_empty() && path.ends_with('/');
+    let name = elems.pop();
+    Ok((elems, name, post_slash))
+}
+
+/// `elems` を辿った先のディレクトリに対して `f` を呼ぶ。
+fn with_dir<R>(elems: &[&str], f: impl FnOnce(&mut DirMap) -> Result<R>) -> Result<R> {
+    let Some((first, rest)) = elems.split_first() else {
+        return f(&mut ROOT.lock_wait());
+    };
+
+    let mut dir = child_dir(&ROOT.lock_wait(), first)?;
+    for elem in rest {
+        let next = child_dir(&dir.lock_wait(), elem)?;
+        dir = next;
+    }
+    let mut dir = dir.lock_wait();
+    f(&mut dir)
+}
+
+fn child_dir(dir: &DirMap, name: &str) -> Result<Arc<Mutex<DirMap>>> {
+    match dir.get(name) {
+        Some(Node::Dir(d)) => Ok(d.clone()),
+        Some(Node::File(_) | Node::Fifo(_)) => Err(make_error!(Code::NotDirectory)),
+        None => Err(make_error!(Code::NoSuchEntry)),
+    }
+}
+
+fn entries(dir: &DirMap) -> Vec<DirEntryInfo> {
+    dir.iter()
+        .map(|(name, node)| node.entry_info(name))
+        .collect()
+}
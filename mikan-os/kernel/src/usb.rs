@@ -27,7 +27,7 @@ extern "C" {
     #[link_name = "_ZN3usb4xhci12ProcessEventERNS0_10ControllerE"]
     fn xhci_process_event(xhc: *mut Controller) -> CxxError;
 
-    #[link_name = "_ZN3usb14HIDMouseDriver18SetDefaultObserverEPFvhaaE"]
+    #[link_name = "_ZN3usb14HIDMouseDriver18SetDefaultObserverEPFvhaaaE"]
     fn hid_mouse_driver_set_default_observer(observer: *const c_void);
 
     #[link_name = "_ZNK3usb4xhci4Port11IsConnectedEv"]
@@ -125,7 +125,7 @@ impl EventRing {
     }
 }
 
-type MouseObserverType = fn(c_uchar, c_schar, c_schar);
+type MouseObserverType = fn(c_uchar, c_schar, c_schar, c_schar);
 
 #[repr(C)]
 struct Function {
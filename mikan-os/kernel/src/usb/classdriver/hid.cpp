@@ -52,6 +52,7 @@ namespace usb {
 
   Error HIDBaseDriver::OnInterruptCompleted(EndpointID ep_id, const void* buf, int len) {
     if (ep_id.IsIn()) {
+      received_len_ = len;
       OnDataReceived();
       std::copy_n(buf_.begin(), len, previous_buf_.begin());
       return ParentDevice()->InterruptIn(ep_interrupt_in_, buf_.data(), in_packet_size_);
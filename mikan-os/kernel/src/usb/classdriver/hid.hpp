@@ -23,6 +23,8 @@ namespace usb {
     const static size_t kBufferSize = 1024;
     const std::array<uint8_t, kBufferSize>& Buffer() const { return buf_; }
     const std::array<uint8_t, kBufferSize>& PreviousBuffer() const { return previous_buf_; }
+    /** @brief 最後に受け取ったデータのバイト数． */
+    int ReceivedLength() const { return received_len_; }
 
    private:
     EndpointID ep_interrupt_in_;
@@ -30,6 +32,7 @@ namespace usb {
     const int interface_index_;
     int in_packet_size_;
     int initialize_phase_{0};
+    int received_len_{0};
 
     std::array<uint8_t, kBufferSize> buf_{}, previous_buf_{};
   };
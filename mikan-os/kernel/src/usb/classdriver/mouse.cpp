@@ -7,7 +7,7 @@
 
 namespace usb {
   HIDMouseDriver::HIDMouseDriver(Device* dev, int interface_index)
-      : HIDBaseDriver{dev, interface_index, 3} {
+      : HIDBaseDriver{dev, interface_index, 4} {
   }
 
   // #@@range_begin(on_data_received)
@@ -15,8 +15,13 @@ namespace usb {
     uint8_t buttons = Buffer()[0];
     int8_t displacement_x = Buffer()[1];
     int8_t displacement_y = Buffer()[2];
-    NotifyMouseMove(buttons, displacement_x, displacement_y);
-    Log(kDebug, "%02x,(%3d,%3d)\n", buttons, displacement_x, displacement_y);
+    // ブートプロトコルのレポートはボタンと X, Y の 3 バイトで、ホイールは規格に含まれない。
+    // 多くのマウスはブートプロトコルでも 4 バイト目にホイールの回転量を付けてくるので、
+    // 実際に 4 バイト以上届いた場合に限りそれをホイールとみなす。
+    // レポートディスクリプタは解釈しないので、それ以外の配置のマウスではホイールは使えない。
+    int8_t wheel = ReceivedLength() >= 4 ? Buffer()[3] : 0;
+    NotifyMouseMove(buttons, displacement_x, displacement_y, wheel);
+    Log(kDebug, "%02x,(%3d,%3d),%3d\n", buttons, displacement_x, displacement_y, wheel);
     return MAKE_ERROR(Error::kSuccess);
   }
   // #@@range_end(on_data_received)
@@ -41,9 +46,9 @@ namespace usb {
 
   // #@@range_begin(notify_mousemove)
   void HIDMouseDriver::NotifyMouseMove(
-      uint8_t buttons, int8_t displacement_x, int8_t displacement_y) {
+      uint8_t buttons, int8_t displacement_x, int8_t displacement_y, int8_t wheel) {
     for (int i = 0; i < num_observers_; ++i) {
-      observers_[i](buttons, displacement_x, displacement_y);
+      observers_[i](buttons, displacement_x, displacement_y, wheel);
     }
   }
   // #@@range_end(notify_mousemove)
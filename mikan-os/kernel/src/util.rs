@@ -52,6 +52,11 @@ impl<T> OnceStatic<T> {
 
         self.lock.store(false, Relaxed);
     }
+
+    /// 初期化済みかどうかを返す。
+    pub fn is_initialized(&self) -> bool {
+        self.is_initialized.load(Acquire)
+    }
 }
 
 impl<T> Drop for OnceStatic<T> {
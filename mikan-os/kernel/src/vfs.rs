@@ -0,0 +1,188 @@
+//! パスに応じて、そのパスを扱うファイルシステムへ処理を振り分ける。
+
+use crate::{
+    devfs,
+    error::{Code, Result},
+    ext2, fat,
+    file::{DirEntryInfo, FileDescriptor, FileFlags},
+    make_error, path, procfs, tmpfs,
+};
+
+/// FAT 以外のファイルシステムの種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileSystem {
+    Dev,
+    Proc,
+    Tmp,
+    Ext2,
+}
+
+/// マウントポイントと、そこにマウントされているファイルシステムの一覧。
+/// ここに含まれないパスは全てブートボリュームの FAT で扱う。
+/// `/mnt` は ext2 ボリュームが無い場合は空のディレクトリとして扱う。
+const MOUNTS: [(&str, FileSystem); 4] = [
+    ("/dev", FileSystem::Dev),
+    ("/proc", FileSystem::Proc),
+    ("/tmp", FileSystem::Tmp),
+    ("/mnt", FileSystem::Ext2),
+];
+
+/// 絶対パス `path` が指すファイルもしくはディレクトリを `flags` に従って開く。
+///
+/// ディレクトリは読み込み専用でしか開けない。
+/// [FileFlags::APPEND] が指定された場合は、書き込みをファイルの末尾から始める。
+pub fn open(path: &str, flags: FileFlags) -> Result<FileDescriptor> {
+    let mut fd = match find_mount(path) {
+        Some((FileSystem::Dev, rest)) => devfs::open(rest)?,
+        Some((FileSystem::Proc, rest)) => {
+            if flags & FileFlags::ACCMODE != FileFlags::RDONLY {
+                return Err(make_error!(Code::ReadOnly));
+            }
+            procfs::open(rest)?
+        }
+        Some((FileSystem::Tmp, rest)) => tmpfs::open(rest, flags)?,
+        Some((FileSystem::Ext2, rest)) => {
+            if flags & FileFlags::ACCMODE != FileFlags::RDONLY {
+                return Err(make_error!(Code::ReadOnly));
+            }
+            ext2::open(rest)?
+        }
+        None => open_fat(path, flags)?,
+    };
+
+    if fd.is_dir() && flags & FileFlags::ACCMODE != FileFlags::RDONLY {
+        return Err(make_error!(Code::IsDirectory));
+    }
+    if flags & FileFlags::APPEND != FileFlags::new(0) {
+        fd.seek_end();
+    }
+    Ok(fd)
+}
+
+/// 絶対パス `path` が指すファイルもしくはディレクトリの情報を返す。
+///
+/// FIFO は開くと相手側を待ってしまうので、`/tmp` 以下は開かずに調べる。
+pub fn stat(path: &str) -> Result<DirEntryInfo> {
+    if let Some((FileSystem::Tmp, rest)) = find_mount(path) {
+        return tmpfs::stat(rest);
+    }
+
+    let fd = open(path, FileFlags::RDONLY)?;
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let attr = if fd.is_dir() {
+        fat::Attribute::Directory as _
+    } else {
+        0
+    };
+    Ok(DirEntryInfo {
+        mtime: fd.mtime(),
+        ..DirEntryInfo::new(name, attr, fd.size() as _)
+    })
+}
+
+/// 絶対パス `path` がブートボリュームの FAT 上のファイルを指していれば、そのディレクトリエントリを返す。
+pub fn fat_entry(path: &str) -> Option<&'static mut fat::DirectoryEntry> {
+    if find_mount(path).is_some() {
+        return None;
+    }
+    match fat::find_file(path, 0) {
+        (Some(entry), false) if entry.attr != fat::Attribute::Directory as u8 => Some(entry),
+        _ => None,
+    }
+}
+
+/// 絶対パス `path` がディレクトリを指していなければエラーを返す。
+pub fn check_dir(path: &str) -> Result<()> {
+    if stat(path)?.attr & fat::Attribute::Directory as u8 != 0 {
+        Ok(())
+    } else {
+        Err(make_error!(Code::NotDirectory))
+    }
+}
+
+/// 絶対パス `path` に空のディレクトリを作る。
+pub fn make_dir(path: &str) -> Result<()> {
+    match find_mount(path) {
+        Some((FileSystem::Tmp, rest)) => tmpfs::make_dir(rest),
+        Some((FileSystem::Dev | FileSystem::Proc | FileSystem::Ext2, _)) => {
+            Err(make_error!(Code::ReadOnly))
+        }
+        None => fat::make_dir(path),
+    }
+}
+
+/// 絶対パス `path` に FIFO を作る。
+///
+/// FIFO を置けるのは `/tmp` 以下のみ。
+pub fn make_fifo(path: &str) -> Result<()> {
+    match find_mount(path) {
+        Some((FileSystem::Tmp, rest)) => tmpfs::make_fifo(rest),
+        Some((FileSystem::Dev | FileSystem::Proc | FileSystem::Ext2, _)) => {
+            Err(make_error!(Code::ReadOnly))
+        }
+        None => Err(make_error!(
+            Code::NotImplemented,
+            "FIFOs can only be created under /tmp"
+        )),
+    }
+}
+
+/// 絶対パス `path` が指すファイルもしくは空のディレクトリを削除する。
+pub fn remove(path: &str) -> Result<()> {
+    match find_mount(path) {
+        Some((FileSystem::Tmp, rest)) => tmpfs::remove(rest),
+        Some((FileSystem::Dev | FileSystem::Proc | FileSystem::Ext2, _)) => {
+            Err(make_error!(Code::ReadOnly))
+        }
+        // ルートディレクトリは削除できない
+        None if path::is_root(path) => Err(make_error!(Code::ReadOnly)),
+        None => fat::remove(path),
+    }
+}
+
+/// 絶対パス `path` を扱うファイルシステムが、ファイル名の大文字と小文字を区別しないかどうかを返す。
+pub fn ignores_case(path: &str) -> bool {
+    // FAT のみが区別しない
+    find_mount(path).is_none()
+}
+
+/// `path` がマウントポイント以下を指している場合は、
+/// そのファイルシステムとマウントポイント以降のパスを返す。
+fn find_mount(path: &str) -> Option<(FileSystem, &str)> {
+    MOUNTS.iter().find_map(|&(mount_point, fs)| {
+        let rest = path.strip_prefix(mount_point)?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some((fs, rest))
+        } else {
+            None
+        }
+    })
+}
+
+fn open_fat(path: &str, flags: FileFlags) -> Result<FileDescriptor> {
+    // ルートディレクトリはディレクトリエントリを持たないので特別扱い
+    if path::is_root(path) {
+        return Ok(FileDescriptor::new_dir(0));
+    }
+
+    match fat::find_file(path, 0) {
+        (Some(entry), post_slash) => {
+            if entry.attr == fat::Attribute::Directory as _ {
+                Ok(FileDescriptor::new_dir(entry.first_cluster() as _))
+            } else if post_slash {
+                Err(make_error!(Code::NotDirectory))
+            } else {
+                if flags & FileFlags::TRUNC != FileFlags::new(0) {
+                    fat::truncate(entry);
+                }
+                Ok(FileDescriptor::new_fat(entry))
+            }
+        }
+        (None, _) => {
+            if flags & FileFlags::CREAT == FileFlags::new(0) {
+                return Err(make_error!(Code::NoSuchEntry));
+            }
+            Ok(FileDescriptor::new_fat(fat::create_file(path)?))
+        }
+    }
+}
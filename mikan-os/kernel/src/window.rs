@@ -13,8 +13,12 @@ use crate::{
 
 const CLOSE_BUTTON_WIDTH: usize = 16;
 const CLOSE_BUTTON_HEIGHT: usize = 14;
+/// 枠のうち、角として 2 辺を同時にドラッグできる部分の長さ。
+const CORNER_SIZE: i32 = 16;
 
-const CLOSE_BUTTON: [&[u8; CLOSE_BUTTON_WIDTH]; CLOSE_BUTTON_HEIGHT] = [
+type ButtonShape = [&'static [u8; CLOSE_BUTTON_WIDTH]; CLOSE_BUTTON_HEIGHT];
+
+const CLOSE_BUTTON: ButtonShape = [
     b"...............@",
     b".:::::::::::::$@",
     b".:::::::::::::$@",
@@ -31,9 +35,48 @@ const CLOSE_BUTTON: [&[u8; CLOSE_BUTTON_WIDTH]; CLOSE_BUTTON_HEIGHT] = [
     b"@@@@@@@@@@@@@@@@",
 ];
 
+const MINIMIZE_BUTTON: ButtonShape = [
+    b"...............@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".:::@@@@@@::::$@",
+    b".:::@@@@@@::::$@",
+    b".:::::::::::::$@",
+    b".$$$$$$$$$$$$$$@",
+    b"@@@@@@@@@@@@@@@@",
+];
+
+const MAXIMIZE_BUTTON: ButtonShape = [
+    b"...............@",
+    b".:::::::::::::$@",
+    b".::@@@@@@@@:::$@",
+    b".::@@@@@@@@:::$@",
+    b".::@::::::@:::$@",
+    b".::@::::::@:::$@",
+    b".::@::::::@:::$@",
+    b".::@::::::@:::$@",
+    b".::@::::::@:::$@",
+    b".::@@@@@@@@:::$@",
+    b".:::::::::::::$@",
+    b".:::::::::::::$@",
+    b".$$$$$$$$$$$$$$@",
+    b"@@@@@@@@@@@@@@@@",
+];
+
 pub enum Window {
     Base(WindowBase),
-    Toplevel { base: WindowBase, title: String },
+    Toplevel {
+        base: WindowBase,
+        title: String,
+        /// 枠のドラッグや最大化ボタンで大きさを変えられるかどうか。
+        resizable: bool,
+    },
 }
 
 impl Window {
@@ -43,6 +86,9 @@ impl Window {
     pub const MARGIN_X: u32 = (Self::TOP_LEFT_MARGIN.x() + Self::BOTTOM_RIGHT_MARGIN.x()) as u32;
     pub const MARGIN_Y: u32 = (Self::TOP_LEFT_MARGIN.y() + Self::BOTTOM_RIGHT_MARGIN.y()) as u32;
 
+    /// 枠のドラッグで変えられる、ウィンドウ全体の大きさの最小値。
+    pub const MIN_SIZE: Vector2D<i32> = Vector2D::new(96, 48);
+
     pub fn new_base(width: u32, height: u32, shadow_format: PixelFormat) -> Self {
         WindowBase::new(width, height, shadow_format).into()
     }
@@ -56,12 +102,63 @@ impl Window {
         let mut ret = Self::Toplevel {
             base: WindowBase::new(width, height, shadow_format),
             title: title.into(),
+            resizable: false,
         };
         ret.draw_window();
 
         ret
     }
 
+    /// ウィンドウ全体の大きさを `width` x `height` に変える。
+    ///
+    /// 元の内容は重なる部分だけ引き継ぎ、トップレベルウィンドウでは枠とタイトルバーを描き直す。
+    /// タイトルバーは非アクティブとして描くので、アクティブなウィンドウでは [Window::activate] を呼ぶこと。
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let base = self.base_mut();
+        let mut new_base = WindowBase::new(width, height, base.shadow_buffer.pixel_format());
+        new_base.transparent_color = base.transparent_color;
+        new_base.opacity = base.opacity;
+        for y in 0..cmp::min(height, base.height) as i32 {
+            for x in 0..cmp::min(width, base.width) as i32 {
+                let pos = Vector2D::new(x, y);
+                // 不透明でないピクセルも重ねずにそのまま引き継ぐ
+                new_base.store(pos, base.at(pos));
+            }
+        }
+        *base = new_base;
+
+        if matches!(self, Self::Toplevel { .. }) {
+            self.draw_window();
+        }
+    }
+
+    /// 大きさを変えられるかどうかを設定する。トップレベルウィンドウでは最大化ボタンの有無が変わる。
+    pub fn set_resizable(&mut self, value: bool) {
+        if let Self::Toplevel { resizable, .. } = self {
+            *resizable = value;
+            self.draw_window_title(false);
+        }
+    }
+
+    /// 大きさを変えられるかどうかを返す。
+    pub fn is_resizable(&self) -> bool {
+        matches!(
+            self,
+            Self::Toplevel {
+                resizable: true,
+                ..
+            }
+        )
+    }
+
+    /// タイトルを返す。トップレベルウィンドウでない場合は `None`。
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            Self::Base(_) => None,
+            Self::Toplevel { title, .. } => Some(title),
+        }
+    }
+
     pub fn activate(&mut self) {
         self.base_mut().activate();
         if matches!(self, Self::Toplevel { .. }) {
@@ -175,6 +272,7 @@ impl Window {
     }
 
     pub fn draw_window_title(&mut self, active: bool) {
+        let buttons = self.title_buttons();
         let (base, title) = match self {
             Self::Base(_) => {
                 log!(
@@ -183,7 +281,7 @@ impl Window {
                 );
                 return;
             }
-            Self::Toplevel { base, title } => (base, title),
+            Self::Toplevel { base, title, .. } => (base, title),
         };
 
         let win_w = base.width as i32;
@@ -200,26 +298,48 @@ impl Window {
             &PixelColor::to_color(0xffffff),
         );
 
-        for (y, row_data) in CLOSE_BUTTON.iter().enumerate() {
-            for (x, &b) in row_data.iter().enumerate() {
-                let c = match b {
-                    b'@' => 0x000000,
-                    b'$' => 0x848484,
-                    b':' => 0xc6c6c6,
-                    _ => 0xffffff,
-                };
-                let c = PixelColor::to_color(c);
-
-                let x = x as i32;
-                let y = y as i32;
-                base.write(
-                    Vector2D::new(win_w - 5 - CLOSE_BUTTON_WIDTH as i32 + x, 5 + y),
-                    &c,
-                );
+        for (region, x0) in buttons.into_iter().flatten() {
+            let shape = match region {
+                WindowRegion::MinimizeButton => &MINIMIZE_BUTTON,
+                WindowRegion::MaximizeButton => &MAXIMIZE_BUTTON,
+                _ => &CLOSE_BUTTON,
+            };
+            for (y, row_data) in shape.iter().enumerate() {
+                for (x, &b) in row_data.iter().enumerate() {
+                    let c = match b {
+                        b'@' => 0x000000,
+                        b'$' => 0x848484,
+                        b':' => 0xc6c6c6,
+                        _ => 0xffffff,
+                    };
+                    let c = PixelColor::to_color(c);
+
+                    base.write(Vector2D::new(x0 + x as i32, 5 + y as i32), &c);
+                }
             }
         }
     }
 
+    /// タイトルバーのボタンと、その左端の x 座標を返す。
+    ///
+    /// 最大化ボタンは、大きさを変えられるウィンドウにのみ置く。
+    fn title_buttons(&self) -> [Option<(WindowRegion, i32)>; 3] {
+        let width = CLOSE_BUTTON_WIDTH as i32;
+        let close = self.base().width() as i32 - 5 - width;
+        let mut x = close - 2;
+        let maximize = if self.is_resizable() {
+            x -= width;
+            Some((WindowRegion::MaximizeButton, x))
+        } else {
+            None
+        };
+        [
+            Some((WindowRegion::CloseButton, close)),
+            maximize,
+            Some((WindowRegion::MinimizeButton, x - width)),
+        ]
+    }
+
     /// [PixelWrite] のメソッドを呼ぶと、全体としてのライターが返るとは限らないため、
     /// 全体としての [PixelWriter] が欲しい場合はこちらを呼ぶ。
     pub fn base(&self) -> &WindowBase {
@@ -236,20 +356,25 @@ impl Window {
         match self {
             Self::Base(_) => WindowRegion::Other,
             Self::Toplevel { base, .. } => {
-                if !(2..base.width() as i32 - 2).contains(&pos.x())
-                    || !(2..base.height() as i32 - 2).contains(&pos.y())
-                {
-                    WindowRegion::Border
-                } else if pos.y() < Self::TOP_LEFT_MARGIN.y() {
-                    if (base.width() as i32 - 5 - CLOSE_BUTTON_WIDTH as i32
-                        ..base.width() as i32 - 5)
-                        .contains(&pos.x())
-                        && (5..5 + CLOSE_BUTTON_HEIGHT as i32).contains(&pos.y())
-                    {
-                        WindowRegion::CloseButton
-                    } else {
-                        WindowRegion::TitleBar
-                    }
+                let (w, h) = (base.width() as i32, base.height() as i32);
+                let (x, y) = (pos.x(), pos.y());
+                let left = x < Self::TOP_LEFT_MARGIN.x();
+                let top = y < 3;
+                let right = x >= w - Self::BOTTOM_RIGHT_MARGIN.x();
+                let bottom = y >= h - Self::BOTTOM_RIGHT_MARGIN.y();
+                if left || top || right || bottom {
+                    // 角の近くでは、隣の辺も同時にドラッグする
+                    let near_left = (left || top || bottom) && x < CORNER_SIZE;
+                    let near_top = (left || top || right) && y < CORNER_SIZE;
+                    let near_right = (top || right || bottom) && x >= w - CORNER_SIZE;
+                    let near_bottom = (left || right || bottom) && y >= h - CORNER_SIZE;
+                    WindowRegion::Border([near_left, near_top, near_right, near_bottom])
+                } else if y < Self::TOP_LEFT_MARGIN.y() {
+                    let button = self.title_buttons().into_iter().flatten().find(|&(_, x0)| {
+                        (x0..x0 + CLOSE_BUTTON_WIDTH as i32).contains(&x)
+                            && (5..5 + CLOSE_BUTTON_HEIGHT as i32).contains(&y)
+                    });
+                    button.map_or(WindowRegion::TitleBar, |(region, _)| region)
                 } else {
                     WindowRegion::Other
                 }
@@ -372,6 +497,15 @@ impl PixelWrite for Window {
         }
     }
 
+    fn fill_rectangle(&mut self, pos: Vector2D<i32>, size: Vector2D<i32>, c: &PixelColor) {
+        match self {
+            Self::Base(base) => base.fill_rectangle(pos, size, c),
+            Self::Toplevel { base, .. } => {
+                base.fill_rectangle(pos + Self::TOP_LEFT_MARGIN, size, c)
+            }
+        }
+    }
+
     fn frame_buffer(&self) -> usize {
         match self {
             Self::Base(base) => base.frame_buffer(),
@@ -409,6 +543,12 @@ pub struct WindowBase {
     transparent_color: Option<PixelColor>,
     /// シャドウバッファ。
     shadow_buffer: FrameBuffer,
+    /// 前回 [WindowBase::take_damage] を呼んでから描き込まれた部分を含む最小の長方形。
+    damage: Option<Rectangle<i32>>,
+    /// ウィンドウ全体の不透明度。`255` で不透明。
+    opacity: u8,
+    /// 不透明でないピクセルが描き込まれたことがあるかどうか。
+    has_alpha: bool,
 }
 
 impl WindowBase {
@@ -435,6 +575,9 @@ impl WindowBase {
             data,
             transparent_color: None,
             shadow_buffer: FrameBuffer::new(config).unwrap(),
+            damage: None,
+            opacity: u8::MAX,
+            has_alpha: false,
         }
     }
 
@@ -460,8 +603,8 @@ impl WindowBase {
         // `window_area` のこの部分だけ転送する
         let intersection = *area & window_area;
 
-        // 透明色が設定されていない場合はそのまま描画する
-        if self.transparent_color.is_none() {
+        // 透明な部分がない場合はそのまま描画する
+        if self.is_opaque() {
             dst.copy(
                 intersection.pos,
                 &self.shadow_buffer,
@@ -474,32 +617,130 @@ impl WindowBase {
             return;
         }
 
-        // 透明色が設定されている場合は、その色のピクセルは描画しない
-        let tc = self.transparent_color.unwrap();
+        // 透明色のピクセルは描画せず、不透明でないピクセルは下の色と混ぜる
         // 描き込むフレームバッファからはみ出る分は描画しない
-        for y in cmp::max(0, 0 - intersection.pos.y())
-            ..cmp::min(
-                intersection.size.y(),
-                dst.vertical_resolution() as i32 - intersection.pos.y(),
-            )
-        {
-            for x in cmp::max(0, 0 - intersection.pos.x())
-                ..cmp::min(
-                    intersection.size.x(),
-                    dst.horizontal_resolution() as i32 - intersection.pos.x(),
-                )
-            {
-                let pos_relative = Vector2D::new(x, y);
+        let dst_area = Rectangle {
+            pos: Vector2D::new(0, 0),
+            size: Vector2D::new(
+                dst.horizontal_resolution() as i32,
+                dst.vertical_resolution() as i32,
+            ),
+        };
+        let intersection = intersection & dst_area;
+        for y in 0..intersection.size.y() {
+            for x in 0..intersection.size.x() {
+                let pos_relative = intersection.pos - pos + Vector2D::new(x, y);
                 let c = self.at(pos_relative);
-                if *c != tc {
-                    dst.write(pos + pos_relative, c);
+                if Some(*c) == self.transparent_color {
+                    continue;
+                }
+                let alpha = (c.alpha() as u32 * self.opacity as u32 / 255) as u8;
+                match alpha {
+                    0 => {}
+                    u8::MAX => dst.write(pos + pos_relative, c),
+                    alpha => dst.blend(pos + pos_relative, c, alpha),
                 }
             }
         }
     }
 
     pub fn r#move(&mut self, dst_pos: Vector2D<i32>, src: &Rectangle<i32>) {
-        self.shadow_buffer.r#move(dst_pos, src)
+        self.shadow_buffer.r#move(dst_pos, src);
+
+        // 透明色や不透明度を持つウィンドウの描画では data を読むので、同じように動かす
+        let width = self.width as i32;
+        let mut move_row = |dy: i32| {
+            let src_start = ((src.pos.y() + dy) * width + src.pos.x()) as usize;
+            let dst_start = ((dst_pos.y() + dy) * width + dst_pos.x()) as usize;
+            self.data
+                .copy_within(src_start..src_start + src.size.x() as usize, dst_start);
+        };
+        if dst_pos.y() < src.pos.y() {
+            (0..src.size.y()).for_each(&mut move_row);
+        } else {
+            (0..src.size.y()).rev().for_each(&mut move_row);
+        }
+
+        self.add_damage(Rectangle {
+            pos: dst_pos,
+            size: src.size,
+        });
+    }
+
+    /// 描き込まれた部分として `area` を加える。
+    pub fn add_damage(&mut self, area: Rectangle<i32>) {
+        let area = area
+            & Rectangle {
+                pos: Vector2D::new(0, 0),
+                size: self.size(),
+            };
+        if area.size.x() <= 0 || area.size.y() <= 0 {
+            return;
+        }
+        self.damage = Some(match self.damage {
+            Some(damage) => damage | area,
+            None => area,
+        });
+    }
+
+    /// 前回呼んでから描き込まれた部分を返し、記録を消す。
+    pub fn take_damage(&mut self) -> Option<Rectangle<i32>> {
+        self.damage.take()
+    }
+
+    /// ウィンドウ全体の不透明度を設定する。`255` で不透明。
+    pub fn set_opacity(&mut self, opacity: u8) {
+        self.opacity = opacity;
+    }
+
+    /// ウィンドウ全体の不透明度を返す。
+    pub fn opacity(&self) -> u8 {
+        self.opacity
+    }
+
+    /// ウィンドウ全体が不透明で、下にあるものが透けて見える部分がないかどうか。
+    pub fn is_opaque(&self) -> bool {
+        self.transparent_color.is_none() && !self.has_alpha && self.opacity == u8::MAX
+    }
+
+    /// `pos` から `size` の範囲のピクセルを、重ねずに不透明度ごと `c` に置き換える。
+    ///
+    /// 不透明でない色で塗ると、その部分はウィンドウの下にあるものが透けて見えるようになる。
+    pub fn replace_rectangle(&mut self, pos: Vector2D<i32>, size: Vector2D<i32>, c: &PixelColor) {
+        for dy in 0..size.y() {
+            for dx in 0..size.x() {
+                self.store(pos + Vector2D::new(dx, dy), c);
+            }
+        }
+        self.add_damage(Rectangle { pos, size });
+    }
+
+    /// 描き込まれた部分を記録せずにピクセルを塗る。
+    ///
+    /// 不透明でない色は、元のピクセルの上に重ねる。
+    fn put(&mut self, pos: Vector2D<i32>, color: &PixelColor) -> bool {
+        let color = if color.alpha() == u8::MAX {
+            *color
+        } else {
+            match self.at_mut(pos) {
+                Some(pixel) => color.over(pixel),
+                None => return false,
+            }
+        };
+        self.store(pos, &color)
+    }
+
+    /// 描き込まれた部分を記録せずに、ピクセルを `color` に置き換える。
+    fn store(&mut self, pos: Vector2D<i32>, color: &PixelColor) -> bool {
+        let Some(pixel) = self.at_mut(pos) else {
+            return false;
+        };
+        *pixel = *color;
+        if color.alpha() != u8::MAX {
+            self.has_alpha = true;
+        }
+        self.shadow_buffer.write(pos, color);
+        true
     }
 
     /// ウィンドウの透過色を設定する。
@@ -533,10 +774,22 @@ impl PixelWrite for WindowBase {
     ///
     /// 描画が必要な場合は `Window::draw_to()` を呼ぶこと。
     fn write(&mut self, pos: Vector2D<i32>, color: &PixelColor) {
-        if let Some(pixel) = self.at_mut(pos) {
-            *pixel = *color;
-            self.shadow_buffer.write(pos, color);
+        if self.put(pos, color) {
+            self.add_damage(Rectangle {
+                pos,
+                size: Vector2D::new(1, 1),
+            });
+        }
+    }
+
+    /// 描き込まれた部分の記録をピクセルごとではなく、まとめて 1 回だけ行う。
+    fn fill_rectangle(&mut self, pos: Vector2D<i32>, size: Vector2D<i32>, c: &PixelColor) {
+        for dy in 0..size.y() {
+            for dx in 0..size.x() {
+                self.put(pos + Vector2D::new(dx, dy), c);
+            }
         }
+        self.add_damage(Rectangle { pos, size });
     }
 
     fn frame_buffer(&self) -> usize {
@@ -560,6 +813,9 @@ impl PixelWrite for WindowBase {
 pub enum WindowRegion {
     TitleBar,
     CloseButton,
-    Border,
+    MinimizeButton,
+    MaximizeButton,
+    /// 枠。左・上・右・下のどの辺かを表し、角では 2 つが `true` になる。
+    Border([bool; 4]),
     Other,
 }
@@ -12,7 +12,7 @@ use core::{
     ffi::c_void,
     fmt::Write,
     mem::{size_of, transmute},
-    ptr::{copy_nonoverlapping, write_bytes},
+    ptr::{self, copy_nonoverlapping, write_bytes},
     slice,
 };
 use elf::{Elf64Phdr, ProgramType};
@@ -590,6 +590,23 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
         }
     };
 
+    // "\ext2_disk" があれば、2 つ目のボリュームとして読み込む
+    // 無い場合はヌルポインタを渡す
+    let (ext2_image, ext2_size) = match root_dir.open(
+        cstr16!("\\ext2_disk"),
+        FileMode::Read,
+        FileAttribute::empty(),
+    ) {
+        Ok(file) => match read_file(&mut system_table, file) {
+            Ok(buf) => (buf.as_mut_ptr() as *mut c_void, buf.len()),
+            Err(e) => {
+                error!("failed to read ext2 volume file: {}", e);
+                halt();
+            }
+        },
+        Err(_) => (ptr::null_mut(), 0),
+    };
+
     // UEFI のブートサービスを終了する
     let (system_table, _) = system_table.exit_boot_services(MemoryType(0));
 
@@ -641,6 +658,8 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
         usize,
         *const c_void,
         *mut c_void,
+        *mut c_void,
+        usize,
     ) = unsafe { transmute(kernel_ehdr.entry) };
     entry_point(
         &config,
@@ -649,6 +668,8 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
         kernel_last_addr - kernel_first_addr,
         acpi_table,
         volume_image.as_mut_ptr() as *mut c_void,
+        ext2_image,
+        ext2_size,
     );
 
     halt()